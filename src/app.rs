@@ -1,35 +1,143 @@
+use crate::buffer::Buffer;
+use crate::cli::{CliArgs, Target};
+use crate::commands;
+use crate::encoding::{self, TextEncoding};
+use crate::find_in_files::{FileMatches, SearchJob, SearchScope, Where, RESULTS_TAB};
+use crate::goto;
+use crate::highlight::HighlightWorker;
+use crate::ipc::{Client, IpcServer, PendingServer};
+use crate::quick_panel::QuickPanel;
+use crate::recent::RecentItems;
+use crate::replace_in_files::{self, FileChanges, ReplacePreview, ReplacedFile, PREVIEW_TAB};
+use crate::search::{self, FindOptions, QueryHistory};
+use crate::selection::{Selection, Selections};
+use crate::session::{Session, TabView};
+use crate::settings::{self, Settings, SettingsLoader};
+use crate::syntax::{self, SYNTAX_SET};
+use crate::theme::{self, Chrome};
 use crate::ui;
 use eframe::egui;
+use encoding_rs::Encoding;
 use ignore::gitignore::{Gitignore, GitignoreBuilder};
-use ignore::WalkBuilder;
+use ropey::Rope;
 use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use syntect::parsing::SyntaxReference;
+
+// How long typing has to pause before the find matches are updated.
+const FIND_DELAY: Duration = Duration::from_millis(150);
+// New files are named this plus a number until they are saved somewhere.
+const UNTITLED_PREFIX: &str = "Untitled-";
+
+// Whether a tab is a new file, whose name is not a path on disk.
+pub fn is_untitled(path: &Path) -> bool {
+    path.is_relative()
+        && path
+            .to_str()
+            .is_some_and(|name| name.starts_with(UNTITLED_PREFIX))
+}
 
 pub struct SublimeRustApp {
     pub current_dir: Option<PathBuf>, // Changed to Option
     pub expanded_dirs: HashSet<PathBuf>,
     pub open_tabs: Vec<PathBuf>,
     pub active_tab_index: Option<usize>,
-    pub tab_contents: HashMap<PathBuf, String>,
+    pub tab_contents: HashMap<PathBuf, Buffer>,
     pub dirty_files: HashSet<PathBuf>,
+    // Why the last open or save failed.
+    pub file_error: Option<String>,
     pub cursor_pos: (usize, usize),
     pub closing_file_index: Option<usize>,
+    pub about_visible: bool,
     pub sidebar_visible: bool,
     pub find_query: String,
-    pub find_matches: Vec<usize>,
+    pub find_history: QueryHistory,
+    pub find_matches: Vec<Range<usize>>,
+    pub find_options: FindOptions,
+    pub find_error: Option<String>,
+    // The tab and buffer version the matches were found in.
+    pub find_target: Option<(PathBuf, u64)>,
+    // The text they were found in, so an edit is only searched around.
+    pub find_text: Option<Rope>,
+    // Lines with a match, for the scroll bar.
+    pub find_match_lines: Vec<usize>,
+    // The buffer version seen changing while typing, and when.
+    pub find_edited: Option<(u64, Instant)>,
+    // Shown in place of the match count, e.g. after Replace All.
+    pub find_status: Option<String>,
+    // What "In selection" searches: the selections when it was turned on.
+    pub find_scope: Vec<Range<usize>>,
     pub current_match_index: Option<usize>,
     pub find_active: bool,
     pub find_just_activated: bool,
+    pub replace_active: bool,
+    pub replace_query: String,
     pub find_scroll_requested: bool,
     pub find_in_files_active: bool,
     pub find_in_files_find_query: String,
+    pub find_in_files_history: QueryHistory,
     pub find_in_files_where_query: String,
     pub find_in_files_replace_query: String,
     pub find_in_files_respect_gitignore: bool,
-    pub find_in_files_results: Option<String>,
+    pub find_in_files_options: FindOptions,
+    pub find_in_files_show_context: bool,
+    pub find_in_files_results: Vec<FileMatches>,
+    // File, line and match within the line of the result last visited.
+    pub find_in_files_current: Option<(usize, usize, usize)>,
+    pub find_in_files_job: Option<SearchJob>,
+    // A bad pattern in the Where field.
+    pub find_in_files_error: Option<String>,
+    // What the last replace did, shown until the next search.
+    pub find_in_files_status: Option<String>,
+    // Replace was pressed: preview once the search for it is done.
+    pub replace_in_files_pending: bool,
+    pub replace_preview: Option<ReplacePreview>,
+    pub replace_in_files_undo: Option<Vec<ReplacedFile>>,
     pub gitignore: Option<Gitignore>,
     pub untitled_counter: usize,
+    pub palette_active: bool,
+    pub palette_just_activated: bool,
+    pub palette_query: String,
+    pub palette_selected: usize,
+    pub goto_active: bool,
+    pub goto_just_activated: bool,
+    pub goto_query: String,
+    pub goto_selected: usize,
+    pub goto_index: Option<goto::FileIndex>,
+    pub goto_symbols: Option<(PathBuf, Vec<goto::Symbol>)>,
+    pub goto_previewed: Option<(PathBuf, Option<(usize, usize)>)>,
+    pub goto_transient_tab: Option<PathBuf>,
+    pub goto_return_tab: Option<usize>,
+    pub settings: Settings,
+    pub settings_loader: SettingsLoader,
+    pub settings_error: Option<String>,
+    pub settings_changed: bool,
+    pub tab_views: HashMap<PathBuf, TabView>,
+    pub viewed_tab: Option<PathBuf>,
+    pub recent: RecentItems,
+    pub closed_tabs: Vec<PathBuf>,
+    pub wait_tabs: Vec<PathBuf>,
+    pub ipc_server: Option<IpcServer>,
+    pub selections: Selections,
+    pub highlighter: HighlightWorker,
+    pub chrome: Chrome,
+    // Syntax names chosen with View → Syntax, by tab.
+    pub syntax_overrides: HashMap<PathBuf, String>,
+    pub theme_picker_active: bool,
+    pub theme_picker_just_activated: bool,
+    pub theme_picker_query: String,
+    pub theme_picker_selected: usize,
+    pub theme_picker_names: Vec<String>,
+    // The theme in use before the picker started previewing others.
+    pub theme_picker_original: String,
+    pub quick_panel: Option<QuickPanel>,
+    pub wait_clients: Vec<(Vec<PathBuf>, Client)>,
+    // Off for windows that did not restore the session, so closing them
+    // does not overwrite it.
+    pub persist_session: bool,
 }
 
 impl Default for SublimeRustApp {
@@ -41,248 +149,1410 @@ impl Default for SublimeRustApp {
             active_tab_index: None,
             tab_contents: HashMap::new(),
             dirty_files: HashSet::new(),
+            file_error: None,
             cursor_pos: (1, 1),
             closing_file_index: None,
+            about_visible: false,
             sidebar_visible: true,
             find_query: String::new(),
+            find_history: QueryHistory::default(),
             find_matches: Vec::new(),
+            find_options: FindOptions::default(),
+            find_error: None,
+            find_target: None,
+            find_text: None,
+            find_match_lines: Vec::new(),
+            find_edited: None,
+            find_status: None,
+            find_scope: Vec::new(),
             current_match_index: None,
             find_active: false,
             find_just_activated: false,
+            replace_active: false,
+            replace_query: String::new(),
             find_scroll_requested: false,
             find_in_files_active: false,
             find_in_files_find_query: String::new(),
+            find_in_files_history: QueryHistory::default(),
             find_in_files_where_query: String::new(),
             find_in_files_replace_query: String::new(),
             find_in_files_respect_gitignore: true,
-            find_in_files_results: None,
+            find_in_files_options: FindOptions::default(),
+            find_in_files_show_context: false,
+            find_in_files_results: Vec::new(),
+            find_in_files_current: None,
+            find_in_files_job: None,
+            find_in_files_error: None,
+            find_in_files_status: None,
+            replace_in_files_pending: false,
+            replace_preview: None,
+            replace_in_files_undo: None,
             gitignore: None,
             untitled_counter: 0,
+            palette_active: false,
+            palette_just_activated: false,
+            palette_query: String::new(),
+            palette_selected: 0,
+            goto_active: false,
+            goto_just_activated: false,
+            goto_query: String::new(),
+            goto_selected: 0,
+            goto_index: None,
+            goto_symbols: None,
+            goto_previewed: None,
+            goto_transient_tab: None,
+            goto_return_tab: None,
+            settings: Settings::default(),
+            settings_loader: SettingsLoader::new(),
+            settings_error: None,
+            settings_changed: true,
+            tab_views: HashMap::new(),
+            viewed_tab: None,
+            recent: RecentItems::default(),
+            closed_tabs: Vec::new(),
+            wait_tabs: Vec::new(),
+            ipc_server: None,
+            selections: Selections::default(),
+            highlighter: HighlightWorker::default(),
+            chrome: Chrome::default(),
+            syntax_overrides: HashMap::new(),
+            theme_picker_active: false,
+            theme_picker_just_activated: false,
+            theme_picker_query: String::new(),
+            theme_picker_selected: 0,
+            theme_picker_names: Vec::new(),
+            theme_picker_original: String::new(),
+            quick_panel: None,
+            wait_clients: Vec::new(),
+            persist_session: true,
         }
     }
 }
 
 impl SublimeRustApp {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        let mut visuals = egui::Visuals::dark();
-        visuals.window_fill = egui::Color32::from_rgb(0x23, 0x23, 0x23);
-        visuals.panel_fill = egui::Color32::from_rgb(0x1e, 0x1e, 0x1e);
+    pub fn new(
+        cc: &eframe::CreationContext<'_>,
+        args: CliArgs,
+        server: Option<PendingServer>,
+    ) -> Self {
+        let mut app = Self {
+            recent: RecentItems::load(),
+            ipc_server: server.map(|server| server.start(cc.egui_ctx.clone())),
+            ..Self::default()
+        };
+        app.reload_settings();
+        // A window opened for `--wait` (e.g. a commit message) or explicitly
+        // as a new window starts clean instead of taking over the session.
+        app.persist_session = !(args.wait || args.new_window);
+        if app.persist_session {
+            if let Some(session) = Session::load() {
+                session.restore(&mut app);
+            }
+        }
+        let wait = args.wait;
+        let opened = app.open_targets(args);
+        if wait {
+            app.wait_tabs = opened;
+        }
+        app
+    }
+
+    // Returns the tabs the targets ended up in, for `--wait`.
+    pub fn open_targets(&mut self, args: CliArgs) -> Vec<PathBuf> {
+        let mut opened = Vec::new();
+        for target in args.targets {
+            let path = match target {
+                Target::Folder(path) => {
+                    self.open_folder_path(path);
+                    continue;
+                }
+                Target::Stdin(text) => {
+                    let path = self.new_file();
+                    if let Some(buffer) = self.tab_contents.get_mut(&path) {
+                        buffer.set_text(&text);
+                    }
+                    path
+                }
+                Target::File { path, line, col } => {
+                    // Like Sublime, a path that does not exist yet opens as
+                    // an empty buffer that creates the file on save.
+                    if !path.exists() && !self.open_tabs.contains(&path) {
+                        self.tab_contents.insert(path.clone(), Buffer::new());
+                        self.open_tabs.push(path.clone());
+                        self.dirty_files.insert(path.clone());
+                    }
+                    self.open_file_path(path.clone());
+                    if let Some(line) = line {
+                        let col = col.unwrap_or(1);
+                        self.goto_line_col(line.saturating_sub(1), col.saturating_sub(1));
+                    }
+                    path
+                }
+            };
+            opened.push(path);
+        }
+        opened
+    }
+
+    // Opens what other invocations forwarded to this window.
+    fn handle_forwarded_opens(&mut self, ctx: &egui::Context) {
+        while let Some((args, client)) = self.ipc_server.as_ref().and_then(|s| s.try_recv()) {
+            let wait = args.wait;
+            let opened = self.open_targets(args);
+            if wait && !opened.is_empty() {
+                self.wait_clients.push((opened, client));
+            } else {
+                client.reply("ok");
+            }
+            ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+        }
 
-        visuals.widgets.noninteractive.bg_fill = egui::Color32::from_rgb(0x1e, 0x1e, 0x1e);
-        visuals.widgets.inactive.bg_fill = egui::Color32::TRANSPARENT;
-        visuals.widgets.hovered.bg_fill = egui::Color32::from_rgb(0x3e, 0x3e, 0x3e);
-        visuals.widgets.active.bg_fill = egui::Color32::from_rgb(0x2d, 0x2d, 0x2d);
+        let open_tabs = &self.open_tabs;
+        let (done, waiting) = std::mem::take(&mut self.wait_clients)
+            .into_iter()
+            .partition(|(paths, _)| !paths.iter().any(|p| open_tabs.contains(p)));
+        self.wait_clients = waiting;
+        for (_, client) in done {
+            client.reply("closed");
+        }
+    }
+
+    pub fn reload_settings(&mut self) {
+        let (settings, error) = self.settings_loader.load();
+        self.set_settings(settings, error);
+    }
+
+    fn set_settings(&mut self, settings: Settings, error: Option<String>) {
+        let theme_errors = theme::reload_themes();
+        let error = error
+            .or_else(|| theme_errors.into_iter().next())
+            .or_else(|| syntax::load_errors().into_iter().next());
+        // The Find in Files toggle starts out as the setting, but a reload
+        // leaves it alone unless the setting itself changed.
+        if settings.respect_gitignore != self.settings.respect_gitignore {
+            self.find_in_files_respect_gitignore = settings.respect_gitignore;
+        }
+        self.settings = settings;
+        self.settings_error = error;
+        self.settings_changed = true;
+    }
+
+    // Pushes font and theme settings into egui. Only called when they change,
+    // since rebuilding the font atlas is expensive.
+    pub fn apply_settings(&mut self, ctx: &egui::Context) {
+        self.apply_theme(ctx);
+
+        let mut fonts = egui::FontDefinitions::default();
+        fonts.font_data.insert(
+            "unicode_font".to_owned(),
+            egui::FontData::from_static(include_bytes!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/assets/JetBrainsMonoNerdFont-Regular.ttf"
+            ))),
+        );
+        fonts
+            .families
+            .entry(egui::FontFamily::Proportional)
+            .or_default()
+            .insert(0, "unicode_font".to_owned());
+
+        if !self.settings.font_face.is_empty() {
+            match fs::read(&self.settings.font_face) {
+                Ok(bytes) => {
+                    fonts
+                        .font_data
+                        .insert("font_face".to_owned(), egui::FontData::from_owned(bytes));
+                    fonts
+                        .families
+                        .entry(egui::FontFamily::Monospace)
+                        .or_default()
+                        .insert(0, "font_face".to_owned());
+                }
+                Err(e) => {
+                    self.settings_error = Some(format!("{}: {}", self.settings.font_face, e));
+                }
+            }
+        }
+        ctx.set_fonts(fonts);
+
+        let font_size = self.settings.font_size;
+        ctx.style_mut(|style| {
+            style.text_styles.insert(
+                egui::TextStyle::Monospace,
+                egui::FontId::monospace(font_size),
+            );
+        });
+        self.settings_changed = false;
+    }
 
-        visuals.widgets.noninteractive.fg_stroke =
-            egui::Stroke::new(1.0, egui::Color32::from_rgb(0xcc, 0xcc, 0xcc));
-        visuals.widgets.inactive.fg_stroke =
-            egui::Stroke::new(1.0, egui::Color32::from_rgb(0xcc, 0xcc, 0xcc));
+    // Makes `path` the project root: sidebar, .gitignore and project settings.
+    pub fn set_project_dir(&mut self, path: PathBuf) {
+        self.current_dir = Some(path.clone());
+        self.expanded_dirs.insert(path.clone());
 
-        cc.egui_ctx.set_visuals(visuals);
+        // Load gitignore
+        let mut builder = GitignoreBuilder::new(&path);
+        let gitignore_path = path.join(".gitignore");
+        if gitignore_path.exists() {
+            builder.add(gitignore_path);
+        }
+        self.gitignore = Some(builder.build().unwrap());
 
-        Self::default()
+        self.settings_loader.set_project_dir(Some(path));
+        self.reload_settings();
     }
 
-    pub fn new_file(&mut self) {
+    pub fn edit_settings(&mut self) {
+        if let Some(path) = settings::ensure_user_settings() {
+            self.open_path(path);
+        }
+    }
+
+    pub fn new_file(&mut self) -> PathBuf {
         self.untitled_counter += 1;
-        let new_path = PathBuf::from(format!("Untitled-{}", self.untitled_counter));
-        self.tab_contents.insert(new_path.clone(), String::new());
+        let new_path = PathBuf::from(format!("{}{}", UNTITLED_PREFIX, self.untitled_counter));
+        self.tab_contents.insert(new_path.clone(), Buffer::new());
         self.open_tabs.push(new_path.clone());
         self.active_tab_index = Some(self.open_tabs.len() - 1);
-        self.dirty_files.insert(new_path);
+        self.dirty_files.insert(new_path.clone());
+        new_path
     }
 
     pub fn open_folder(&mut self) {
         if let Some(path) = rfd::FileDialog::new().pick_folder() {
-            self.current_dir = Some(path.clone());
-            self.expanded_dirs.insert(path.clone());
+            self.open_folder_path(path);
+        }
+    }
+
+    pub fn open_project(&mut self) {
+        let picked = rfd::FileDialog::new()
+            .add_filter("Sublime Project", &["sublime-project"])
+            .pick_file();
+        if let Some(path) = picked {
+            self.open_project_file(&path);
+        }
+    }
 
-            // Load gitignore
-            let mut builder = GitignoreBuilder::new(&path);
-            let gitignore_path = path.join(".gitignore");
-            if gitignore_path.exists() {
-                builder.add(gitignore_path);
+    // Opens the folder a `.sublime-project` file lists, relative to the file
+    // itself. Only one folder is shown at a time, so that is the first one.
+    pub fn open_project_file(&mut self, path: &Path) {
+        let folder = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| {
+                serde_json::from_str::<serde_json::Value>(&settings::strip_json_extensions(&text))
+                    .map_err(|e| e.to_string())
+            })
+            .and_then(|project| {
+                project["folders"][0]["path"]
+                    .as_str()
+                    .map(|folder| path.parent().unwrap_or(Path::new("")).join(folder))
+                    .ok_or_else(|| "No folders in the project".to_string())
+            });
+        match folder {
+            Ok(folder) if folder.is_dir() => {
+                self.file_error = None;
+                self.open_folder_path(folder);
+            }
+            Ok(folder) => {
+                self.file_error = Some(format!("Cannot open {}: not a folder", folder.display()));
+            }
+            Err(e) => {
+                self.file_error = Some(format!("Cannot open {}: {}", path.display(), e));
             }
-            self.gitignore = Some(builder.build().unwrap());
         }
     }
 
+    pub fn open_folder_path(&mut self, path: PathBuf) {
+        self.recent.add_folder(&path);
+        self.set_project_dir(path);
+    }
+
     pub fn open_file(&mut self) {
         if let Some(path) = rfd::FileDialog::new().pick_file() {
-            if self.current_dir.is_none() {
-                // If no folder is open, set the parent of the file as the current directory
-                if let Some(parent) = path.parent() {
-                    let parent_path = parent.to_path_buf();
-                    self.current_dir = Some(parent_path.clone());
-                    self.expanded_dirs.insert(parent_path.clone());
-
-                    // Load gitignore
-                    let mut builder = GitignoreBuilder::new(&parent_path);
-                    let gitignore_path = parent_path.join(".gitignore");
-                    if gitignore_path.exists() {
-                        builder.add(gitignore_path);
-                    }
-                    self.gitignore = Some(builder.build().unwrap());
-                }
+            self.open_file_path(path);
+        }
+    }
+
+    pub fn open_file_path(&mut self, path: PathBuf) {
+        if self.current_dir.is_none() {
+            // If no folder is open, set the parent of the file as the current directory
+            if let Some(parent) = path.parent() {
+                self.set_project_dir(parent.to_path_buf());
             }
-            if let Ok(content) = fs::read_to_string(&path) {
-                if !self.open_tabs.contains(&path) {
-                    self.tab_contents.insert(path.clone(), content);
-                    self.open_tabs.push(path.clone());
-                    self.active_tab_index = Some(self.open_tabs.len() - 1);
+        }
+        if self.open_path(path.clone()) {
+            self.recent.add_file(&path);
+        }
+    }
+
+    pub fn show_open_recent(&mut self) {
+        self.recent.prune();
+        let items = self
+            .recent
+            .files
+            .iter()
+            .chain(&self.recent.folders)
+            .map(|path| path.display().to_string())
+            .collect();
+        self.quick_panel = Some(QuickPanel::new(
+            "Open recent file or folder",
+            items,
+            None,
+            |app, _, _, item| {
+                let path = PathBuf::from(item);
+                if path.is_dir() {
+                    app.open_folder_path(path);
                 } else {
-                    // File is already open, just switch to it
-                    if let Some(pos) = self.open_tabs.iter().position(|p| p == &path) {
-                        self.active_tab_index = Some(pos);
-                    }
+                    app.open_file_path(path);
                 }
+            },
+        ));
+    }
+
+    pub fn reopen_closed_tab(&mut self) {
+        while let Some(path) = self.closed_tabs.pop() {
+            if self.open_path(path) {
+                break;
+            }
+        }
+    }
+
+    // Focuses the tab for `path`, loading it from disk if it is not open yet.
+    pub fn open_path(&mut self, path: PathBuf) -> bool {
+        if let Some(pos) = self.open_tabs.iter().position(|p| p == &path) {
+            self.active_tab_index = Some(pos);
+            return true;
+        }
+        match Buffer::from_file(&path) {
+            Ok(buffer) => {
+                self.file_error = None;
+                self.tab_contents.insert(path.clone(), buffer);
+                self.open_tabs.push(path);
+                self.active_tab_index = Some(self.open_tabs.len() - 1);
+                true
+            }
+            Err(e) => {
+                self.file_error = Some(format!("Cannot open {}: {}", path.display(), e));
+                false
             }
         }
     }
 
+    pub fn show_find_in_files(&mut self) {
+        self.find_in_files_active = true;
+        if let Some(dir) = &self.current_dir {
+            self.find_in_files_where_query = dir.to_str().unwrap_or("").to_string();
+        }
+    }
+
+    pub fn syntax_for(&self, path: &Path) -> &'static SyntaxReference {
+        self.syntax_overrides
+            .get(path)
+            .and_then(|name| SYNTAX_SET.find_syntax_by_name(name))
+            .unwrap_or_else(|| syntax::detect_syntax(path, self.tab_contents.get(path)))
+    }
+
+    // Overrides the syntax of the active tab; None goes back to detecting it.
+    pub fn set_syntax(&mut self, name: Option<String>) {
+        let Some(path) = self
+            .active_tab_index
+            .and_then(|i| self.open_tabs.get(i))
+            .cloned()
+        else {
+            return;
+        };
+        match name {
+            Some(name) => self.syntax_overrides.insert(path, name),
+            None => self.syntax_overrides.remove(&path),
+        };
+    }
+
+    pub fn show_syntax_picker(&mut self) {
+        let Some(path) = self.active_tab_index.and_then(|i| self.open_tabs.get(i)) else {
+            return;
+        };
+        let mut items = vec!["Auto Detect".to_string()];
+        items.extend(syntax::names().into_iter().map(str::to_string));
+        let current = match self.syntax_overrides.get(path) {
+            Some(name) => items.iter().position(|item| item == name),
+            None => Some(0),
+        };
+        self.quick_panel = Some(QuickPanel::new(
+            "Set syntax",
+            items,
+            current,
+            |app, _, idx, name| app.set_syntax((idx > 0).then(|| name.to_string())),
+        ));
+    }
+
+    pub fn apply_theme(&mut self, ctx: &egui::Context) {
+        self.chrome = Chrome::from_theme(&theme::find_theme(&self.settings.theme));
+        ctx.set_visuals(self.chrome.visuals());
+    }
+
+    pub fn show_theme_picker(&mut self) {
+        let errors = theme::reload_themes();
+        if let Some(error) = errors.into_iter().next() {
+            self.settings_error = Some(error);
+        }
+        self.theme_picker_names = theme::theme_names();
+        self.theme_picker_original = self.settings.theme.clone();
+        self.theme_picker_active = true;
+        self.theme_picker_just_activated = true;
+        self.theme_picker_query.clear();
+        self.theme_picker_selected = 0;
+    }
+
+    // Shows `name` without saving it, while the picker moves over it.
+    pub fn preview_theme(&mut self, ctx: &egui::Context, name: &str) {
+        if self.settings.theme != name {
+            self.settings.theme = name.to_string();
+            self.apply_theme(ctx);
+        }
+    }
+
+    // Saves `name` as the theme, and as the light or dark one to toggle back
+    // to.
+    pub fn choose_theme(&mut self, name: &str) {
+        let dark = Chrome::from_theme(&theme::find_theme(name)).dark;
+        let slot = if dark { "dark_theme" } else { "light_theme" };
+        match settings::set_user_settings(&[("theme", name), (slot, name)]) {
+            Ok(()) => self.reload_settings(),
+            Err(e) => self.settings_error = Some(e),
+        }
+    }
+
+    // Saves the chosen theme, or puts back the original one when cancelled.
+    pub fn finish_theme_picker(&mut self, ctx: &egui::Context, chosen: Option<String>) {
+        self.theme_picker_active = false;
+        match chosen {
+            Some(name) => self.choose_theme(&name),
+            None => {
+                let original = std::mem::take(&mut self.theme_picker_original);
+                self.preview_theme(ctx, &original);
+            }
+        }
+    }
+
+    pub fn toggle_light_dark(&mut self) {
+        let name = if self.chrome.dark {
+            self.settings.light_theme.clone()
+        } else {
+            self.settings.dark_theme.clone()
+        };
+        match settings::set_user_settings(&[("theme", &name)]) {
+            Ok(()) => self.reload_settings(),
+            Err(e) => self.settings_error = Some(e),
+        }
+    }
+
+    pub fn show_command_palette(&mut self) {
+        self.palette_active = true;
+        self.palette_just_activated = true;
+        self.palette_query.clear();
+        self.palette_selected = 0;
+    }
+
+    pub fn show_find(&mut self, replace: bool) {
+        self.find_active = true;
+        self.find_just_activated = true;
+        self.replace_active = replace;
+    }
+
     pub fn perform_find(&mut self) {
-        self.find_matches.clear();
+        // The query or the options changed, so nothing can be kept.
+        self.find_text = None;
+        self.update_find();
+    }
+
+    // Finds again if the text or the tab changed since the last search, so
+    // the highlighted matches follow edits.
+    pub fn refresh_find(&mut self) {
+        if self.find_target != self.active_find_target() {
+            self.update_find();
+        }
+    }
+
+    // Like `refresh_find`, but while the text keeps changing it waits for
+    // typing to pause instead of searching at every key.
+    pub fn refresh_find_when_idle(&mut self, ctx: &egui::Context) {
+        let Some((path, version)) = self.active_find_target() else {
+            return;
+        };
+        let edited = match &self.find_target {
+            Some((found_in, found_at)) => *found_in == path && *found_at != version,
+            None => false,
+        };
+        if edited {
+            let since = match self.find_edited {
+                Some((seen, since)) if seen == version => since,
+                _ => {
+                    self.find_edited = Some((version, Instant::now()));
+                    Instant::now()
+                }
+            };
+            let waited = since.elapsed();
+            if waited < FIND_DELAY {
+                ctx.request_repaint_after(FIND_DELAY - waited);
+                return;
+            }
+        }
+        self.refresh_find();
+    }
+
+    // Searches the active tab. Matches from the last search of the same
+    // tab are kept where the text around them did not change.
+    fn update_find(&mut self) {
+        let previous = self.find_target.take().zip(self.find_text.take());
+        let previous_matches = std::mem::take(&mut self.find_matches);
+        self.find_match_lines.clear();
+        self.find_edited = None;
+        self.find_error = None;
+        self.find_status = None;
+        self.current_match_index = None;
+        self.find_target = self.active_find_target();
         if self.find_query.is_empty() {
-            self.current_match_index = None;
             return;
         }
+        let regex = match search::build_regex(&self.find_query, &self.find_options) {
+            Ok(regex) => regex,
+            Err(e) => {
+                self.find_error = Some(e);
+                return;
+            }
+        };
+        let Some((path, _)) = &self.find_target else {
+            return;
+        };
+        let Some(buffer) = self.tab_contents.get(path) else {
+            return;
+        };
 
-        if let Some(idx) = self.active_tab_index {
-            if let Some(path) = self.open_tabs.get(idx) {
-                if let Some(content) = self.tab_contents.get(path) {
-                    self.find_matches = content
-                        .match_indices(&self.find_query)
-                        .map(|(byte_offset, _)| content[..byte_offset].chars().count())
-                        .collect();
-                    if !self.find_matches.is_empty() {
-                        if self.current_match_index.is_none()
-                            || self.current_match_index.unwrap() >= self.find_matches.len()
-                        {
-                            self.current_match_index = Some(0);
-                        }
-                    } else {
-                        self.current_match_index = None;
-                    }
-                }
+        let text = buffer.snapshot();
+        let matches = match previous {
+            Some(((found_in, _), old)) if found_in == *path => {
+                search::rematch(&regex, &old, &text, &previous_matches)
+            }
+            _ => search::find_all(&regex, &text),
+        };
+        let in_scope = |range: &Range<usize>| {
+            !self.find_options.in_selection
+                || self
+                    .find_scope
+                    .iter()
+                    .any(|scope| scope.start <= range.start && range.end <= scope.end)
+        };
+        self.find_matches = matches.into_iter().filter(in_scope).collect();
+        for range in &self.find_matches {
+            let line = text.char_to_line(range.start);
+            if self.find_match_lines.last() != Some(&line) {
+                self.find_match_lines.push(line);
             }
         }
+        self.find_text = Some(text);
+
+        // Count from the caret, as the next Find Next would.
+        let caret = self.selections.primary().start();
+        self.current_match_index = self
+            .find_matches
+            .iter()
+            .position(|m| m.start >= caret)
+            .or_else(|| (!self.find_matches.is_empty()).then_some(0));
     }
 
-    pub fn perform_find_in_files(&mut self) {
-        if self.find_in_files_find_query.is_empty() {
-            self.find_in_files_results = None;
+    fn active_find_target(&self) -> Option<(PathBuf, u64)> {
+        let path = self.open_tabs.get(self.active_tab_index?)?;
+        Some((path.clone(), self.tab_contents.get(path)?.version()))
+    }
+
+    // Remembers the current selections as the range "In selection" searches.
+    pub fn capture_find_scope(&mut self) {
+        self.find_scope = self
+            .selections
+            .iter()
+            .filter(|s| !s.is_empty())
+            .map(|s| s.range())
+            .collect();
+    }
+
+    // Selects the first match after the selection, wrapping around to the
+    // top if enabled.
+    pub fn find_next(&mut self) {
+        self.find_history.push(&self.find_query);
+        let after = self.selections.primary().end();
+        let next = self
+            .find_matches
+            .iter()
+            .position(|m| m.start >= after)
+            .or_else(|| self.wrap_to(0));
+        if next.is_some() {
+            self.current_match_index = next;
+            self.move_to_match();
+        }
+    }
+
+    pub fn find_prev(&mut self) {
+        self.find_history.push(&self.find_query);
+        let before = self.selections.primary().start();
+        let prev = self
+            .find_matches
+            .iter()
+            .rposition(|m| m.end <= before)
+            .or_else(|| self.wrap_to(self.find_matches.len().wrapping_sub(1)));
+        if prev.is_some() {
+            self.current_match_index = prev;
+            self.move_to_match();
+        }
+    }
+
+    // Ctrl+E: searches for the selection, or the word at the caret.
+    pub fn use_selection_for_find(&mut self) {
+        let Some(buffer) = self
+            .active_tab_index
+            .and_then(|i| self.open_tabs.get(i))
+            .and_then(|path| self.tab_contents.get(path))
+        else {
+            return;
+        };
+        let primary = self.selections.primary();
+        let range = if primary.is_empty() {
+            Selections::word_at(buffer, primary.head).range()
+        } else {
+            primary.range()
+        };
+        if range.is_empty() {
             return;
         }
+        let text = buffer.slice(range);
+        self.find_query = if self.find_options.regex {
+            regex::escape(&text)
+        } else {
+            text
+        };
+        self.perform_find();
+    }
 
-        let mut results = String::new();
-        let mut matches_count = 0;
-        let mut files_count = 0;
-
-        let walker = WalkBuilder::new(&self.find_in_files_where_query)
-            .git_ignore(self.find_in_files_respect_gitignore)
-            .build();
-
-        for result in walker {
-            if let Ok(entry) = result {
-                if entry.file_type().map_or(false, |ft| ft.is_file()) {
-                    if let Ok(content) = fs::read_to_string(entry.path()) {
-                        let mut file_has_match = false;
-                        for (line_num, line) in content.lines().enumerate() {
-                            if line.contains(&self.find_in_files_find_query) {
-                                if !file_has_match {
-                                    results.push_str(&format!("\n{}:\n", entry.path().display()));
-                                    file_has_match = true;
-                                    files_count += 1;
-                                }
-                                results.push_str(&format!("  {}: {}\n", line_num + 1, line.trim()));
-                                matches_count += 1;
-                            }
-                        }
-                    }
-                }
+    // Ctrl+F3: jumps to the next occurrence of the word at the caret.
+    pub fn find_under(&mut self) {
+        self.use_selection_for_find();
+        self.find_next();
+    }
+
+    fn wrap_to(&self, index: usize) -> Option<usize> {
+        (self.find_options.wrap && index < self.find_matches.len()).then_some(index)
+    }
+
+    // Replaces the selected match, if the selection is one, and selects the
+    // next.
+    pub fn replace_next(&mut self) {
+        self.perform_find();
+        let selected = self.selections.primary().range();
+        if self.find_matches.contains(&selected) {
+            if let Some(replaced) = self.replace_matches(vec![selected]) {
+                self.set_selection(replaced[0].end, replaced[0].end);
+                self.perform_find();
             }
         }
+        self.find_next();
+    }
 
-        let results_tab = PathBuf::from(format!(
-            "find://Searching {} files for {}",
-            files_count, self.find_in_files_find_query
-        ));
+    // Replaces every match as a single undo step.
+    pub fn replace_all(&mut self) {
+        self.perform_find();
+        let matches = std::mem::take(&mut self.find_matches);
+        let count = matches.len();
+        if let Some(replaced) = self.replace_matches(matches) {
+            if let Some(first) = replaced.first() {
+                self.set_selection(first.start, first.start);
+            }
+            self.perform_find();
+            self.find_status = Some(match count {
+                1 => "Replaced 1 occurrence".to_string(),
+                _ => format!("Replaced {} occurrences", count),
+            });
+        }
+    }
 
-        results.push_str(&format!(
-            "\n{} matches found in {} files.",
-            matches_count, files_count
-        ));
-        self.find_in_files_results = Some(results.clone());
-        self.tab_contents.insert(results_tab.clone(), results);
+    // Applies the replace field to the given matches of the active buffer,
+    // returning where the replacements ended up.
+    fn replace_matches(&mut self, matches: Vec<Range<usize>>) -> Option<Vec<Range<usize>>> {
+        if matches.is_empty() {
+            return None;
+        }
+        let regex = search::build_regex(&self.find_query, &self.find_options).ok()?;
+        let path = self
+            .active_tab_index
+            .and_then(|i| self.open_tabs.get(i))
+            .cloned()?;
+        let buffer = self.tab_contents.get_mut(&path)?;
+
+        let text = buffer.text();
+        let edits: Vec<(Range<usize>, String)> = matches
+            .into_iter()
+            .map(|range| {
+                let bytes = buffer.char_to_byte(range.start)..buffer.char_to_byte(range.end);
+                let replaced = search::replacement(
+                    &regex,
+                    &text,
+                    bytes,
+                    &self.replace_query,
+                    &self.find_options,
+                );
+                (range, replaced)
+            })
+            .collect();
+
+        // Where each edit lands once the ones before it have shifted the text.
+        let mut delta = 0isize;
+        let replaced = edits
+            .iter()
+            .map(|(range, text)| {
+                let start = range.start.saturating_add_signed(delta);
+                let len = text.chars().count();
+                delta += len as isize - range.len() as isize;
+                start..start + len
+            })
+            .collect();
+        let shift = |pos: usize| {
+            let delta: isize = edits
+                .iter()
+                .filter(|(range, _)| range.start < pos && range.end <= pos)
+                .map(|(range, text)| text.chars().count() as isize - range.len() as isize)
+                .sum();
+            pos.saturating_add_signed(delta)
+        };
+        for scope in &mut self.find_scope {
+            *scope = shift(scope.start)..shift(scope.end);
+        }
+
+        buffer.seal_history();
+        buffer.edit_many(edits);
+        buffer.seal_history();
+        self.refresh_dirty(&path);
+        Some(replaced)
+    }
 
+    pub fn perform_find_in_files(&mut self, ctx: &egui::Context) {
+        if self.find_in_files_find_query.is_empty() {
+            self.find_in_files_job = None;
+            self.find_in_files_results.clear();
+            self.find_in_files_current = None;
+            return;
+        }
+        self.find_in_files_history
+            .push(&self.find_in_files_find_query);
+        self.find_in_files_error = None;
+        self.find_in_files_status = None;
+        let scope = self.find_in_files_scope();
+        let context = if self.find_in_files_show_context {
+            self.settings.find_in_files_context_lines
+        } else {
+            0
+        };
+        match SearchJob::start(
+            ctx,
+            &self.find_in_files_find_query,
+            self.find_in_files_options,
+            context,
+            scope,
+        ) {
+            Ok(job) => self.find_in_files_job = Some(job),
+            Err(e) => {
+                self.find_in_files_error = Some(e);
+                return;
+            }
+        }
+        self.find_in_files_results.clear();
+        self.find_in_files_current = None;
+
+        let results_tab = PathBuf::from(RESULTS_TAB);
+        self.tab_contents
+            .insert(results_tab.clone(), Buffer::default());
         if !self.open_tabs.contains(&results_tab) {
             self.open_tabs.push(results_tab.clone());
         }
         self.active_tab_index = self.open_tabs.iter().position(|p| p == &results_tab);
     }
 
-    pub fn perform_replace_in_files(&mut self) {
-        if self.find_in_files_find_query.is_empty() {
+    fn find_in_files_scope(&self) -> SearchScope {
+        let filter = Where::parse(&self.find_in_files_where_query);
+        let mut roots = filter.folders;
+        if filter.open_folders {
+            roots.extend(self.current_dir.clone());
+        }
+        let buffers = self
+            .open_tabs
+            .iter()
+            .filter(|path| {
+                filter.open_files || (filter.unsaved && self.dirty_files.contains(*path))
+            })
+            .filter_map(|path| Some((path.clone(), self.tab_contents.get(path)?.snapshot())))
+            .filter(|(path, _)| path.as_os_str() != RESULTS_TAB)
+            .collect();
+        SearchScope {
+            roots,
+            include: filter.include,
+            exclude: filter.exclude,
+            buffers,
+            respect_gitignore: self.find_in_files_respect_gitignore,
+        }
+    }
+
+    // Moves what the search found since the last frame into the results.
+    fn poll_find_in_files(&mut self, ctx: &egui::Context) {
+        let Some(job) = self.find_in_files_job.as_mut().filter(|job| !job.done) else {
+            return;
+        };
+        self.find_in_files_results.extend(job.poll());
+        // Keep the count of searched files moving.
+        if !job.done {
+            ctx.request_repaint_after(Duration::from_millis(100));
+            return;
+        }
+        let cancelled = job.cancelled();
+        if std::mem::take(&mut self.replace_in_files_pending) && !cancelled {
+            self.preview_replace_in_files();
+        }
+    }
+
+    pub fn cancel_find_in_files(&mut self) {
+        if let Some(job) = &self.find_in_files_job {
+            job.cancel();
+        }
+    }
+
+    pub fn find_in_files_running(&self) -> bool {
+        self.find_in_files_job.as_ref().is_some_and(|job| !job.done)
+    }
+
+    // The line above the results: what is being searched and how far it got.
+    pub fn find_in_files_summary(&self) -> String {
+        let Some(job) = &self.find_in_files_job else {
+            return String::new();
+        };
+        if !job.done {
+            return format!(
+                "Searching {} files for \"{}\"...",
+                job.searched(),
+                job.query
+            );
+        }
+        let modes: Vec<&str> = [
+            (job.options.regex, "regex"),
+            (job.options.case_sensitive, "case sensitive"),
+            (job.options.whole_word, "whole word"),
+        ]
+        .into_iter()
+        .filter_map(|(on, name)| on.then_some(name))
+        .collect();
+        let mut summary = format!("Searched {} files for \"{}\"", job.searched(), job.query);
+        if !modes.is_empty() {
+            summary.push_str(&format!(" ({})", modes.join(", ")));
+        }
+        summary.push_str(&format!(": {} matches in {} files", job.matches, job.files));
+        if job.cancelled() {
+            summary.push_str(" (cancelled)");
+        }
+        summary
+    }
+
+    // Opens the file of a result with the match selected; context lines
+    // just put the caret at their start.
+    pub fn open_result(&mut self, file: usize, line: usize, match_idx: usize) {
+        let Some(result) = self.find_in_files_results.get(file) else {
+            return;
+        };
+        let Some(result_line) = result.lines.get(line) else {
+            return;
+        };
+        let path = result.path.clone();
+        let line_num = result_line.line;
+        let (column, len) = result_line.match_columns(match_idx).unwrap_or((0, 0));
+        self.find_in_files_current = Some((file, line, match_idx));
+        if !self.open_path(path.clone()) {
+            return;
+        }
+        if let Some(buffer) = self.tab_contents.get(&path) {
+            let start = buffer.line_col_to_char(line_num, column);
+            self.set_selection(start, start + len);
+            // Already set up for this tab, so switching to it keeps the
+            // selection instead of restoring the old caret.
+            self.viewed_tab = Some(path);
+        }
+    }
+
+    // F4 and Shift+F4: the next or previous match in the results, wrapping
+    // around at the ends.
+    pub fn step_result(&mut self, forward: bool) {
+        let positions: Vec<(usize, usize, usize)> = self
+            .find_in_files_results
+            .iter()
+            .enumerate()
+            .flat_map(|(f, file)| {
+                file.lines
+                    .iter()
+                    .enumerate()
+                    .flat_map(move |(l, line)| (0..line.matches.len()).map(move |m| (f, l, m)))
+            })
+            .collect();
+        if positions.is_empty() {
             return;
         }
+        let current = self
+            .find_in_files_current
+            .and_then(|at| positions.iter().position(|p| *p >= at));
+        let next = match (current, forward) {
+            (None, true) => 0,
+            (None, false) => positions.len() - 1,
+            (Some(idx), true) if Some(&positions[idx]) == self.find_in_files_current.as_ref() => {
+                (idx + 1) % positions.len()
+            }
+            (Some(idx), true) => idx,
+            (Some(idx), false) => (idx + positions.len() - 1) % positions.len(),
+        };
+        let (file, line, match_idx) = positions[next];
+        self.open_result(file, line, match_idx);
+    }
+
+    // Searches again, so the preview is of what is there now, and then
+    // shows it instead of replacing straight away.
+    pub fn perform_replace_in_files(&mut self, ctx: &egui::Context) {
+        self.perform_find_in_files(ctx);
+        self.replace_in_files_pending = self.find_in_files_running();
+    }
 
-        let walker = WalkBuilder::new(&self.find_in_files_where_query)
-            .git_ignore(self.find_in_files_respect_gitignore)
-            .build();
-
-        for result in walker {
-            if let Ok(entry) = result {
-                if entry.file_type().map_or(false, |ft| ft.is_file()) {
-                    if let Ok(mut content) = fs::read_to_string(entry.path()) {
-                        if content.contains(&self.find_in_files_find_query) {
-                            content = content.replace(
-                                &self.find_in_files_find_query,
-                                &self.find_in_files_replace_query,
-                            );
-                            if fs::write(entry.path(), content).is_ok() {
-                                // Also update the content if the file is open in a tab
-                                if self.tab_contents.contains_key(entry.path()) {
-                                    if let Ok(new_content) = fs::read_to_string(entry.path()) {
-                                        self.tab_contents
-                                            .insert(entry.path().to_path_buf(), new_content);
-                                    }
-                                }
-                            }
+    fn preview_replace_in_files(&mut self) {
+        let Some(job) = &self.find_in_files_job else {
+            return;
+        };
+        let Ok(regex) = search::build_regex(&job.query, &job.options) else {
+            return;
+        };
+        let replace = self.find_in_files_replace_query.clone();
+        let files: Vec<FileChanges> = self
+            .find_in_files_results
+            .iter()
+            .filter_map(|result| {
+                let path = result.path.clone();
+                let (open, text) = match self.tab_contents.get(&path) {
+                    Some(buffer) => (true, (buffer.text(), buffer.encoding())),
+                    None => (false, replace_in_files::read(&path).ok()?),
+                };
+                FileChanges::build(path, open, text, &regex, &replace, &job.options)
+            })
+            .collect();
+        if files.is_empty() {
+            self.find_in_files_status = Some("Nothing to replace".to_string());
+            return;
+        }
+        self.replace_preview = Some(ReplacePreview {
+            query: job.query.clone(),
+            replace,
+            files,
+        });
+
+        let preview_tab = PathBuf::from(PREVIEW_TAB);
+        self.tab_contents
+            .insert(preview_tab.clone(), Buffer::default());
+        if !self.open_tabs.contains(&preview_tab) {
+            self.open_tabs.push(preview_tab.clone());
+        }
+        self.active_tab_index = self.open_tabs.iter().position(|p| p == &preview_tab);
+    }
+
+    // Makes the changes ticked in the preview. Open files are edited in
+    // their tabs and left for the user to save; the rest are written in
+    // place. Files changed since the preview are left alone.
+    pub fn apply_replace_in_files(&mut self, ctx: &egui::Context) {
+        let Some(preview) = self.replace_preview.take() else {
+            return;
+        };
+        self.close_preview_tab();
+        let (mut replaced, mut changes, mut skipped) = (Vec::new(), 0, Vec::new());
+        for file in preview.files.iter().filter(|f| f.selected() > 0) {
+            let after = file.replaced();
+            if let Some(buffer) = self.tab_contents.get_mut(&file.path) {
+                if !buffer.text_eq(&file.original) {
+                    skipped.push(file.path.clone());
+                    continue;
+                }
+                let edits = file
+                    .edits()
+                    .map(|(range, text)| {
+                        let chars =
+                            buffer.byte_to_char(range.start)..buffer.byte_to_char(range.end);
+                        (chars, text.to_string())
+                    })
+                    .collect();
+                buffer.seal_history();
+                buffer.edit_many(edits);
+                buffer.seal_history();
+                let version = Some(buffer.version());
+                self.refresh_dirty(&file.path);
+                replaced.push(ReplacedFile {
+                    path: file.path.clone(),
+                    before: file.original.clone(),
+                    after,
+                    encoding: file.encoding,
+                    version,
+                });
+            } else {
+                let unchanged = replace_in_files::read(&file.path).is_ok_and(|(text, encoding)| {
+                    text == file.original && encoding == file.encoding
+                });
+                if !unchanged
+                    || replace_in_files::write_atomically(&file.path, &after, file.encoding)
+                        .is_err()
+                {
+                    skipped.push(file.path.clone());
+                    continue;
+                }
+                replaced.push(ReplacedFile {
+                    path: file.path.clone(),
+                    before: file.original.clone(),
+                    after,
+                    encoding: file.encoding,
+                    version: None,
+                });
+            }
+            changes += file.selected();
+        }
+
+        let files = replaced.len();
+        if !replaced.is_empty() {
+            self.replace_in_files_undo = Some(replaced);
+        }
+        // Show what is left of the matches.
+        self.perform_find_in_files(ctx);
+        let mut status = format!("Replaced {} occurrences in {} files", changes, files);
+        if !skipped.is_empty() {
+            status.push_str(&format!(
+                "; skipped {} changed since the preview or not writable",
+                skipped.len()
+            ));
+        }
+        self.find_in_files_status = Some(status);
+    }
+
+    pub fn discard_replace_preview(&mut self) {
+        self.replace_preview = None;
+        self.close_preview_tab();
+    }
+
+    fn close_preview_tab(&mut self) {
+        if let Some(idx) = self
+            .open_tabs
+            .iter()
+            .position(|p| p.as_os_str() == PREVIEW_TAB)
+        {
+            self.close_tab(idx);
+        }
+    }
+
+    // Puts back every file of the last Replace in Files, unless it has been
+    // edited since.
+    pub fn undo_replace_in_files(&mut self) {
+        let Some(replaced) = self.replace_in_files_undo.take() else {
+            return;
+        };
+        let (mut restored, mut skipped) = (0, 0);
+        for file in replaced {
+            let done = match self.tab_contents.get_mut(&file.path) {
+                // Still as the replace left it: undo it in the buffer's own
+                // history, which also gets back whether it was saved.
+                Some(buffer) if file.version == Some(buffer.version()) => {
+                    buffer.undo();
+                    true
+                }
+                Some(buffer) if buffer.text_eq(&file.after) => {
+                    let on_disk = file.version.is_none() && !buffer.is_dirty();
+                    if on_disk
+                        && replace_in_files::write_atomically(
+                            &file.path,
+                            &file.before,
+                            file.encoding,
+                        )
+                        .is_err()
+                    {
+                        false
+                    } else {
+                        buffer.set_text(&file.before);
+                        if on_disk {
+                            buffer.mark_saved();
                         }
+                        true
                     }
                 }
+                Some(_) => false,
+                None => {
+                    replace_in_files::read(&file.path).is_ok_and(|(text, _)| text == file.after)
+                        && replace_in_files::write_atomically(
+                            &file.path,
+                            &file.before,
+                            file.encoding,
+                        )
+                        .is_ok()
+                }
+            };
+            if done {
+                restored += 1;
+                self.refresh_dirty(&file.path);
+            } else {
+                skipped += 1;
             }
         }
-        self.perform_find_in_files();
+        let mut status = format!("Undid Replace in Files in {} files", restored);
+        if skipped > 0 {
+            status.push_str(&format!("; skipped {} edited since", skipped));
+        }
+        self.find_in_files_status = Some(status);
     }
 
-    pub fn move_to_match(&mut self, ctx: &egui::Context) {
+    // Selects the current match, leaving focus in the find panel.
+    pub fn move_to_match(&mut self) {
         if let Some(match_idx) = self.current_match_index {
-            if let Some(char_offset) = self.find_matches.get(match_idx) {
-                let editor_id = egui::Id::new("main_editor");
-                if let Some(mut state) = egui::text_edit::TextEditState::load(ctx, editor_id) {
-                    let start = egui::text::CCursor::new(*char_offset);
-                    let end =
-                        egui::text::CCursor::new(*char_offset + self.find_query.chars().count());
-                    state
-                        .cursor
-                        .set_char_range(Some(egui::text::CCursorRange::two(start, end)));
-                    state.store(ctx, editor_id);
-                    ctx.memory_mut(|mem| mem.request_focus(editor_id));
-                    self.find_scroll_requested = true;
-                }
+            if let Some(range) = self.find_matches.get(match_idx).cloned() {
+                self.set_selection(range.start, range.end);
             }
         }
     }
 
-    pub fn save_file(&mut self, path: PathBuf) {
-        if let Some(content) = self.tab_contents.get(&path) {
-            if let Ok(_) = fs::write(&path, content) {
+    // Selects a char range in the editor, focuses it and scrolls it into view.
+    pub fn select_range(&mut self, ctx: &egui::Context, start: usize, end: usize) {
+        self.set_selection(start, end);
+        ctx.memory_mut(|mem| mem.request_focus(egui::Id::new("main_editor")));
+    }
+
+    // Like `select_range` but leaves keyboard focus where it is.
+    pub fn set_selection(&mut self, start: usize, end: usize) {
+        self.selections = Selections::single(Selection::new(start, end));
+        self.find_scroll_requested = true;
+
+        // Keep the tab's remembered caret in sync so a tab switch in the same
+        // frame does not put the old one back.
+        if let Some(path) = self.active_tab_index.and_then(|i| self.open_tabs.get(i)) {
+            self.tab_views.entry(path.clone()).or_default().cursor = start;
+        }
+    }
+
+    // Runs a multiple selection command on the active tab.
+    pub fn change_selections(
+        &mut self,
+        ctx: &egui::Context,
+        change: impl FnOnce(&mut Selections, &Buffer),
+    ) {
+        let Some(path) = self.active_tab_index.and_then(|i| self.open_tabs.get(i)) else {
+            return;
+        };
+        let Some(buffer) = self.tab_contents.get(path) else {
+            return;
+        };
+        change(&mut self.selections, buffer);
+        ctx.memory_mut(|mem| mem.request_focus(egui::Id::new("main_editor")));
+    }
+
+    // Moves the caret of the active tab to a zero-based line and column.
+    pub fn goto_line_col(&mut self, line: usize, col: usize) {
+        let Some(path) = self.active_tab_index.and_then(|i| self.open_tabs.get(i)) else {
+            return;
+        };
+        if let Some(buffer) = self.tab_contents.get(path) {
+            let char_idx = buffer.line_col_to_char(line, col);
+            self.set_selection(char_idx, char_idx);
+        }
+    }
+
+    pub fn show_goto_anything(&mut self, ctx: &egui::Context) {
+        self.goto_index = self
+            .current_dir
+            .as_ref()
+            .map(|root| goto::FileIndex::start(ctx, root, self.find_in_files_respect_gitignore));
+        self.goto_active = true;
+        self.goto_just_activated = true;
+        self.goto_query.clear();
+        self.goto_selected = 0;
+        self.goto_symbols = None;
+        self.goto_previewed = None;
+        self.goto_transient_tab = None;
+        self.goto_return_tab = self.active_tab_index;
+    }
+
+    // Shows `path` while Goto Anything is open. Files that were not already
+    // open get a transient tab that is dropped again if the overlay is
+    // cancelled or moves on to another file.
+    pub fn preview_goto(&mut self, path: PathBuf, position: Option<(usize, usize)>) {
+        let key = Some((path.clone(), position));
+        if self.goto_previewed == key {
+            return;
+        }
+        self.goto_previewed = key;
+
+        if self.goto_transient_tab.as_ref().is_some_and(|t| t != &path) {
+            self.close_transient_tab();
+        }
+        let was_open = self.open_tabs.contains(&path);
+        if !self.open_path(path.clone()) {
+            return;
+        }
+        if !was_open {
+            self.goto_transient_tab = Some(path);
+        }
+        if let Some((line, col)) = position {
+            self.goto_line_col(line, col);
+        }
+    }
+
+    pub fn finish_goto(&mut self, ctx: &egui::Context, confirm: bool) {
+        self.goto_active = false;
+        self.goto_previewed = None;
+        // Stops the walk if it is still going.
+        self.goto_index = None;
+        if confirm {
+            self.goto_transient_tab = None;
+            ctx.memory_mut(|mem| mem.request_focus(egui::Id::new("main_editor")));
+        } else {
+            self.close_transient_tab();
+            self.active_tab_index = self.goto_return_tab.filter(|&i| i < self.open_tabs.len());
+        }
+    }
+
+    fn close_transient_tab(&mut self) {
+        if let Some(path) = self.goto_transient_tab.take() {
+            if let Some(idx) = self.open_tabs.iter().position(|p| p == &path) {
+                self.close_tab(idx);
+            }
+        }
+    }
+
+    pub fn save_file(&mut self, path: PathBuf) -> bool {
+        let Some(buffer) = self.tab_contents.get_mut(&path) else {
+            return false;
+        };
+        match buffer.save(&path) {
+            Ok(()) => {
+                buffer.mark_saved();
                 self.dirty_files.remove(&path);
+                self.file_error = None;
+                true
+            }
+            Err(e) => {
+                self.file_error = Some(format!("Cannot save {}: {}", path.display(), e));
+                false
+            }
+        }
+    }
+
+    // Reads the active file again as the given encoding. Only offered for
+    // tabs without unsaved changes, which this would throw away.
+    pub fn reopen_with_encoding(&mut self, encoding: &'static Encoding) {
+        let Some(path) = self
+            .active_tab_index
+            .and_then(|i| self.open_tabs.get(i))
+            .cloned()
+        else {
+            return;
+        };
+        match Buffer::from_file_as(&path, encoding) {
+            Ok(buffer) => {
+                let cursor = self.selections.primary().head.min(buffer.len_chars());
+                self.tab_contents.insert(path.clone(), buffer);
+                self.dirty_files.remove(&path);
+                self.file_error = None;
+                self.set_selection(cursor, cursor);
+            }
+            Err(e) => {
+                self.file_error = Some(format!("Cannot open {}: {}", path.display(), e));
+            }
+        }
+    }
+
+    pub fn active_encoding(&self) -> Option<TextEncoding> {
+        let path = self.active_tab_index.and_then(|i| self.open_tabs.get(i))?;
+        Some(self.tab_contents.get(path)?.encoding())
+    }
+
+    pub fn show_reopen_with_encoding(&mut self) {
+        let current = self.active_encoding();
+        let items = encoding::CHOICES
+            .iter()
+            .map(|choice| TextEncoding::new(choice(), false).name())
+            .collect();
+        let current = encoding::CHOICES
+            .iter()
+            .position(|choice| current.is_some_and(|c| c.encoding == choice()));
+        self.quick_panel = Some(QuickPanel::new(
+            "Reopen with encoding",
+            items,
+            current,
+            |app, _, idx, _| app.reopen_with_encoding(encoding::CHOICES[idx]()),
+        ));
+    }
+
+    pub fn show_save_with_encoding(&mut self) {
+        let choices = encoding::save_choices();
+        let current = self.active_encoding();
+        let items = choices.iter().map(TextEncoding::name).collect();
+        let current = choices.iter().position(|c| Some(*c) == current);
+        self.quick_panel = Some(QuickPanel::new(
+            "Save with encoding",
+            items,
+            current,
+            |app, _, idx, _| app.save_with_encoding(encoding::save_choices()[idx]),
+        ));
+    }
+
+    // Saves the active file in another encoding, which it then keeps.
+    pub fn save_with_encoding(&mut self, encoding: TextEncoding) {
+        let Some(path) = self
+            .active_tab_index
+            .and_then(|i| self.open_tabs.get(i))
+            .cloned()
+        else {
+            return;
+        };
+        let Some(buffer) = self.tab_contents.get_mut(&path) else {
+            return;
+        };
+        let previous = buffer.encoding();
+        buffer.set_encoding(encoding);
+        if !self.save_file(path.clone()) {
+            if let Some(buffer) = self.tab_contents.get_mut(&path) {
+                buffer.set_encoding(previous);
             }
         }
     }
 
+    pub fn refresh_dirty(&mut self, path: &PathBuf) {
+        if self.tab_contents.get(path).is_some_and(|b| b.is_dirty()) {
+            self.dirty_files.insert(path.clone());
+        } else {
+            self.dirty_files.remove(path);
+        }
+    }
+
+    pub fn undo(&mut self, ctx: &egui::Context) {
+        self.apply_history(ctx, Buffer::undo);
+    }
+
+    pub fn redo(&mut self, ctx: &egui::Context) {
+        self.apply_history(ctx, Buffer::redo);
+    }
+
+    fn apply_history(&mut self, ctx: &egui::Context, step: fn(&mut Buffer) -> Option<usize>) {
+        let Some(path) = self
+            .active_tab_index
+            .and_then(|i| self.open_tabs.get(i))
+            .cloned()
+        else {
+            return;
+        };
+        let Some(cursor) = self.tab_contents.get_mut(&path).and_then(step) else {
+            return;
+        };
+        self.refresh_dirty(&path);
+        self.select_range(ctx, cursor, cursor);
+    }
+
     pub fn save_active_file(&mut self) {
         if let Some(idx) = self.active_tab_index {
             if let Some(path) = self.open_tabs.get(idx).cloned() {
@@ -298,11 +1568,12 @@ impl SublimeRustApp {
                     .set_file_name(path.file_name().unwrap().to_str().unwrap())
                     .save_file()
                 {
-                    if let Some(content) = self.tab_contents.get(&path).cloned() {
-                        if let Ok(_) = fs::write(&new_path, &content) {
+                    if let Some(mut buffer) = self.tab_contents.get(&path).cloned() {
+                        if buffer.save(&new_path).is_ok() {
+                            buffer.mark_saved();
                             self.tab_contents.remove(&path);
                             self.dirty_files.remove(&path);
-                            self.tab_contents.insert(new_path.clone(), content);
+                            self.tab_contents.insert(new_path.clone(), buffer);
                             self.open_tabs[idx] = new_path;
                         }
                     }
@@ -320,7 +1591,18 @@ impl SublimeRustApp {
 
     pub fn close_tab(&mut self, idx: usize) {
         let path = self.open_tabs.remove(idx);
+        // Whatever is kept for a path is what is open in it, so the buffer
+        // goes with the tab.
+        self.tab_contents.remove(&path);
         self.dirty_files.remove(&path);
+        if path.as_os_str() == PREVIEW_TAB {
+            self.replace_preview = None;
+        }
+        self.highlighter.forget(&path);
+        self.syntax_overrides.remove(&path);
+        if path.is_file() {
+            self.closed_tabs.push(path);
+        }
 
         if let Some(active_idx) = self.active_tab_index {
             if idx == active_idx {
@@ -339,6 +1621,13 @@ impl SublimeRustApp {
 }
 
 impl eframe::App for SublimeRustApp {
+    // Hot exit: unsaved buffers are kept in the session instead of prompting.
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        if self.persist_session {
+            let _ = Session::capture(self).save();
+        }
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         // Set the window title
         let mut title = "SuRuC".to_string();
@@ -351,78 +1640,52 @@ impl eframe::App for SublimeRustApp {
         }
         ctx.send_viewport_cmd(egui::ViewportCommand::Title(title));
 
-        // Handle shortcuts
-        if ctx.input_mut(|i| {
-            i.consume_shortcut(&egui::KeyboardShortcut::new(
-                egui::Modifiers::CTRL,
-                egui::Key::N,
-            ))
-        }) {
-            self.new_file();
-        }
-        if ctx.input_mut(|i| {
-            i.consume_shortcut(&egui::KeyboardShortcut::new(
-                egui::Modifiers::CTRL,
-                egui::Key::S,
-            ))
-        }) {
-            self.save_active_file();
-        }
-        if ctx.input_mut(|i| {
-            i.consume_shortcut(&egui::KeyboardShortcut::new(
-                egui::Modifiers::CTRL | egui::Modifiers::SHIFT,
-                egui::Key::S,
-            ))
-        }) {
-            self.save_as_active_file();
-        }
-        if ctx.input_mut(|i| {
-            i.consume_shortcut(&egui::KeyboardShortcut::new(
-                egui::Modifiers::COMMAND | egui::Modifiers::SHIFT,
-                egui::Key::F,
-            ))
-        }) {
-            self.find_in_files_active = true; // !self.find_in_files_active;
-            if self.find_in_files_active {
-                if let Some(dir) = &self.current_dir {
-                    self.find_in_files_where_query = dir.to_str().unwrap_or("").to_string();
-                }
-            }
-        } else if ctx.input_mut(|i| {
-            i.consume_shortcut(&egui::KeyboardShortcut::new(
-                egui::Modifiers::CTRL,
-                egui::Key::F,
-            ))
-        }) {
-            self.find_active = true;
-            self.find_just_activated = true;
-        }
-        if ctx.input_mut(|i| {
-            i.consume_shortcut(&egui::KeyboardShortcut::new(
-                egui::Modifiers::CTRL,
-                egui::Key::O,
-            ))
-        }) {
-            self.open_file();
-        }
-        if ctx.input_mut(|i| {
-            i.consume_shortcut(&egui::KeyboardShortcut::new(
-                egui::Modifiers::CTRL | egui::Modifiers::SHIFT,
-                egui::Key::O,
-            ))
-        }) {
-            self.open_folder();
+        if let Some((settings, error)) = self.settings_loader.poll() {
+            self.set_settings(settings, error);
         }
+        if self.settings_changed {
+            self.apply_settings(ctx);
+        }
+
+        // `--wait`: the window exists only for these files, so it goes away
+        // once the last of them is closed.
+        if !self.wait_tabs.is_empty() && !self.wait_tabs.iter().any(|p| self.open_tabs.contains(p))
+        {
+            self.wait_tabs.clear();
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        }
+
+        self.handle_forwarded_opens(ctx);
+        self.poll_find_in_files(ctx);
+
+        commands::handle_shortcuts(self, ctx);
         if self.find_in_files_active && ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
             self.find_in_files_active = false;
         }
         if self.find_active && ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
             self.find_active = false;
         }
+        if self.palette_active && ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.palette_active = false;
+        }
+        if self.goto_active && ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.finish_goto(ctx, false);
+        }
+        if self.theme_picker_active && ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.finish_theme_picker(ctx, None);
+        }
+        if self.quick_panel.is_some() && ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.quick_panel = None;
+        }
 
         ui::render_menu_bar(self, ctx);
         ui::render_footer(self, ctx);
         ui::render_close_confirmation(self, ctx);
+        ui::render_about(self, ctx);
+        ui::render_command_palette(self, ctx);
+        ui::render_goto_anything(self, ctx);
+        ui::render_theme_picker(self, ctx);
+        ui::render_quick_panel(self, ctx);
 
         if self.sidebar_visible {
             if let Some(root) = self.current_dir.clone() {
@@ -440,7 +1703,7 @@ impl eframe::App for SublimeRustApp {
         }
 
         egui::CentralPanel::default().show(ctx, |ui| {
-            if self.current_dir.is_none() {
+            if self.current_dir.is_none() && self.open_tabs.is_empty() {
                 ui.centered_and_justified(|ui| {
                     ui.label("Open a file or folder to start.");
                 });
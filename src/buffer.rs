@@ -0,0 +1,320 @@
+use crate::encoding::{self, TextEncoding};
+use crate::history::{Edit, History};
+use encoding_rs::Encoding;
+use ropey::Rope;
+use std::fs;
+use std::io::{self, BufWriter};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static NEXT_VERSION: AtomicU64 = AtomicU64::new(1);
+
+fn next_version() -> u64 {
+    NEXT_VERSION.fetch_add(1, Ordering::Relaxed)
+}
+
+// Text storage for a single tab, kept in a rope so edits stay cheap however
+// large the text is.
+#[derive(Clone, Default)]
+pub struct Buffer {
+    rope: Rope,
+    history: History,
+    // Changes with every edit and is never shared by two different texts,
+    // so it can key caches of derived data (layout, highlighting).
+    version: u64,
+    encoding: TextEncoding,
+}
+
+impl Buffer {
+    pub fn new() -> Self {
+        Self {
+            history: History::unsaved(),
+            version: next_version(),
+            ..Self::default()
+        }
+    }
+
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let (text, encoding) = encoding::decode(&fs::read(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Self::decoded(text, encoding))
+    }
+
+    // Reads the file as the given encoding instead of detecting it.
+    pub fn from_file_as(path: &Path, encoding: &'static Encoding) -> io::Result<Self> {
+        let (text, encoding) = encoding::decode_as(&fs::read(path)?, encoding)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Self::decoded(text, encoding))
+    }
+
+    fn decoded(text: String, encoding: TextEncoding) -> Self {
+        Self {
+            rope: Rope::from_str(&text),
+            version: next_version(),
+            encoding,
+            ..Self::default()
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if self.encoding == TextEncoding::default() {
+            let file = fs::File::create(path)?;
+            return self.rope.write_to(BufWriter::new(file));
+        }
+        // Encoded before the file is touched, so text the encoding cannot
+        // hold leaves it as it was.
+        let bytes = encoding::encode(&self.rope.to_string(), self.encoding)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, bytes)
+    }
+
+    pub fn encoding(&self) -> TextEncoding {
+        self.encoding
+    }
+
+    pub fn set_encoding(&mut self, encoding: TextEncoding) {
+        self.encoding = encoding;
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    // A cheap copy of the text that later edits do not affect.
+    pub fn snapshot(&self) -> Rope {
+        self.rope.clone()
+    }
+
+    pub fn len_lines(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    pub fn len_chars(&self) -> usize {
+        self.rope.len_chars()
+    }
+
+    pub fn line_to_char(&self, line: usize) -> usize {
+        self.rope.line_to_char(line.min(self.rope.len_lines()))
+    }
+
+    // Length of a line in chars, without its line ending.
+    pub fn line_len(&self, line: usize) -> usize {
+        if line >= self.rope.len_lines() {
+            return 0;
+        }
+        let slice = self.rope.line(line);
+        let mut len = slice.len_chars();
+        if len > 0 && slice.char(len - 1) == '\n' {
+            len -= 1;
+            if len > 0 && slice.char(len - 1) == '\r' {
+                len -= 1;
+            }
+        }
+        len
+    }
+
+    // The text of a line without its line ending.
+    pub fn line_text(&self, line: usize) -> String {
+        if line >= self.rope.len_lines() {
+            return String::new();
+        }
+        let start = self.rope.line_to_char(line);
+        self.rope
+            .slice(start..start + self.line_len(line))
+            .to_string()
+    }
+
+    // The whole text as one string, for APIs that need a `&str`. It is a
+    // copy, so it is only made when one is actually needed.
+    pub fn text(&self) -> String {
+        self.rope.to_string()
+    }
+
+    pub fn text_eq(&self, text: &str) -> bool {
+        self.rope == text
+    }
+
+    pub fn char_at(&self, char_idx: usize) -> Option<char> {
+        (char_idx < self.rope.len_chars()).then(|| self.rope.char(char_idx))
+    }
+
+    pub fn slice(&self, char_range: Range<usize>) -> String {
+        let len = self.rope.len_chars();
+        self.rope
+            .slice(char_range.start.min(len)..char_range.end.min(len))
+            .to_string()
+    }
+
+    pub fn char_to_byte(&self, char_idx: usize) -> usize {
+        self.rope.char_to_byte(char_idx.min(self.rope.len_chars()))
+    }
+
+    pub fn byte_to_char(&self, byte_idx: usize) -> usize {
+        self.rope.byte_to_char(byte_idx.min(self.rope.len_bytes()))
+    }
+
+    // Zero-based (line, column) of a char index, column counted in chars.
+    pub fn char_to_line_col(&self, char_idx: usize) -> (usize, usize) {
+        let char_idx = char_idx.min(self.rope.len_chars());
+        let line = self.rope.char_to_line(char_idx);
+        (line, char_idx - self.rope.line_to_char(line))
+    }
+
+    pub fn line_col_to_char(&self, line: usize, col: usize) -> usize {
+        if line >= self.rope.len_lines() {
+            return self.rope.len_chars();
+        }
+        self.rope.line_to_char(line) + col.min(self.line_len(line))
+    }
+
+    // The column a char is drawn at, with tabs taken into account.
+    pub fn display_col(&self, char_idx: usize, tab_size: usize) -> usize {
+        let (line, col) = self.char_to_line_col(char_idx);
+        self.rope
+            .line(line)
+            .chars()
+            .take(col)
+            .fold(0, |at, c| col_after(c, at, tab_size))
+    }
+
+    // The char drawn nearest to a display column of `line`, or the end of
+    // the line if it is shorter.
+    pub fn display_col_to_char(&self, line: usize, col: usize, tab_size: usize) -> usize {
+        if line >= self.rope.len_lines() {
+            return self.rope.len_chars();
+        }
+        let start = self.rope.line_to_char(line);
+        let mut at = 0;
+        for (idx, c) in self
+            .rope
+            .line(line)
+            .chars()
+            .take(self.line_len(line))
+            .enumerate()
+        {
+            let next = col_after(c, at, tab_size);
+            if next > col {
+                // Within a tab, whichever of its sides is closer.
+                return start + idx + usize::from(col - at > next - col);
+            }
+            at = next;
+        }
+        start + self.line_len(line)
+    }
+
+    // Replaces several ranges at once, e.g. typing at multiple carets. The
+    // ranges are in the current text, sorted and non-overlapping.
+    pub fn edit_many(&mut self, edits: Vec<(Range<usize>, String)>) {
+        let len = self.rope.len_chars();
+        let mut recorded = Vec::new();
+        // Back to front, so earlier edits do not shift later ranges.
+        for (range, text) in edits.into_iter().rev() {
+            let range = range.start.min(len)..range.end.min(len);
+            if range.is_empty() && text.is_empty() {
+                continue;
+            }
+            let removed = self.rope.slice(range.clone()).to_string();
+            self.remove_raw(range.clone());
+            self.insert_raw(range.start, &text);
+            recorded.push(Edit {
+                at: range.start,
+                removed,
+                inserted: text,
+            });
+        }
+        self.history.record_batch(recorded);
+    }
+
+    // Replaces the whole text as a single undoable edit.
+    pub fn set_text(&mut self, text: &str) {
+        let removed = self.rope.to_string();
+        if removed == text {
+            return;
+        }
+        self.rope = Rope::from_str(text);
+        self.version = next_version();
+        self.history.record_group(vec![Edit {
+            at: 0,
+            removed,
+            inserted: text.to_string(),
+        }]);
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.history.is_dirty()
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.history.mark_saved();
+    }
+
+    pub fn seal_history(&mut self) {
+        self.history.seal();
+    }
+
+    // Reverts the last undo step, returning where the cursor should go.
+    pub fn undo(&mut self) -> Option<usize> {
+        let edits = self.history.undo()?;
+        let mut cursor = 0;
+        for edit in edits {
+            let inserted = edit.inserted.chars().count();
+            self.remove_raw(edit.at..edit.at + inserted);
+            self.insert_raw(edit.at, &edit.removed);
+            cursor = edit.at + edit.removed.chars().count();
+        }
+        Some(cursor)
+    }
+
+    pub fn redo(&mut self) -> Option<usize> {
+        let edits = self.history.redo()?;
+        let mut cursor = 0;
+        for edit in edits {
+            let removed = edit.removed.chars().count();
+            self.remove_raw(edit.at..edit.at + removed);
+            self.insert_raw(edit.at, &edit.inserted);
+            cursor = edit.end();
+        }
+        Some(cursor)
+    }
+
+    fn insert_raw(&mut self, char_idx: usize, text: &str) {
+        self.version = next_version();
+        self.rope.insert(char_idx, text);
+    }
+
+    fn remove_raw(&mut self, char_range: Range<usize>) {
+        if char_range.is_empty() {
+            return;
+        }
+        self.version = next_version();
+        self.rope.remove(char_range);
+    }
+}
+
+// Where the char drawn at column `col` ends: a tab reaches the next
+// multiple of `tab_size`, anything else takes one column.
+pub fn col_after(c: char, col: usize, tab_size: usize) -> usize {
+    if c == '\t' {
+        (col / tab_size + 1) * tab_size
+    } else {
+        col + 1
+    }
+}
+
+impl From<String> for Buffer {
+    fn from(text: String) -> Self {
+        Self {
+            rope: Rope::from_str(&text),
+            history: History::default(),
+            version: next_version(),
+            encoding: TextEncoding::default(),
+        }
+    }
+}
+
+impl From<&str> for Buffer {
+    fn from(text: &str) -> Self {
+        Self::from(text.to_string())
+    }
+}
@@ -0,0 +1,222 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+pub const USAGE: &str = "\
+Usage: suruc [OPTIONS] [PATH[:LINE[:COL]] | -]...
+
+Arguments:
+  PATH[:LINE[:COL]]  File or folder to open, optionally at a line and column
+  -                  Read text from stdin into a new untitled buffer
+
+Options:
+  -n, --new-window   Open a new window instead of reusing a running one
+  -w, --wait         Wait for the opened files to be closed before exiting
+  -h, --help         Print this help
+  -v, --version      Print the version";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Target {
+    // Line and column are one-based, as typed on the command line.
+    File {
+        path: PathBuf,
+        line: Option<usize>,
+        col: Option<usize>,
+    },
+    Folder(PathBuf),
+    Stdin(String),
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CliArgs {
+    pub targets: Vec<Target>,
+    pub new_window: bool,
+    pub wait: bool,
+}
+
+pub enum ParseOutcome {
+    Run(CliArgs),
+    // Print the message and exit with the code.
+    Exit(String, i32),
+}
+
+// Resolves `.`, `..` and symlinks, so a file is opened in one tab however
+// it was named. A file that does not exist yet goes by its folder.
+fn absolute(path: &Path) -> PathBuf {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir()
+            .map(|cwd| cwd.join(path))
+            .unwrap_or_else(|_| path.to_path_buf())
+    };
+    if let Ok(resolved) = path.canonicalize() {
+        return resolved;
+    }
+    let resolved = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => parent.canonicalize().ok().map(|p| p.join(name)),
+        _ => None,
+    };
+    resolved.unwrap_or(path)
+}
+
+// `foo.rs:12:5` means line 12, column 5 of `foo.rs` unless a file with that
+// literal name exists.
+fn parse_path(arg: &str) -> Target {
+    let literal = Path::new(arg);
+    if !literal.exists() {
+        let mut path = arg;
+        let mut numbers = Vec::new();
+        while numbers.len() < 2 {
+            match path.rsplit_once(':') {
+                Some((head, tail)) if !head.is_empty() => match tail.parse::<usize>() {
+                    Ok(n) => {
+                        numbers.insert(0, n);
+                        path = head;
+                    }
+                    Err(_) => break,
+                },
+                _ => break,
+            }
+        }
+        if !numbers.is_empty() {
+            return Target::File {
+                path: absolute(Path::new(path)),
+                line: numbers.first().copied(),
+                col: numbers.get(1).copied(),
+            };
+        }
+    }
+
+    let path = absolute(literal);
+    if path.is_dir() {
+        Target::Folder(path)
+    } else {
+        Target::File {
+            path,
+            line: None,
+            col: None,
+        }
+    }
+}
+
+pub fn parse(args: impl IntoIterator<Item = String>) -> ParseOutcome {
+    let mut parsed = CliArgs::default();
+    let mut only_paths = false;
+
+    for arg in args {
+        if only_paths {
+            parsed.targets.push(parse_path(&arg));
+            continue;
+        }
+        match arg.as_str() {
+            "--" => only_paths = true,
+            "-n" | "--new-window" => parsed.new_window = true,
+            "-w" | "--wait" => parsed.wait = true,
+            "-h" | "--help" => return ParseOutcome::Exit(USAGE.to_string(), 0),
+            "-v" | "--version" => {
+                return ParseOutcome::Exit(format!("suruc {}", env!("CARGO_PKG_VERSION")), 0)
+            }
+            "-" => {
+                let mut text = String::new();
+                if let Err(e) = io::stdin().read_to_string(&mut text) {
+                    return ParseOutcome::Exit(format!("suruc: cannot read stdin: {}", e), 1);
+                }
+                parsed.targets.push(Target::Stdin(text));
+            }
+            flag if flag.starts_with('-') => {
+                return ParseOutcome::Exit(
+                    format!("suruc: unknown option '{}'\n\n{}", flag, USAGE),
+                    2,
+                )
+            }
+            path => parsed.targets.push(parse_path(path)),
+        }
+    }
+    ParseOutcome::Run(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(args: &[&str]) -> CliArgs {
+        match parse(args.iter().map(|a| a.to_string())) {
+            ParseOutcome::Run(args) => args,
+            ParseOutcome::Exit(message, _) => panic!("exited: {}", message),
+        }
+    }
+
+    fn file(target: &Target) -> (&Path, Option<usize>, Option<usize>) {
+        match target {
+            Target::File { path, line, col } => (path, *line, *col),
+            other => panic!("not a file: {:?}", other),
+        }
+    }
+
+    fn create(path: &Path) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "").unwrap();
+    }
+
+    #[test]
+    fn line_and_column_suffixes() {
+        let expected = absolute(Path::new("no-such-file.rs"));
+        let args = run(&[
+            "no-such-file.rs:12:5",
+            "no-such-file.rs:7",
+            "no-such-file.rs",
+        ]);
+        assert_eq!(
+            file(&args.targets[0]),
+            (expected.as_path(), Some(12), Some(5))
+        );
+        assert_eq!(file(&args.targets[1]), (expected.as_path(), Some(7), None));
+        assert_eq!(file(&args.targets[2]), (expected.as_path(), None, None));
+    }
+
+    #[test]
+    fn an_existing_file_is_taken_literally() {
+        let dir = env::temp_dir().join(format!("suruc-cli-{}", std::process::id()));
+        create(&dir.join("notes:3"));
+        let literal = dir.join("notes:3").display().to_string();
+        let args = run(&[&literal]);
+        assert_eq!(file(&args.targets[0]).1, None);
+        assert!(file(&args.targets[0]).0.ends_with("notes:3"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn relative_and_absolute_names_agree() {
+        let cwd = env::current_dir().unwrap();
+        let args = run(&["./Cargo.toml", "src/../Cargo.toml"]);
+        let absolute = cwd.join("Cargo.toml").canonicalize().unwrap();
+        assert_eq!(file(&args.targets[0]).0, absolute);
+        assert_eq!(file(&args.targets[1]).0, absolute);
+        assert!(matches!(&run(&["src"]).targets[0], Target::Folder(p) if p.ends_with("src")));
+    }
+
+    #[test]
+    fn flags_and_double_dash() {
+        let args = run(&["-n", "--wait", "--", "-w"]);
+        assert!(args.new_window && args.wait);
+        assert!(file(&args.targets[0]).0.ends_with("-w"));
+    }
+
+    #[test]
+    fn unknown_flags_exit_with_usage() {
+        match parse(["--frobnicate".to_string()]) {
+            ParseOutcome::Exit(message, code) => {
+                assert_eq!(code, 2);
+                assert!(message.contains("unknown option '--frobnicate'"));
+                assert!(message.contains("Usage:"));
+            }
+            ParseOutcome::Run(_) => panic!("accepted an unknown flag"),
+        }
+        assert!(matches!(
+            parse(["-h".to_string()]),
+            ParseOutcome::Exit(_, 0)
+        ));
+    }
+}
@@ -0,0 +1,614 @@
+use crate::app::SublimeRustApp;
+use crate::selection::Selections;
+use crate::ui::menu;
+use eframe::egui;
+use egui::{Key, KeyboardShortcut, Modifiers};
+use once_cell::sync::Lazy;
+
+pub struct Command {
+    pub id: &'static str,
+    pub title: &'static str,
+    // Top-level menu and group within it; groups are split by separators.
+    pub menu: Option<(&'static str, u32)>,
+    // The first binding is the one shown in menus and the palette.
+    pub shortcuts: Vec<KeyboardShortcut>,
+    // The editor widget already reacts to the binding itself, so it is only
+    // displayed and never consumed.
+    pub passthrough: bool,
+    // Shown as a submenu of its choices in menus; the palette runs the
+    // command instead, which asks in a quick panel.
+    pub submenu: Option<fn(&mut SublimeRustApp, &mut egui::Ui)>,
+    pub enabled: fn(&SublimeRustApp) -> bool,
+    pub run: fn(&mut SublimeRustApp, &egui::Context),
+}
+
+pub const MENUS: [&str; 10] = [
+    "File",
+    "Edit",
+    "Selection",
+    "Find",
+    "View",
+    "Goto",
+    "Tools",
+    "Project",
+    "Preferences",
+    "Help",
+];
+
+const CTRL: Modifiers = Modifiers::CTRL;
+const CTRL_SHIFT: Modifiers = Modifiers {
+    shift: true,
+    ..Modifiers::CTRL
+};
+const CTRL_ALT: Modifiers = Modifiers {
+    alt: true,
+    ..Modifiers::CTRL
+};
+const COMMAND_SHIFT: Modifiers = Modifiers {
+    shift: true,
+    ..Modifiers::COMMAND
+};
+
+fn always(_: &SublimeRustApp) -> bool {
+    true
+}
+
+fn has_active_tab(app: &SublimeRustApp) -> bool {
+    app.active_tab_index.is_some()
+}
+
+fn has_find_query(app: &SublimeRustApp) -> bool {
+    app.active_tab_index.is_some() && !app.find_query.is_empty()
+}
+
+fn has_find_results(app: &SublimeRustApp) -> bool {
+    !app.find_in_files_results.is_empty()
+}
+
+fn replace_visible(app: &SublimeRustApp) -> bool {
+    app.find_active && app.replace_active && app.active_tab_index.is_some()
+}
+
+fn has_replace_undo(app: &SublimeRustApp) -> bool {
+    app.replace_in_files_undo.is_some()
+}
+
+// A tab holding a file or an untitled buffer, not results.
+fn has_file_tab(app: &SublimeRustApp) -> bool {
+    app.active_tab_index
+        .and_then(|i| app.open_tabs.get(i))
+        .is_some_and(|p| {
+            app.tab_contents.contains_key(p) && !p.to_str().unwrap_or("").starts_with("find://")
+        })
+}
+
+// Reopening throws away unsaved changes, so it waits until there are none.
+fn can_reopen(app: &SublimeRustApp) -> bool {
+    app.active_tab_index
+        .and_then(|i| app.open_tabs.get(i))
+        .is_some_and(|p| p.is_file() && !app.dirty_files.contains(p))
+}
+
+fn has_dirty_files(app: &SublimeRustApp) -> bool {
+    !app.dirty_files.is_empty()
+}
+
+// Feeds an event to the editor widget as if the user had typed it.
+fn send_to_editor(ctx: &egui::Context, event: egui::Event) {
+    ctx.memory_mut(|mem| mem.request_focus(egui::Id::new("main_editor")));
+    ctx.input_mut(|i| i.events.push(event));
+}
+
+pub static COMMANDS: Lazy<Vec<Command>> = Lazy::new(|| {
+    vec![
+        Command {
+            id: "new_file",
+            title: "New File",
+            menu: Some(("File", 0)),
+            shortcuts: vec![KeyboardShortcut::new(CTRL, Key::N)],
+            passthrough: false,
+            submenu: None,
+            enabled: always,
+            run: |app, _| {
+                app.new_file();
+            },
+        },
+        Command {
+            id: "prompt_open_file",
+            title: "Open File...",
+            menu: Some(("File", 1)),
+            shortcuts: vec![KeyboardShortcut::new(CTRL, Key::O)],
+            passthrough: false,
+            submenu: None,
+            enabled: always,
+            run: |app, _| app.open_file(),
+        },
+        Command {
+            id: "prompt_open_folder",
+            title: "Open Folder...",
+            menu: Some(("File", 1)),
+            shortcuts: vec![KeyboardShortcut::new(CTRL_SHIFT, Key::O)],
+            passthrough: false,
+            submenu: None,
+            enabled: always,
+            run: |app, _| app.open_folder(),
+        },
+        Command {
+            id: "open_recent",
+            title: "Open Recent",
+            menu: Some(("File", 1)),
+            shortcuts: vec![],
+            passthrough: false,
+            submenu: Some(menu::render_open_recent),
+            enabled: always,
+            run: |app, _| app.show_open_recent(),
+        },
+        Command {
+            id: "reopen_last_file",
+            title: "Reopen Closed Tab",
+            menu: None,
+            shortcuts: vec![KeyboardShortcut::new(CTRL_SHIFT, Key::T)],
+            passthrough: false,
+            submenu: None,
+            enabled: |app| !app.closed_tabs.is_empty(),
+            run: |app, _| app.reopen_closed_tab(),
+        },
+        Command {
+            id: "clear_recent_files",
+            title: "Clear Recent Items",
+            menu: None,
+            shortcuts: vec![],
+            passthrough: false,
+            submenu: None,
+            enabled: |app| !app.recent.files.is_empty() || !app.recent.folders.is_empty(),
+            run: |app, _| app.recent.clear(),
+        },
+        Command {
+            id: "save",
+            title: "Save",
+            menu: Some(("File", 2)),
+            shortcuts: vec![KeyboardShortcut::new(CTRL, Key::S)],
+            passthrough: false,
+            submenu: None,
+            enabled: has_active_tab,
+            run: |app, _| app.save_active_file(),
+        },
+        Command {
+            id: "prompt_save_as",
+            title: "Save As...",
+            menu: Some(("File", 2)),
+            shortcuts: vec![KeyboardShortcut::new(CTRL_SHIFT, Key::S)],
+            passthrough: false,
+            submenu: None,
+            enabled: has_active_tab,
+            run: |app, _| app.save_as_active_file(),
+        },
+        Command {
+            id: "save_all",
+            title: "Save All",
+            menu: Some(("File", 2)),
+            shortcuts: vec![],
+            passthrough: false,
+            submenu: None,
+            enabled: has_dirty_files,
+            run: |app, _| app.save_all_files(),
+        },
+        Command {
+            id: "reopen_with_encoding",
+            title: "Reopen with Encoding",
+            menu: Some(("File", 2)),
+            shortcuts: vec![],
+            passthrough: false,
+            submenu: Some(menu::render_reopen_with_encoding),
+            enabled: can_reopen,
+            run: |app, _| app.show_reopen_with_encoding(),
+        },
+        Command {
+            id: "save_with_encoding",
+            title: "Save with Encoding",
+            menu: Some(("File", 2)),
+            shortcuts: vec![],
+            passthrough: false,
+            submenu: Some(menu::render_save_with_encoding),
+            enabled: has_file_tab,
+            run: |app, _| app.show_save_with_encoding(),
+        },
+        Command {
+            id: "exit",
+            title: "Exit",
+            menu: Some(("File", 3)),
+            shortcuts: vec![],
+            passthrough: false,
+            submenu: None,
+            enabled: always,
+            run: |_, ctx| ctx.send_viewport_cmd(egui::ViewportCommand::Close),
+        },
+        Command {
+            id: "undo",
+            title: "Undo",
+            menu: Some(("Edit", 0)),
+            shortcuts: vec![KeyboardShortcut::new(CTRL, Key::Z)],
+            passthrough: false,
+            submenu: None,
+            enabled: has_active_tab,
+            run: |app, ctx| app.undo(ctx),
+        },
+        Command {
+            id: "redo",
+            title: "Redo",
+            menu: Some(("Edit", 0)),
+            shortcuts: vec![
+                KeyboardShortcut::new(CTRL, Key::Y),
+                KeyboardShortcut::new(CTRL_SHIFT, Key::Z),
+            ],
+            passthrough: false,
+            submenu: None,
+            enabled: has_active_tab,
+            run: |app, ctx| app.redo(ctx),
+        },
+        Command {
+            id: "cut",
+            title: "Cut",
+            menu: Some(("Edit", 1)),
+            shortcuts: vec![KeyboardShortcut::new(CTRL, Key::X)],
+            passthrough: true,
+            submenu: None,
+            enabled: has_active_tab,
+            run: |_, ctx| send_to_editor(ctx, egui::Event::Cut),
+        },
+        Command {
+            id: "copy",
+            title: "Copy",
+            menu: Some(("Edit", 1)),
+            shortcuts: vec![KeyboardShortcut::new(CTRL, Key::C)],
+            passthrough: true,
+            submenu: None,
+            enabled: has_active_tab,
+            run: |_, ctx| send_to_editor(ctx, egui::Event::Copy),
+        },
+        Command {
+            id: "paste",
+            title: "Paste",
+            menu: Some(("Edit", 1)),
+            shortcuts: vec![KeyboardShortcut::new(CTRL, Key::V)],
+            passthrough: true,
+            submenu: None,
+            enabled: has_active_tab,
+            run: |_, ctx| {
+                // egui only reads the clipboard for the shortcut itself.
+                let text = arboard::Clipboard::new().and_then(|mut c| c.get_text());
+                if let Ok(text) = text {
+                    send_to_editor(ctx, egui::Event::Paste(text));
+                }
+            },
+        },
+        Command {
+            id: "select_all",
+            title: "Select All",
+            menu: Some(("Selection", 0)),
+            shortcuts: vec![KeyboardShortcut::new(CTRL, Key::A)],
+            passthrough: false,
+            submenu: None,
+            enabled: has_active_tab,
+            run: |app, ctx| app.change_selections(ctx, |s, b| s.select_all(b)),
+        },
+        Command {
+            id: "split_selection_into_lines",
+            title: "Split into Lines",
+            menu: Some(("Selection", 1)),
+            shortcuts: vec![KeyboardShortcut::new(CTRL_SHIFT, Key::L)],
+            passthrough: false,
+            submenu: None,
+            enabled: has_active_tab,
+            run: |app, ctx| app.change_selections(ctx, Selections::split_into_lines),
+        },
+        Command {
+            id: "select_lines_up",
+            title: "Add Previous Line",
+            menu: Some(("Selection", 1)),
+            shortcuts: vec![KeyboardShortcut::new(CTRL_ALT, Key::ArrowUp)],
+            passthrough: false,
+            submenu: None,
+            enabled: has_active_tab,
+            run: |app, ctx| {
+                let tab_size = app.settings.tab_size.max(1);
+                app.change_selections(ctx, |s, b| s.add_line(b, true, tab_size))
+            },
+        },
+        Command {
+            id: "select_lines_down",
+            title: "Add Next Line",
+            menu: Some(("Selection", 1)),
+            shortcuts: vec![KeyboardShortcut::new(CTRL_ALT, Key::ArrowDown)],
+            passthrough: false,
+            submenu: None,
+            enabled: has_active_tab,
+            run: |app, ctx| {
+                let tab_size = app.settings.tab_size.max(1);
+                app.change_selections(ctx, |s, b| s.add_line(b, false, tab_size))
+            },
+        },
+        Command {
+            // Escape is handled by the editor, which also uses it to close
+            // panels.
+            id: "single_selection",
+            title: "Single Selection",
+            menu: Some(("Selection", 1)),
+            shortcuts: vec![KeyboardShortcut::new(Modifiers::NONE, Key::Escape)],
+            passthrough: true,
+            submenu: None,
+            enabled: |app| app.selections.len() > 1,
+            run: |app, ctx| app.change_selections(ctx, |s, _| s.collapse()),
+        },
+        Command {
+            id: "show_panel_find",
+            title: "Find...",
+            menu: Some(("Find", 0)),
+            shortcuts: vec![KeyboardShortcut::new(CTRL, Key::F)],
+            passthrough: false,
+            submenu: None,
+            enabled: always,
+            run: |app, _| app.show_find(false),
+        },
+        Command {
+            // Also works with the find panel closed, using the last query.
+            id: "find_next",
+            title: "Find Next",
+            menu: Some(("Find", 0)),
+            shortcuts: vec![KeyboardShortcut::new(Modifiers::NONE, Key::F3)],
+            passthrough: false,
+            submenu: None,
+            enabled: has_find_query,
+            run: |app, _| {
+                app.refresh_find();
+                app.find_next();
+            },
+        },
+        Command {
+            id: "find_prev",
+            title: "Find Previous",
+            menu: Some(("Find", 0)),
+            shortcuts: vec![KeyboardShortcut::new(Modifiers::SHIFT, Key::F3)],
+            passthrough: false,
+            submenu: None,
+            enabled: has_find_query,
+            run: |app, _| {
+                app.refresh_find();
+                app.find_prev();
+            },
+        },
+        Command {
+            id: "show_panel_replace",
+            title: "Replace...",
+            menu: Some(("Find", 0)),
+            shortcuts: vec![KeyboardShortcut::new(CTRL, Key::H)],
+            passthrough: false,
+            submenu: None,
+            enabled: always,
+            run: |app, _| app.show_find(true),
+        },
+        Command {
+            id: "show_panel_find_in_files",
+            title: "Find in Files...",
+            menu: Some(("Find", 0)),
+            shortcuts: vec![KeyboardShortcut::new(COMMAND_SHIFT, Key::F)],
+            passthrough: false,
+            submenu: None,
+            enabled: always,
+            run: |app, _| app.show_find_in_files(),
+        },
+        Command {
+            id: "next_result",
+            title: "Next Result",
+            menu: Some(("Find", 2)),
+            shortcuts: vec![KeyboardShortcut::new(Modifiers::NONE, Key::F4)],
+            passthrough: false,
+            submenu: None,
+            enabled: has_find_results,
+            run: |app, _| app.step_result(true),
+        },
+        Command {
+            id: "prev_result",
+            title: "Previous Result",
+            menu: Some(("Find", 2)),
+            shortcuts: vec![KeyboardShortcut::new(Modifiers::SHIFT, Key::F4)],
+            passthrough: false,
+            submenu: None,
+            enabled: has_find_results,
+            run: |app, _| app.step_result(false),
+        },
+        Command {
+            id: "replace_next",
+            title: "Replace Next",
+            menu: Some(("Find", 1)),
+            shortcuts: vec![KeyboardShortcut::new(CTRL_SHIFT, Key::H)],
+            passthrough: false,
+            submenu: None,
+            enabled: replace_visible,
+            run: |app, _| app.replace_next(),
+        },
+        Command {
+            id: "replace_all",
+            title: "Replace All",
+            menu: Some(("Find", 1)),
+            shortcuts: vec![KeyboardShortcut::new(CTRL_ALT, Key::Enter)],
+            passthrough: false,
+            submenu: None,
+            enabled: replace_visible,
+            run: |app, _| app.replace_all(),
+        },
+        Command {
+            id: "undo_replace_in_files",
+            title: "Undo Replace in Files",
+            menu: Some(("Find", 2)),
+            shortcuts: vec![],
+            passthrough: false,
+            submenu: None,
+            enabled: has_replace_undo,
+            run: |app, _| app.undo_replace_in_files(),
+        },
+        Command {
+            id: "slurp_find_string",
+            title: "Use Selection for Find",
+            menu: Some(("Find", 1)),
+            shortcuts: vec![KeyboardShortcut::new(CTRL, Key::E)],
+            passthrough: false,
+            submenu: None,
+            enabled: has_active_tab,
+            run: |app, _| app.use_selection_for_find(),
+        },
+        Command {
+            id: "find_under",
+            title: "Find Under",
+            menu: Some(("Find", 1)),
+            shortcuts: vec![KeyboardShortcut::new(CTRL, Key::F3)],
+            passthrough: false,
+            submenu: None,
+            enabled: has_active_tab,
+            run: |app, _| app.find_under(),
+        },
+        Command {
+            id: "find_under_expand",
+            title: "Quick Add Next",
+            menu: Some(("Find", 1)),
+            shortcuts: vec![KeyboardShortcut::new(CTRL, Key::D)],
+            passthrough: false,
+            submenu: None,
+            enabled: has_active_tab,
+            run: |app, ctx| app.change_selections(ctx, Selections::add_next_occurrence),
+        },
+        Command {
+            id: "find_all_under",
+            title: "Quick Find All",
+            menu: Some(("Find", 1)),
+            shortcuts: vec![KeyboardShortcut::new(Modifiers::ALT, Key::F3)],
+            passthrough: false,
+            submenu: None,
+            enabled: has_active_tab,
+            run: |app, ctx| app.change_selections(ctx, Selections::select_all_occurrences),
+        },
+        Command {
+            id: "toggle_side_bar",
+            title: "Toggle Side Bar",
+            menu: Some(("View", 0)),
+            shortcuts: vec![],
+            passthrough: false,
+            submenu: None,
+            enabled: always,
+            run: |app, _| app.sidebar_visible = !app.sidebar_visible,
+        },
+        Command {
+            id: "set_syntax",
+            title: "Syntax",
+            menu: Some(("View", 0)),
+            shortcuts: vec![],
+            passthrough: false,
+            submenu: Some(menu::render_syntax_menu),
+            enabled: has_file_tab,
+            run: |app, _| app.show_syntax_picker(),
+        },
+        Command {
+            id: "show_overlay_goto",
+            title: "Goto Anything...",
+            menu: Some(("Goto", 0)),
+            shortcuts: vec![KeyboardShortcut::new(CTRL, Key::P)],
+            passthrough: false,
+            submenu: None,
+            enabled: always,
+            run: |app, ctx| app.show_goto_anything(ctx),
+        },
+        Command {
+            id: "show_overlay_command_palette",
+            title: "Command Palette...",
+            menu: Some(("Tools", 0)),
+            shortcuts: vec![KeyboardShortcut::new(CTRL_SHIFT, Key::P)],
+            passthrough: false,
+            submenu: None,
+            enabled: always,
+            run: |app, _| app.show_command_palette(),
+        },
+        Command {
+            id: "prompt_open_project",
+            title: "Open Project...",
+            menu: Some(("Project", 0)),
+            shortcuts: vec![],
+            passthrough: false,
+            submenu: None,
+            enabled: always,
+            run: |app, _| app.open_project(),
+        },
+        Command {
+            id: "edit_settings",
+            title: "Settings",
+            menu: Some(("Preferences", 0)),
+            shortcuts: vec![],
+            passthrough: false,
+            submenu: None,
+            enabled: always,
+            run: |app, _| app.edit_settings(),
+        },
+        Command {
+            id: "select_color_scheme",
+            title: "Select Color Scheme...",
+            menu: Some(("Preferences", 1)),
+            shortcuts: vec![],
+            passthrough: false,
+            submenu: None,
+            enabled: always,
+            run: |app, _| app.show_theme_picker(),
+        },
+        Command {
+            id: "toggle_light_dark",
+            title: "Toggle Light/Dark Color Scheme",
+            menu: Some(("Preferences", 1)),
+            shortcuts: vec![],
+            passthrough: false,
+            submenu: None,
+            enabled: always,
+            run: |app, _| app.toggle_light_dark(),
+        },
+        Command {
+            id: "show_about_window",
+            title: "About SuRuC",
+            menu: Some(("Help", 0)),
+            shortcuts: vec![],
+            passthrough: false,
+            submenu: None,
+            enabled: always,
+            run: |app, _| app.about_visible = true,
+        },
+    ]
+});
+
+pub fn find(id: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|c| c.id == id)
+}
+
+pub fn execute(app: &mut SublimeRustApp, ctx: &egui::Context, command: &Command) {
+    if (command.enabled)(app) {
+        (command.run)(app, ctx);
+    }
+}
+
+// Runs the command bound to any shortcut pressed this frame. Bindings with
+// more modifiers are tried first since egui ignores extra Shift when matching.
+pub fn handle_shortcuts(app: &mut SublimeRustApp, ctx: &egui::Context) {
+    static BINDINGS: Lazy<Vec<(KeyboardShortcut, &'static Command)>> = Lazy::new(|| {
+        let mut bindings: Vec<_> = COMMANDS
+            .iter()
+            .filter(|c| !c.passthrough)
+            .flat_map(|c| c.shortcuts.iter().map(move |s| (*s, c)))
+            .collect();
+        bindings.sort_by_key(|(s, _)| {
+            let m = s.modifiers;
+            std::cmp::Reverse(m.ctrl as u8 + m.command as u8 + m.shift as u8 + m.alt as u8)
+        });
+        bindings
+    });
+
+    for (shortcut, command) in BINDINGS.iter() {
+        if (command.enabled)(app) && ctx.input_mut(|i| i.consume_shortcut(shortcut)) {
+            (command.run)(app, ctx);
+        }
+    }
+}
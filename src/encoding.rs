@@ -0,0 +1,319 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+// Files with a NUL byte this early on are taken to be binary, like grep
+// does, unless they are UTF-16.
+const BINARY_CHECK_LEN: usize = 8 * 1024;
+
+// How the text of a file is stored on disk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextEncoding {
+    pub encoding: &'static Encoding,
+    // Written back at the start of the file, as it was found.
+    pub bom: bool,
+}
+
+impl Default for TextEncoding {
+    fn default() -> Self {
+        Self {
+            encoding: UTF_8,
+            bom: false,
+        }
+    }
+}
+
+impl TextEncoding {
+    pub fn new(encoding: &'static Encoding, bom: bool) -> Self {
+        Self { encoding, bom }
+    }
+
+    pub fn name(&self) -> String {
+        let name = label(self.encoding);
+        if self.bom {
+            format!("{} with BOM", name)
+        } else {
+            name.to_string()
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let (name, bom) = match name.strip_suffix(" with BOM") {
+            Some(name) => (name, true),
+            None => (name, false),
+        };
+        let encoding = CHOICES
+            .iter()
+            .map(|choice| choice())
+            .find(|encoding| label(encoding) == name)
+            .or_else(|| Encoding::for_label(name.as_bytes()))?;
+        Some(Self::new(encoding, bom))
+    }
+}
+
+// Offered by Reopen with Encoding and Save with Encoding, in menu order.
+pub const CHOICES: [fn() -> &'static Encoding; 24] = [
+    || UTF_8,
+    || UTF_16LE,
+    || UTF_16BE,
+    || encoding_rs::WINDOWS_1252,
+    || encoding_rs::ISO_8859_15,
+    || encoding_rs::WINDOWS_1250,
+    || encoding_rs::ISO_8859_2,
+    || encoding_rs::WINDOWS_1251,
+    || encoding_rs::KOI8_R,
+    || encoding_rs::KOI8_U,
+    || encoding_rs::IBM866,
+    || encoding_rs::WINDOWS_1253,
+    || encoding_rs::WINDOWS_1254,
+    || encoding_rs::WINDOWS_1255,
+    || encoding_rs::WINDOWS_1256,
+    || encoding_rs::WINDOWS_1257,
+    || encoding_rs::WINDOWS_1258,
+    || encoding_rs::WINDOWS_874,
+    || encoding_rs::SHIFT_JIS,
+    || encoding_rs::EUC_JP,
+    || encoding_rs::ISO_2022_JP,
+    || encoding_rs::GBK,
+    || encoding_rs::BIG5,
+    || encoding_rs::EUC_KR,
+];
+
+// What Save with Encoding offers: the choices, and the Unicode ones again
+// with a BOM.
+pub fn save_choices() -> Vec<TextEncoding> {
+    CHOICES
+        .iter()
+        .flat_map(|choice| {
+            let encoding = choice();
+            let boms: &[bool] = if encoding.name().starts_with("UTF") {
+                &[false, true]
+            } else {
+                &[false]
+            };
+            boms.iter()
+                .map(move |&bom| TextEncoding::new(encoding, bom))
+        })
+        .collect()
+}
+
+// Names as Sublime shows them; anything else goes by its WHATWG name.
+fn label(encoding: &'static Encoding) -> &'static str {
+    match encoding.name() {
+        "UTF-16LE" => "UTF-16 LE",
+        "UTF-16BE" => "UTF-16 BE",
+        "windows-1252" => "Western (Windows 1252)",
+        "ISO-8859-15" => "Western (ISO 8859-15)",
+        "windows-1250" => "Central European (Windows 1250)",
+        "ISO-8859-2" => "Central European (ISO 8859-2)",
+        "windows-1251" => "Cyrillic (Windows 1251)",
+        "KOI8-R" => "Cyrillic (KOI8-R)",
+        "KOI8-U" => "Cyrillic (KOI8-U)",
+        "IBM866" => "Cyrillic (Windows 866)",
+        "windows-1253" => "Greek (Windows 1253)",
+        "windows-1254" => "Turkish (Windows 1254)",
+        "windows-1255" => "Hebrew (Windows 1255)",
+        "windows-1256" => "Arabic (Windows 1256)",
+        "windows-1257" => "Baltic (Windows 1257)",
+        "windows-1258" => "Vietnamese (Windows 1258)",
+        "windows-874" => "Thai (Windows 874)",
+        "Shift_JIS" => "Japanese (Shift JIS)",
+        "EUC-JP" => "Japanese (EUC-JP)",
+        "ISO-2022-JP" => "Japanese (ISO-2022-JP)",
+        "GBK" => "Chinese Simplified (GBK)",
+        "Big5" => "Chinese Traditional (Big5)",
+        "EUC-KR" => "Korean (EUC-KR)",
+        name => name,
+    }
+}
+
+// Works out the encoding of a file: its BOM if it has one, then UTF-16 if
+// every other byte is NUL (which is also valid UTF-8), UTF-8 if the bytes
+// are valid, and failing all that chardetng's guess.
+//
+// Binary files, and files the guess cannot read without turning bytes into
+// U+FFFD, are refused: saving that text would not give the file back.
+pub fn decode(bytes: &[u8]) -> Result<(String, TextEncoding), String> {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+        return decode_exactly(&bytes[bom_len..], TextEncoding::new(encoding, true));
+    }
+    let utf16 = utf16_without_bom(bytes);
+    if utf16.is_none() {
+        if let Ok(text) = std::str::from_utf8(bytes) {
+            return Ok((text.to_string(), TextEncoding::default()));
+        }
+        if is_binary(bytes) {
+            return Err("Binary file".to_string());
+        }
+    }
+    let encoding = utf16.unwrap_or_else(|| {
+        let mut detector = EncodingDetector::new();
+        detector.feed(bytes, true);
+        detector.guess(None, true)
+    });
+    decode_exactly(bytes, TextEncoding::new(encoding, false))
+}
+
+// Reads the bytes as the user says they are, dropping a BOM that agrees.
+pub fn decode_as(
+    bytes: &[u8],
+    encoding: &'static Encoding,
+) -> Result<(String, TextEncoding), String> {
+    let bom_len = match Encoding::for_bom(bytes) {
+        Some((found, len)) if found == encoding => len,
+        _ => 0,
+    };
+    decode_exactly(&bytes[bom_len..], TextEncoding::new(encoding, bom_len > 0))
+}
+
+fn decode_exactly(bytes: &[u8], encoding: TextEncoding) -> Result<(String, TextEncoding), String> {
+    let (text, had_errors) = encoding.encoding.decode_without_bom_handling(bytes);
+    if had_errors {
+        return Err(format!("Not valid {}", label(encoding.encoding)));
+    }
+    Ok((text.into_owned(), encoding))
+}
+
+// Whether the start of a file looks like binary data rather than text.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    let head = &bytes[..bytes.len().min(BINARY_CHECK_LEN)];
+    head.contains(&0) && !is_utf16(head)
+}
+
+// Whether the bytes are UTF-16, whose NULs do not make it binary.
+fn is_utf16(bytes: &[u8]) -> bool {
+    let bom = Encoding::for_bom(bytes).map(|(encoding, _)| encoding);
+    matches!(bom, Some(e) if e == UTF_16LE || e == UTF_16BE) || utf16_without_bom(bytes).is_some()
+}
+
+// Mostly ASCII text in UTF-16 has a NUL in every other byte, which text in
+// any other encoding does not.
+fn utf16_without_bom(bytes: &[u8]) -> Option<&'static Encoding> {
+    if !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let sample = &bytes[..bytes.len().min(4096)];
+    let nuls = |offset: usize| {
+        sample
+            .iter()
+            .skip(offset)
+            .step_by(2)
+            .filter(|&&b| b == 0)
+            .count()
+    };
+    let (even, odd) = (nuls(0), nuls(1));
+    let half = sample.len() / 4;
+    if odd > half && even == 0 {
+        Some(UTF_16LE)
+    } else if even > half && odd == 0 {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+// The bytes to write for `text`, or why it cannot be written that way.
+pub fn encode(text: &str, encoding: TextEncoding) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    // encoding_rs only decodes UTF-16, so it is done here.
+    if encoding.encoding == UTF_16LE || encoding.encoding == UTF_16BE {
+        let little = encoding.encoding == UTF_16LE;
+        let units = encoding.bom.then_some(0xfeff).into_iter();
+        for unit in units.chain(text.encode_utf16()) {
+            bytes.extend(if little {
+                unit.to_le_bytes()
+            } else {
+                unit.to_be_bytes()
+            });
+        }
+        return Ok(bytes);
+    }
+    if encoding.bom && encoding.encoding == UTF_8 {
+        bytes.extend_from_slice(b"\xef\xbb\xbf");
+    }
+    let (encoded, _, unmappable) = encoding.encoding.encode(text);
+    if unmappable {
+        return Err(format!(
+            "{} cannot represent some of the text",
+            encoding.name()
+        ));
+    }
+    bytes.extend_from_slice(&encoded);
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Written as `encoding`, then read back without being told how.
+    fn round_trip(text: &str, encoding: TextEncoding) -> (String, TextEncoding) {
+        decode(&encode(text, encoding).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn unicode_round_trips_with_and_without_bom() {
+        let text = "héllo wörld\nline two\n";
+        for encoding in [UTF_8, UTF_16LE, UTF_16BE] {
+            for bom in [false, true] {
+                let encoding = TextEncoding::new(encoding, bom);
+                assert_eq!(round_trip(text, encoding), (text.to_string(), encoding));
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_utf8_falls_back_to_a_guess() {
+        let bytes = encode(
+            "café crème brûlée",
+            TextEncoding::new(encoding_rs::WINDOWS_1252, false),
+        );
+        let (text, encoding) = decode(&bytes.unwrap()).unwrap();
+        assert_eq!(text, "café crème brûlée");
+        assert_eq!(encoding.encoding, encoding_rs::WINDOWS_1252);
+    }
+
+    #[test]
+    fn decode_as_drops_only_a_matching_bom() {
+        let bytes = b"\xef\xbb\xbfabc";
+        assert_eq!(
+            decode_as(bytes, UTF_8),
+            Ok(("abc".to_string(), TextEncoding::new(UTF_8, true)))
+        );
+        let (text, encoding) = decode_as(bytes, encoding_rs::WINDOWS_1252).unwrap();
+        assert_eq!(text, "ï»¿abc");
+        assert!(!encoding.bom);
+    }
+
+    #[test]
+    fn binary_and_undecodable_files_are_refused() {
+        assert_eq!(
+            decode(b"\x7fELF\x02\x01\x00\x00\xff\xfe"),
+            Err("Binary file".to_string())
+        );
+        // NULs are fine in UTF-16, and in text that is valid UTF-8 anyway.
+        assert!(decode(&encode("ab", TextEncoding::new(UTF_16LE, false)).unwrap()).is_ok());
+        assert!(decode(b"a\x00b").is_ok());
+        assert_eq!(
+            decode_as(b"\xff\xfe\xfd", UTF_8),
+            Err("Not valid UTF-8".to_string())
+        );
+    }
+
+    #[test]
+    fn unrepresentable_text_is_refused() {
+        let latin1 = TextEncoding::new(encoding_rs::WINDOWS_1252, false);
+        assert!(encode("snowman ☃", latin1).is_err());
+        assert_eq!(encode("plain", latin1).unwrap(), b"plain");
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for encoding in save_choices() {
+            assert_eq!(TextEncoding::from_name(&encoding.name()), Some(encoding));
+        }
+        assert_eq!(
+            TextEncoding::from_name("latin1"),
+            Some(TextEncoding::new(encoding_rs::WINDOWS_1252, false))
+        );
+    }
+}
@@ -0,0 +1,422 @@
+use crate::encoding;
+use crate::search::{self, FindOptions};
+use eframe::egui;
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::{WalkBuilder, WalkState};
+use regex::Regex;
+use ropey::Rope;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+pub const RESULTS_TAB: &str = "find://Find Results";
+
+// The Where field: comma-separated folders, `*.rs` to only search matching
+// files, `-*/target/*` to leave some out, and `<open folders>`,
+// `<open files>` or `<unsaved>`.
+#[derive(Debug, Default, PartialEq)]
+pub struct Where {
+    pub folders: Vec<PathBuf>,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub open_folders: bool,
+    pub open_files: bool,
+    pub unsaved: bool,
+}
+
+impl Where {
+    pub fn parse(text: &str) -> Self {
+        let mut parsed = Self::default();
+        for part in text.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            match part {
+                "<open folders>" | "<project>" => parsed.open_folders = true,
+                "<open files>" => parsed.open_files = true,
+                "<unsaved>" => parsed.unsaved = true,
+                _ => {
+                    if let Some(pattern) = part.strip_prefix('-') {
+                        parsed.exclude.push(pattern.trim().to_string());
+                    } else if part.contains(['*', '?', '[']) {
+                        parsed.include.push(part.to_string());
+                    } else {
+                        parsed.folders.push(expand_home(part));
+                    }
+                }
+            }
+        }
+        // Only filters, or nothing at all, means the open folders.
+        if parsed.folders.is_empty() && !parsed.open_files && !parsed.unsaved {
+            parsed.open_folders = true;
+        }
+        parsed
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~/").zip(dirs::home_dir()) {
+        Some((rest, home)) => home.join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+// What a search covers, with the Where field resolved against the window.
+pub struct SearchScope {
+    pub roots: Vec<PathBuf>,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    // Searched as they are in the editor, unsaved changes included.
+    pub buffers: Vec<(PathBuf, Rope)>,
+    pub respect_gitignore: bool,
+}
+
+impl SearchScope {
+    // The include and exclude patterns as `ignore` overrides for a root.
+    fn overrides(&self, root: &Path) -> Result<Override, String> {
+        let mut builder = OverrideBuilder::new(root);
+        let patterns = self
+            .include
+            .iter()
+            .map(|p| glob(p))
+            .chain(self.exclude.iter().map(|p| format!("!{}", glob(p))));
+        for pattern in patterns {
+            builder
+                .add(&pattern)
+                .map_err(|e| format!("{}: {}", pattern, e))?;
+        }
+        builder.build().map_err(|e| e.to_string())
+    }
+}
+
+// Sublime's `*` also matches across folders, so a `*` standing for a whole
+// path component becomes `**`: `*/target/*` then skips `target` anywhere.
+fn glob(pattern: &str) -> String {
+    if !pattern.contains('/') {
+        return pattern.to_string();
+    }
+    pattern
+        .split('/')
+        .map(|part| if part == "*" { "**" } else { part })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// A line shown in the results. Lines without matches are context around
+// the ones with.
+pub struct ResultLine {
+    // Zero-based.
+    pub line: usize,
+    pub text: String,
+    // Byte ranges within `text`.
+    pub matches: Vec<Range<usize>>,
+}
+
+impl ResultLine {
+    // Zero-based char column and length of a match, for selecting it in
+    // the file.
+    pub fn match_columns(&self, idx: usize) -> Option<(usize, usize)> {
+        let range = self.matches.get(idx)?;
+        let column = self.text[..range.start].chars().count();
+        Some((column, self.text[range.clone()].chars().count()))
+    }
+}
+
+pub struct FileMatches {
+    pub path: PathBuf,
+    pub lines: Vec<ResultLine>,
+    // A match spanning lines has a range on each.
+    pub matches: usize,
+}
+
+// A Find in Files search running on the walker's thread pool. Matches come
+// in file by file as they are found; dropping the job stops it.
+pub struct SearchJob {
+    pub query: String,
+    pub options: FindOptions,
+    // Lines shown around each match.
+    pub context: usize,
+    results: Receiver<FileMatches>,
+    cancelled: Arc<AtomicBool>,
+    searched: Arc<AtomicUsize>,
+    pub files: usize,
+    pub matches: usize,
+    pub done: bool,
+}
+
+impl SearchJob {
+    pub fn start(
+        ctx: &egui::Context,
+        query: &str,
+        options: FindOptions,
+        context: usize,
+        scope: SearchScope,
+    ) -> Result<Self, String> {
+        let regex = search::build_regex(query, &options)
+            .map_err(|e| search::short_error(&e).to_string())?;
+        let (sender, results) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let searched = Arc::new(AtomicUsize::new(0));
+
+        let mut walkers = Vec::new();
+        for root in &scope.roots {
+            walkers.push(
+                WalkBuilder::new(root)
+                    .git_ignore(scope.respect_gitignore)
+                    .overrides(scope.overrides(root)?)
+                    .build_parallel(),
+            );
+        }
+        // Open files are matched against the patterns by their full path.
+        let filter = scope.overrides(Path::new("/"))?;
+        let buffers: Vec<_> = scope
+            .buffers
+            .into_iter()
+            .filter(|(path, _)| !filter.matched(path, false).is_ignore())
+            .collect();
+        let in_memory: HashSet<PathBuf> = buffers.iter().map(|(path, _)| path.clone()).collect();
+
+        let (ctx, stop, count) = (ctx.clone(), cancelled.clone(), searched.clone());
+        thread::spawn(move || {
+            for (path, text) in buffers {
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                count.fetch_add(1, Ordering::Relaxed);
+                if let Some(found) = search_text(path, &text.to_string(), &regex, context) {
+                    let _ = sender.send(found);
+                    ctx.request_repaint();
+                }
+            }
+            for walker in walkers {
+                walker.run(|| {
+                    let (sender, ctx, stop, count) =
+                        (sender.clone(), ctx.clone(), stop.clone(), count.clone());
+                    let (regex, in_memory) = (regex.clone(), &in_memory);
+                    Box::new(move |entry| {
+                        if stop.load(Ordering::Relaxed) {
+                            return WalkState::Quit;
+                        }
+                        let Ok(entry) = entry else {
+                            return WalkState::Continue;
+                        };
+                        if !entry.file_type().is_some_and(|ft| ft.is_file())
+                            || in_memory.contains(entry.path())
+                        {
+                            return WalkState::Continue;
+                        }
+                        count.fetch_add(1, Ordering::Relaxed);
+                        if let Some(found) = search_file(entry.into_path(), &regex, context) {
+                            if sender.send(found).is_err() {
+                                return WalkState::Quit;
+                            }
+                            ctx.request_repaint();
+                        }
+                        WalkState::Continue
+                    })
+                });
+            }
+            // The last sender goes away here, which tells `poll` it is over.
+            drop(sender);
+            ctx.request_repaint();
+        });
+
+        Ok(Self {
+            query: query.to_string(),
+            options,
+            context,
+            results,
+            cancelled,
+            searched,
+            files: 0,
+            matches: 0,
+            done: false,
+        })
+    }
+
+    // What arrived since the last call.
+    pub fn poll(&mut self) -> Vec<FileMatches> {
+        let mut found = Vec::new();
+        loop {
+            match self.results.try_recv() {
+                Ok(file) => {
+                    self.files += 1;
+                    self.matches += file.matches;
+                    found.push(file);
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.done = true;
+                    break;
+                }
+            }
+        }
+        found
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    // Files looked at so far, matching or not.
+    pub fn searched(&self) -> usize {
+        self.searched.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for SearchJob {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+fn search_file(path: PathBuf, regex: &Regex, context: usize) -> Option<FileMatches> {
+    let bytes = fs::read(&path).ok()?;
+    if encoding::is_binary(&bytes) {
+        return None;
+    }
+    let (text, _) = encoding::decode(&bytes).ok()?;
+    search_text(path, &text, regex, context)
+}
+
+// Runs over the whole text so a pattern can span lines, then splits each
+// match into its part on every line it touches.
+fn search_text(path: PathBuf, content: &str, regex: &Regex, context: usize) -> Option<FileMatches> {
+    let mut found = regex
+        .find_iter(content)
+        .filter(|m| !m.is_empty())
+        .peekable();
+    found.peek()?;
+
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let line_of = |byte: usize| line_starts.partition_point(|&start| start <= byte) - 1;
+    let line_text = |line: usize| {
+        let start = line_starts[line];
+        let end = line_starts
+            .get(line + 1)
+            .map_or(content.len(), |next| next - 1);
+        content[start..end].trim_end_matches('\r')
+    };
+
+    let mut matched: BTreeMap<usize, Vec<Range<usize>>> = BTreeMap::new();
+    let mut matches = 0;
+    for m in found {
+        matches += 1;
+        let (first, last) = (line_of(m.start()), line_of(m.end() - 1));
+        for (line, &start) in line_starts.iter().enumerate().take(last + 1).skip(first) {
+            let len = line_text(line).len();
+            // Clamped to the text, which leaves out the line break and any `\r`.
+            let range = m.start().max(start).min(start + len) - start..(m.end() - start).min(len);
+            // A match of just the line break still marks its line.
+            if !range.is_empty() || line == first {
+                matched
+                    .entry(line)
+                    .or_default()
+                    .push(range.start..range.end.max(range.start));
+            }
+        }
+    }
+
+    // Not counting the empty line after a final line break.
+    let last_line = line_starts.len() - 1 - content.ends_with('\n') as usize;
+    let mut shown: BTreeMap<usize, Vec<Range<usize>>> = BTreeMap::new();
+    for (&line, ranges) in &matched {
+        for around in line.saturating_sub(context)..=(line + context).min(last_line) {
+            shown.entry(around).or_default();
+        }
+        shown.insert(line, ranges.clone());
+    }
+    let lines = shown
+        .into_iter()
+        .map(|(line, matches)| ResultLine {
+            line,
+            text: line_text(line).to_string(),
+            matches,
+        })
+        .collect();
+    Some(FileMatches {
+        path,
+        lines,
+        matches,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, content: &str) -> FileMatches {
+        let options = FindOptions {
+            regex: true,
+            ..FindOptions::default()
+        };
+        let regex = search::build_regex(pattern, &options).unwrap();
+        search_text(PathBuf::from("test"), content, &regex, 0).unwrap()
+    }
+
+    #[test]
+    fn match_on_crlf_line_break_stays_within_line() {
+        let found = find("\\n", "abc\r\ndef\r\n");
+        assert_eq!(found.lines[0].text, "abc");
+        assert_eq!(found.lines[0].matches, vec![3..3]);
+        assert_eq!(found.lines[0].match_columns(0), Some((3, 0)));
+    }
+
+    #[test]
+    fn multiline_match_is_split_per_line() {
+        let found = find("c\\r\\nd", "abc\r\ndef\r\n");
+        assert_eq!(found.matches, 1);
+        assert_eq!(found.lines[0].matches, vec![2..3]);
+        assert_eq!(found.lines[1].matches, vec![0..1]);
+    }
+
+    #[test]
+    fn where_splits_folders_filters_and_exclusions() {
+        let parsed = Where::parse(" src , *.rs,-*/target/*, - *.lock,,");
+        assert_eq!(
+            parsed,
+            Where {
+                folders: vec![PathBuf::from("src")],
+                include: vec!["*.rs".to_string()],
+                exclude: vec!["*/target/*".to_string(), "*.lock".to_string()],
+                ..Where::default()
+            }
+        );
+    }
+
+    #[test]
+    fn where_defaults_to_the_open_folders() {
+        assert!(Where::parse("").open_folders);
+        assert!(Where::parse("*.rs, -*.md").open_folders);
+        assert!(Where::parse("<project>").open_folders);
+
+        let open_files = Where::parse("<open files>");
+        assert!(open_files.open_files && !open_files.open_folders);
+        let unsaved = Where::parse("<unsaved>, *.rs");
+        assert!(unsaved.unsaved && !unsaved.open_folders);
+    }
+
+    #[test]
+    fn where_expands_the_home_folder() {
+        let Some(home) = dirs::home_dir() else {
+            return;
+        };
+        assert_eq!(Where::parse("~/code").folders, [home.join("code")]);
+        assert_eq!(Where::parse("a~/b").folders, [PathBuf::from("a~/b")]);
+    }
+
+    #[test]
+    fn whole_component_stars_match_any_depth() {
+        assert_eq!(glob("*.rs"), "*.rs");
+        assert_eq!(glob("*/target/*"), "**/target/**");
+        assert_eq!(glob("src/*.rs"), "src/*.rs");
+    }
+}
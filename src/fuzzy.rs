@@ -0,0 +1,151 @@
+// Sublime-style fuzzy matching: every pattern char must appear in order, and
+// the best alignment is picked by rewarding runs of consecutive chars and
+// matches at word boundaries while penalising gaps.
+
+const SCORE_MATCH: i64 = 16;
+const BONUS_CONSECUTIVE: i64 = 24;
+const BONUS_WORD_START: i64 = 30;
+const BONUS_FIRST_CHAR: i64 = 20;
+const BONUS_EXACT_CASE: i64 = 1;
+const PENALTY_GAP: i64 = 1;
+const PENALTY_LEADING: i64 = 1;
+const MAX_LEADING_PENALTY: i64 = 10;
+const NONE: i64 = i64::MIN / 4;
+
+fn is_separator(c: char) -> bool {
+    matches!(c, '/' | '\\' | '_' | '-' | '.' | ' ' | ':')
+}
+
+fn boundary_bonus(chars: &[char], j: usize) -> i64 {
+    if j == 0 {
+        return BONUS_FIRST_CHAR;
+    }
+    let (prev, cur) = (chars[j - 1], chars[j]);
+    if is_separator(prev) || (prev.is_lowercase() && cur.is_uppercase()) {
+        BONUS_WORD_START
+    } else {
+        0
+    }
+}
+
+fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+// Returns the score and the matched char indices in `candidate`, or `None`
+// when `pattern` is not a subsequence of it. Whitespace in the pattern is
+// ignored and matching is case-insensitive.
+pub fn fuzzy_match(pattern: &str, candidate: &str) -> Option<(i64, Vec<usize>)> {
+    let pat: Vec<char> = pattern.chars().filter(|c| !c.is_whitespace()).collect();
+    if pat.is_empty() {
+        return Some((0, Vec::new()));
+    }
+    let cand: Vec<char> = candidate.chars().collect();
+    let (n, m) = (pat.len(), cand.len());
+    if n > m {
+        return None;
+    }
+    let folded: Vec<char> = cand.iter().copied().map(fold).collect();
+
+    let mut score = vec![NONE; n * m];
+    let mut from = vec![usize::MAX; n * m];
+
+    for i in 0..n {
+        let want = fold(pat[i]);
+        // Best score[i - 1][k] - gap for k <= j - 2, maintained as j advances.
+        let mut running = NONE;
+        let mut running_from = usize::MAX;
+        for j in 0..m {
+            if i > 0 && j >= 2 {
+                running -= PENALTY_GAP;
+                let prev = score[(i - 1) * m + j - 2] - PENALTY_GAP;
+                if prev > running {
+                    running = prev;
+                    running_from = j - 2;
+                }
+            }
+            if folded[j] != want {
+                continue;
+            }
+            let mut base = SCORE_MATCH + boundary_bonus(&cand, j);
+            if cand[j] == pat[i] {
+                base += BONUS_EXACT_CASE;
+            }
+
+            if i == 0 {
+                let leading = (j as i64 * PENALTY_LEADING).min(MAX_LEADING_PENALTY);
+                score[j] = base - leading;
+                continue;
+            }
+
+            let consecutive = if j > 0 && score[(i - 1) * m + j - 1] > NONE / 2 {
+                score[(i - 1) * m + j - 1] + BONUS_CONSECUTIVE
+            } else {
+                NONE
+            };
+            let (best, prev_j) = if consecutive >= running {
+                (consecutive, j.wrapping_sub(1))
+            } else {
+                (running, running_from)
+            };
+            if best > NONE / 2 {
+                score[i * m + j] = best + base;
+                from[i * m + j] = prev_j;
+            }
+        }
+    }
+
+    let last = (n - 1) * m;
+    let (mut j, best) = (0..m)
+        .map(|j| (j, score[last + j]))
+        .max_by_key(|&(_, s)| s)
+        .filter(|&(_, s)| s > NONE / 2)?;
+
+    let mut positions = vec![0; n];
+    for i in (0..n).rev() {
+        positions[i] = j;
+        j = from[i * m + j];
+    }
+    Some((best, positions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(pattern: &str, candidate: &str) -> Option<Vec<usize>> {
+        fuzzy_match(pattern, candidate).map(|(_, positions)| positions)
+    }
+
+    fn score(pattern: &str, candidate: &str) -> i64 {
+        fuzzy_match(pattern, candidate).unwrap().0
+    }
+
+    #[test]
+    fn pattern_chars_must_appear_in_order() {
+        assert_eq!(positions("abc", "a_b_c"), Some(vec![0, 2, 4]));
+        assert_eq!(positions("cba", "a_b_c"), None);
+        assert_eq!(positions("abcd", "abc"), None);
+        assert_eq!(positions("", "anything"), Some(vec![]));
+    }
+
+    #[test]
+    fn case_and_whitespace_are_ignored() {
+        assert_eq!(positions("F B", "foo_bar"), Some(vec![0, 4]));
+        assert!(score("Foo", "Foo") > score("foo", "Foo"));
+    }
+
+    #[test]
+    fn word_starts_are_preferred() {
+        // The `b` of `bar`, not the one in `dubious`.
+        assert_eq!(positions("fb", "fdubious_bar"), Some(vec![0, 9]));
+        assert_eq!(positions("gs", "getString"), Some(vec![0, 3]));
+        assert!(score("ed", "src/editor.rs") > score("ed", "src/selected.rs"));
+    }
+
+    #[test]
+    fn consecutive_runs_beat_scattered_matches() {
+        assert_eq!(positions("tab", "xtxaxb tab"), Some(vec![7, 8, 9]));
+        assert!(score("app", "src/app.rs") > score("app", "src/axpxp.rs"));
+    }
+}
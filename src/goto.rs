@@ -0,0 +1,312 @@
+use crate::fuzzy::fuzzy_match;
+use crate::syntax::SYNTAX_SET;
+use eframe::egui;
+use ignore::WalkBuilder;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use syntect::parsing::{ParseState, ScopeStack, SyntaxReference};
+use syntect::util::LinesWithEndings;
+
+// Matches inside the file name count for more than matches in the directory.
+const FILE_NAME_BONUS: i64 = 40;
+pub const MAX_RESULTS: usize = 100;
+// Files sent over from the indexing thread at a time.
+const INDEX_BATCH: usize = 1000;
+
+// A Goto Anything query split into its parts: `file`, `file:line:col` or
+// `file@symbol`. An empty `file` means the active tab.
+#[derive(Debug, Default, PartialEq)]
+pub struct GotoQuery {
+    pub file: String,
+    pub line: Option<usize>,
+    pub col: Option<usize>,
+    pub symbol: Option<String>,
+}
+
+impl GotoQuery {
+    pub fn parse(query: &str) -> Self {
+        if let Some((file, symbol)) = query.split_once('@') {
+            return Self {
+                file: file.trim().to_string(),
+                symbol: Some(symbol.trim().to_string()),
+                ..Self::default()
+            };
+        }
+
+        // Peel up to two numeric `:N` suffixes off the end: line, then column.
+        let mut file = query;
+        let mut numbers = Vec::new();
+        while numbers.len() < 2 {
+            let Some((head, tail)) = file.rsplit_once(':') else {
+                break;
+            };
+            if let Ok(n) = tail.parse::<usize>() {
+                numbers.insert(0, n);
+            } else if !(tail.is_empty() && numbers.is_empty()) {
+                break;
+            }
+            file = head;
+        }
+        Self {
+            file: file.trim().to_string(),
+            line: numbers.first().copied(),
+            col: numbers.get(1).copied(),
+            symbol: None,
+        }
+    }
+}
+
+pub struct Symbol {
+    pub name: String,
+    pub line: usize,
+    pub col: usize,
+}
+
+// Files under the project, listed on a thread so a large tree does not
+// hold up the overlay. They arrive in batches as the walk goes on.
+pub struct FileIndex {
+    root: PathBuf,
+    files: Vec<PathBuf>,
+    batches: Receiver<Vec<PathBuf>>,
+    cancelled: Arc<AtomicBool>,
+    pub done: bool,
+    // The last ranking, with the pattern and file count it was made for.
+    ranked: Option<(String, usize, Vec<RankedFile>)>,
+}
+
+// A file, its path relative to the root and the matched chars in that.
+pub type RankedFile = (PathBuf, String, Vec<usize>);
+
+impl FileIndex {
+    pub fn start(ctx: &egui::Context, root: &Path, respect_gitignore: bool) -> Self {
+        let (sender, batches) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let walker = WalkBuilder::new(root).git_ignore(respect_gitignore).build();
+
+        let (ctx, stop) = (ctx.clone(), cancelled.clone());
+        thread::spawn(move || {
+            let mut batch = Vec::new();
+            for entry in walker.flatten() {
+                if stop.load(Ordering::Relaxed) {
+                    return;
+                }
+                if entry.file_type().is_some_and(|ft| ft.is_file()) {
+                    batch.push(entry.into_path());
+                }
+                if batch.len() == INDEX_BATCH {
+                    if sender.send(std::mem::take(&mut batch)).is_err() {
+                        return;
+                    }
+                    ctx.request_repaint();
+                }
+            }
+            let _ = sender.send(batch);
+            // The sender goes away here, which tells `poll` it is over.
+            drop(sender);
+            ctx.request_repaint();
+        });
+
+        Self {
+            root: root.to_path_buf(),
+            files: Vec::new(),
+            batches,
+            cancelled,
+            done: false,
+            ranked: None,
+        }
+    }
+
+    pub fn poll(&mut self) {
+        loop {
+            match self.batches.try_recv() {
+                Ok(batch) => self.files.extend(batch),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if !self.done {
+                        self.files.sort();
+                        self.ranked = None;
+                    }
+                    self.done = true;
+                    break;
+                }
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    // Ranks the files against `pattern`, only doing the work again once
+    // the pattern changes or more files have come in.
+    pub fn rank(&mut self, pattern: &str) -> &[RankedFile] {
+        let stale = self
+            .ranked
+            .as_ref()
+            .is_none_or(|(p, len, _)| p != pattern || *len != self.files.len());
+        if stale {
+            let ranked = rank_files(pattern, &self.files, &self.root);
+            self.ranked = Some((pattern.to_string(), self.files.len(), ranked));
+        }
+        self.ranked.as_ref().map_or(&[], |(_, _, ranked)| ranked)
+    }
+}
+
+impl Drop for FileIndex {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+// Ranks `files` against `pattern`, best first. Each result carries the
+// matched char positions within the path shown relative to `root`.
+fn rank_files(pattern: &str, files: &[PathBuf], root: &Path) -> Vec<RankedFile> {
+    let mut ranked: Vec<(i64, &PathBuf, String, Vec<usize>)> = files
+        .iter()
+        .filter_map(|path| {
+            let relative = path
+                .strip_prefix(root)
+                .unwrap_or(path)
+                .display()
+                .to_string();
+            let name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("")
+                .to_string();
+            let name_offset = relative.chars().count() - name.chars().count();
+
+            let by_name = fuzzy_match(pattern, &name).map(|(score, positions)| {
+                let positions = positions.iter().map(|p| p + name_offset).collect();
+                (score + FILE_NAME_BONUS, positions)
+            });
+            let by_path = fuzzy_match(pattern, &relative);
+            let (score, positions) = match (by_name, by_path) {
+                (Some(a), Some(b)) => {
+                    if a.0 >= b.0 {
+                        a
+                    } else {
+                        b
+                    }
+                }
+                (a, b) => a.or(b)?,
+            };
+            Some((score, path, relative, positions))
+        })
+        .collect();
+
+    // Shorter paths win ties, like Sublime.
+    ranked.sort_by(|a, b| b.0.cmp(&a.0).then(a.2.len().cmp(&b.2.len())));
+    ranked.truncate(MAX_RESULTS);
+    ranked
+        .into_iter()
+        .map(|(_, path, relative, positions)| (path.clone(), relative, positions))
+        .collect()
+}
+
+// Collects definitions the grammar marks as `entity.name`, which is what
+// Sublime's own symbol list is built from.
+pub fn symbols(text: &str, syntax: &SyntaxReference) -> Vec<Symbol> {
+    let selector = ScopeStack::from_str("entity.name").unwrap();
+
+    let mut state = ParseState::new(syntax);
+    let mut stack = ScopeStack::new();
+    let mut symbols = Vec::new();
+
+    for (line_idx, line) in LinesWithEndings::from(text).enumerate() {
+        let Ok(ops) = state.parse_line(line, &SYNTAX_SET) else {
+            break;
+        };
+        let mut last = 0;
+        let mut current: Option<Symbol> = None;
+        let mut flush = |current: &mut Option<Symbol>| {
+            if let Some(mut symbol) = current.take() {
+                symbol.name = symbol.name.trim().to_string();
+                if !symbol.name.is_empty() {
+                    symbols.push(symbol);
+                }
+            }
+        };
+
+        let ends = ops.iter().map(|(offset, _)| *offset).chain([line.len()]);
+        let mut ops = ops.iter();
+        for end in ends {
+            if end > last {
+                if selector.does_match(stack.as_slice()).is_some() {
+                    let token = &line[last..end];
+                    match current.as_mut() {
+                        Some(symbol) => symbol.name.push_str(token),
+                        None => {
+                            current = Some(Symbol {
+                                name: token.to_string(),
+                                line: line_idx,
+                                col: line[..last].chars().count(),
+                            })
+                        }
+                    }
+                } else {
+                    flush(&mut current);
+                }
+                last = end;
+            }
+            if let Some((_, op)) = ops.next() {
+                let _ = stack.apply(op);
+            }
+        }
+        flush(&mut current);
+    }
+    symbols
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(file: &str, line: Option<usize>, col: Option<usize>) -> GotoQuery {
+        GotoQuery {
+            file: file.to_string(),
+            line,
+            col,
+            symbol: None,
+        }
+    }
+
+    #[test]
+    fn line_and_column_suffixes() {
+        assert_eq!(GotoQuery::parse("main.rs"), query("main.rs", None, None));
+        assert_eq!(
+            GotoQuery::parse("main.rs:12"),
+            query("main.rs", Some(12), None)
+        );
+        assert_eq!(
+            GotoQuery::parse("main.rs:12:5"),
+            query("main.rs", Some(12), Some(5))
+        );
+        // Only the last two numbers count; the rest is the name.
+        assert_eq!(GotoQuery::parse("a:1:2:3"), query("a:1", Some(2), Some(3)));
+        assert_eq!(GotoQuery::parse(":40"), query("", Some(40), None));
+    }
+
+    #[test]
+    fn a_trailing_colon_is_still_being_typed() {
+        assert_eq!(GotoQuery::parse("main.rs:"), query("main.rs", None, None));
+        assert_eq!(
+            GotoQuery::parse("main.rs:12:"),
+            query("main.rs", Some(12), None)
+        );
+        assert_eq!(GotoQuery::parse("c:foo"), query("c:foo", None, None));
+    }
+
+    #[test]
+    fn symbols_follow_an_at() {
+        let parsed = GotoQuery::parse(" app @ update ");
+        assert_eq!(parsed.file, "app");
+        assert_eq!(parsed.symbol.as_deref(), Some("update"));
+        assert_eq!(parsed.line, None);
+        assert_eq!(GotoQuery::parse("@").symbol.as_deref(), Some(""));
+    }
+}
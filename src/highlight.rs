@@ -0,0 +1,465 @@
+use crate::buffer::Buffer;
+use crate::syntax::SYNTAX_SET;
+use crate::theme::find_theme;
+use eframe::egui;
+use ropey::Rope;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use syntect::highlighting::{HighlightIterator, HighlightState, Highlighter};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxReference};
+
+// Lines highlighted between checks for newer requests.
+const BATCH_LINES: usize = 500;
+
+// Colors for byte ranges of one line.
+pub type LineStyles = Vec<(Range<usize>, egui::Color32)>;
+
+// Syntax and theme names a highlight was made with.
+type Key = (String, String);
+
+pub fn to_color32(color: syntect::highlighting::Color) -> egui::Color32 {
+    egui::Color32::from_rgba_unmultiplied(color.r, color.g, color.b, color.a)
+}
+
+#[derive(Clone, PartialEq)]
+struct State {
+    parse: ParseState,
+    highlight: HighlightState,
+}
+
+#[derive(Clone)]
+struct CachedLine {
+    start: State,
+    end: State,
+    styles: LineStyles,
+}
+
+// Syntax highlighting of one buffer, kept per line together with the parser
+// state before and after it. A line is only highlighted again when its text
+// changed or it now starts in a different state, so after an edit the work
+// stops as soon as the state converges with what was cached.
+struct HighlightCache {
+    key: Key,
+    // The text the cached lines were computed for.
+    text: Rope,
+    version: u64,
+    lines: Vec<Option<CachedLine>>,
+    // Lines before this are known to be correct for `text`.
+    valid: usize,
+}
+
+impl HighlightCache {
+    fn new(key: Key) -> Self {
+        Self {
+            key,
+            text: Rope::new(),
+            version: 0,
+            lines: Vec::new(),
+            valid: 0,
+        }
+    }
+
+    // Forgets the lines that differ between the cached text and `new`:
+    // everything between their common leading and trailing bytes.
+    fn set_text(&mut self, version: u64, new: Rope) {
+        if self.version == version {
+            return;
+        }
+        let Some((prefix, suffix)) = unchanged_lines(&self.text, &new) else {
+            self.version = version;
+            return;
+        };
+        let (old_len, new_len) = (self.text.len_lines(), new.len_lines());
+
+        self.lines.resize(old_len, None);
+        let kept = self.lines.split_off(old_len - suffix);
+        self.lines.truncate(prefix);
+        self.lines.resize(new_len - suffix, None);
+        self.lines.extend(kept);
+
+        self.valid = self.valid.min(prefix);
+        self.text = new;
+        self.version = version;
+    }
+
+    // Highlights at most `budget` more lines towards `end`, returning whether
+    // every line before it is done.
+    fn advance(
+        &mut self,
+        syntax: &SyntaxReference,
+        highlighter: &Highlighter,
+        end: usize,
+        budget: usize,
+    ) -> bool {
+        let end = end.min(self.lines.len());
+        let stop = end.min(self.valid + budget);
+        while self.valid < stop {
+            let line = self.valid;
+            let prev_end = line
+                .checked_sub(1)
+                .and_then(|prev| self.lines[prev].as_ref())
+                .map(|l| &l.end);
+            let reusable = match (&self.lines[line], prev_end) {
+                (Some(cached), Some(prev_end)) => cached.start == *prev_end,
+                (Some(_), None) => line == 0,
+                (None, _) => false,
+            };
+            if !reusable {
+                let start = prev_end.cloned().unwrap_or_else(|| State {
+                    parse: ParseState::new(syntax),
+                    highlight: HighlightState::new(highlighter, ScopeStack::new()),
+                });
+                let text = self.text.line(line).to_string();
+                self.lines[line] = Some(highlight_line(highlighter, start, &text));
+            }
+            self.valid += 1;
+        }
+        self.valid >= end
+    }
+
+    fn styles(&self, range: Range<usize>) -> Vec<LineStyles> {
+        let end = range.end.min(self.valid);
+        self.lines[range.start.min(end)..end]
+            .iter()
+            .map(|l| l.as_ref().map(|l| l.styles.clone()).unwrap_or_default())
+            .collect()
+    }
+}
+
+struct Request {
+    path: PathBuf,
+    key: Key,
+    version: u64,
+    text: Rope,
+    lines: Range<usize>,
+}
+
+enum Message {
+    Highlight(Request),
+    Forget(PathBuf),
+}
+
+// What the worker last finished for a buffer: styles for some of its lines
+// at one version.
+struct Highlighted {
+    key: Key,
+    version: u64,
+    text: Rope,
+    first_line: usize,
+    styles: Vec<LineStyles>,
+}
+
+impl Highlighted {
+    // Styles for lines of `text`. Made for an older version, they only hold
+    // above the first line that changed since: an edit can change how
+    // everything after it parses, however alike those lines read.
+    fn lines(&self, lines: Range<usize>, version: u64, text: &Rope) -> Vec<LineStyles> {
+        let valid = if self.version == version {
+            usize::MAX
+        } else {
+            unchanged_lines(&self.text, text).map_or(usize::MAX, |(prefix, _)| prefix)
+        };
+        lines
+            .map(|line| {
+                line.checked_sub(self.first_line)
+                    .filter(|_| line < valid)
+                    .and_then(|idx| self.styles.get(idx))
+                    .cloned()
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    results: Mutex<HashMap<PathBuf, Highlighted>>,
+}
+
+// Highlights buffers on a background thread. The UI asks for the lines it is
+// about to draw and gets whatever is ready; anything else is drawn plain
+// until the worker catches up and requests a repaint.
+#[derive(Default)]
+pub struct HighlightWorker {
+    sender: Option<Sender<Message>>,
+    shared: Arc<Shared>,
+    // The last request sent per buffer, so unchanged frames send nothing.
+    requested: HashMap<PathBuf, (Key, u64, Range<usize>)>,
+}
+
+impl HighlightWorker {
+    pub fn styles(
+        &mut self,
+        ctx: &egui::Context,
+        path: &Path,
+        buffer: &Buffer,
+        syntax: &SyntaxReference,
+        theme_name: &str,
+        lines: Range<usize>,
+    ) -> Vec<LineStyles> {
+        let key = (syntax.name.clone(), theme_name.to_string());
+        let version = buffer.version();
+        let text = buffer.snapshot();
+
+        let request = (key.clone(), version, lines.clone());
+        if self.requested.get(path) != Some(&request) {
+            self.requested.insert(path.to_path_buf(), request);
+            let shared = &self.shared;
+            let sender = self
+                .sender
+                .get_or_insert_with(|| spawn(ctx.clone(), shared.clone()));
+            let _ = sender.send(Message::Highlight(Request {
+                path: path.to_path_buf(),
+                key: key.clone(),
+                version,
+                text: text.clone(),
+                lines: lines.clone(),
+            }));
+        }
+
+        let results = self.shared.results.lock().unwrap();
+        match results.get(path).filter(|r| r.key == key) {
+            Some(result) => result.lines(lines, version, &text),
+            None => vec![LineStyles::new(); lines.len()],
+        }
+    }
+
+    pub fn forget(&mut self, path: &Path) {
+        self.requested.remove(path);
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(Message::Forget(path.to_path_buf()));
+        }
+    }
+}
+
+fn spawn(ctx: egui::Context, shared: Arc<Shared>) -> Sender<Message> {
+    let (sender, receiver) = mpsc::channel();
+    // Parser state can't cross threads, so the caches live on the worker.
+    thread::spawn(move || {
+        Worker {
+            ctx,
+            shared,
+            receiver,
+            caches: HashMap::new(),
+            pending: HashMap::new(),
+        }
+        .run()
+    });
+    sender
+}
+
+struct Worker {
+    ctx: egui::Context,
+    shared: Arc<Shared>,
+    receiver: Receiver<Message>,
+    caches: HashMap<PathBuf, HighlightCache>,
+    // The newest request per buffer not yet handled.
+    pending: HashMap<PathBuf, Request>,
+}
+
+impl Worker {
+    fn run(mut self) {
+        while let Ok(message) = self.receiver.recv() {
+            self.receive(message);
+            while let Ok(message) = self.receiver.try_recv() {
+                self.receive(message);
+            }
+            while let Some(path) = self.pending.keys().next().cloned() {
+                let request = self.pending.remove(&path).unwrap();
+                self.process(request);
+            }
+        }
+    }
+
+    fn receive(&mut self, message: Message) {
+        match message {
+            Message::Highlight(request) => {
+                self.pending.insert(request.path.clone(), request);
+            }
+            Message::Forget(path) => {
+                self.pending.remove(&path);
+                self.caches.remove(&path);
+                self.shared.results.lock().unwrap().remove(&path);
+            }
+        }
+    }
+
+    fn process(&mut self, request: Request) {
+        let Some(syntax) = SYNTAX_SET.find_syntax_by_name(&request.key.0) else {
+            return;
+        };
+        let theme = find_theme(&request.key.1);
+        let highlighter = Highlighter::new(&theme);
+        let mut cache = self
+            .caches
+            .remove(&request.path)
+            .filter(|cache| cache.key == request.key)
+            .unwrap_or_else(|| HighlightCache::new(request.key.clone()));
+        cache.set_text(request.version, request.text.clone());
+
+        while !cache.advance(syntax, &highlighter, request.lines.end, BATCH_LINES) {
+            // Give up on this request once the buffer changed again or was
+            // closed; the lines done so far stay cached for the next one.
+            let (mut stale, mut closed) = (false, false);
+            while let Ok(message) = self.receiver.try_recv() {
+                match &message {
+                    Message::Highlight(newer) => stale |= newer.path == request.path,
+                    Message::Forget(path) => closed |= *path == request.path,
+                }
+                self.receive(message);
+            }
+            if closed {
+                return;
+            }
+            if stale {
+                self.caches.insert(request.path, cache);
+                return;
+            }
+        }
+
+        let styles = cache.styles(request.lines.clone());
+        self.shared.results.lock().unwrap().insert(
+            request.path.clone(),
+            Highlighted {
+                key: request.key,
+                version: request.version,
+                text: request.text,
+                first_line: request.lines.start,
+                styles,
+            },
+        );
+        self.caches.insert(request.path, cache);
+        self.ctx.request_repaint();
+    }
+}
+
+fn highlight_line(highlighter: &Highlighter, start: State, text: &str) -> CachedLine {
+    let mut state = start.clone();
+    let ops = state
+        .parse
+        .parse_line(text, &SYNTAX_SET)
+        .unwrap_or_default();
+    let mut styles = LineStyles::new();
+    let mut pos = 0;
+    for (style, piece) in HighlightIterator::new(&mut state.highlight, &ops, text, highlighter) {
+        styles.push((pos..pos + piece.len(), to_color32(style.foreground)));
+        pos += piece.len();
+    }
+    CachedLine {
+        start,
+        end: state,
+        styles,
+    }
+}
+
+// Lines at the start and at the end that are the same text in `old` and
+// `new`, or None if nothing changed. The lines holding the first and last
+// differing byte are changed, and so is the one before in case its `\r\n`
+// ending was split; the ones entirely outside are kept.
+pub fn unchanged_lines(old: &Rope, new: &Rope) -> Option<(usize, usize)> {
+    let (head, tail) = unchanged_bytes(old, new)?;
+    let prefix = new.byte_to_line(head.saturating_sub(1));
+    let suffix = (new.len_lines() - 1 - new.byte_to_line(new.len_bytes() - tail))
+        .min(old.len_lines() - 1 - old.byte_to_line(old.len_bytes() - tail));
+    Some((prefix, suffix))
+}
+
+// Bytes at the start and at the end that are the same in `old` and `new`,
+// not overlapping, or None if nothing changed.
+pub fn unchanged_bytes(old: &Rope, new: &Rope) -> Option<(usize, usize)> {
+    let shortest = old.len_bytes().min(new.len_bytes());
+    let head = common_len(old.chunks(), new.chunks(), false);
+    if head == old.len_bytes() && head == new.len_bytes() {
+        return None;
+    }
+    let tail = common_len(chunks_rev(old), chunks_rev(new), true).min(shortest - head);
+    Some((head, tail))
+}
+
+fn chunks_rev(text: &Rope) -> impl Iterator<Item = &str> {
+    text.chunks_at_byte(text.len_bytes()).0.reversed()
+}
+
+// Number of equal bytes at the start (or end) of two chunked texts.
+fn common_len<'a>(
+    mut a: impl Iterator<Item = &'a str>,
+    mut b: impl Iterator<Item = &'a str>,
+    from_end: bool,
+) -> usize {
+    let (mut x, mut y): (&[u8], &[u8]) = (&[], &[]);
+    let mut total = 0;
+    loop {
+        if x.is_empty() {
+            match a.next() {
+                Some(chunk) => x = chunk.as_bytes(),
+                None => return total,
+            }
+            continue;
+        }
+        if y.is_empty() {
+            match b.next() {
+                Some(chunk) => y = chunk.as_bytes(),
+                None => return total,
+            }
+            continue;
+        }
+        let n = x.len().min(y.len());
+        let same = if from_end {
+            let (xs, ys) = (&x[x.len() - n..], &y[y.len() - n..]);
+            xs.iter()
+                .rev()
+                .zip(ys.iter().rev())
+                .take_while(|(p, q)| p == q)
+                .count()
+        } else {
+            x[..n]
+                .iter()
+                .zip(&y[..n])
+                .take_while(|(p, q)| p == q)
+                .count()
+        };
+        total += same;
+        if same < n {
+            return total;
+        }
+        if from_end {
+            x = &x[..x.len() - n];
+            y = &y[..y.len() - n];
+        } else {
+            x = &x[n..];
+            y = &y[n..];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn colored(color: egui::Color32) -> LineStyles {
+        vec![(0..1, color)]
+    }
+
+    #[test]
+    fn stale_styles_stop_at_the_first_changed_line() {
+        let old = Rope::from("a\n/*\nb\nc\n");
+        let result = Highlighted {
+            key: (String::new(), String::new()),
+            version: 1,
+            text: old.clone(),
+            first_line: 0,
+            styles: (0..5).map(|_| colored(egui::Color32::RED)).collect(),
+        };
+        assert!(result.lines(0..5, 1, &old).iter().all(|s| !s.is_empty()));
+
+        // Lines 2 and 3 read the same, but are no longer in a comment.
+        let new = Rope::from("a\n//\nb\nc\n");
+        let styles = result.lines(0..5, 2, &new);
+        assert!(!styles[0].is_empty());
+        assert!(styles[1..].iter().all(|s| s.is_empty()));
+    }
+}
@@ -0,0 +1,246 @@
+use std::time::{Duration, Instant};
+
+// Typing closer together than this is undone as one step.
+const GROUP_TIMEOUT: Duration = Duration::from_millis(1000);
+
+#[derive(Clone, Debug)]
+pub struct Edit {
+    pub at: usize,
+    pub removed: String,
+    pub inserted: String,
+}
+
+impl Edit {
+    pub fn end(&self) -> usize {
+        self.at + self.inserted.chars().count()
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Transaction {
+    id: u64,
+    edits: Vec<Edit>,
+    // Typed, so more typing may join it.
+    batch: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct History {
+    undo: Vec<Transaction>,
+    redo: Vec<Transaction>,
+    next_id: u64,
+    saved: Option<u64>,
+    last_edit: Option<Instant>,
+    sealed: bool,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            next_id: 1,
+            saved: Some(0),
+            last_edit: None,
+            sealed: true,
+        }
+    }
+}
+
+impl History {
+    // A history whose initial state was never written to disk.
+    pub fn unsaved() -> Self {
+        Self {
+            saved: None,
+            ..Self::default()
+        }
+    }
+
+    fn revision(&self) -> u64 {
+        self.undo.last().map_or(0, |t| t.id)
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.saved != Some(self.revision())
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.revision());
+        self.seal();
+    }
+
+    // Forces the next edit into a new undo step.
+    pub fn seal(&mut self) {
+        self.sealed = true;
+    }
+
+    // Records several edits as a single undo step.
+    pub fn record_group(&mut self, edits: Vec<Edit>) {
+        if edits.is_empty() {
+            return;
+        }
+        self.redo.clear();
+        let id = self.next_id;
+        self.next_id += 1;
+        self.undo.push(Transaction {
+            id,
+            edits,
+            batch: false,
+        });
+        self.seal();
+    }
+
+    // Records the edits of one keystroke, at one caret or several. A burst
+    // of typing becomes a single undo step.
+    pub fn record_batch(&mut self, edits: Vec<Edit>) {
+        if edits.is_empty() {
+            return;
+        }
+        let now = Instant::now();
+        self.redo.clear();
+
+        let joins = !self.sealed
+            && edits.iter().all(|e| !e.inserted.contains('\n'))
+            && self
+                .last_edit
+                .is_some_and(|t| now.duration_since(t) < GROUP_TIMEOUT)
+            && self.undo.last().is_some_and(|t| t.batch);
+
+        match self.undo.last_mut() {
+            Some(transaction) if joins => transaction.edits.extend(edits),
+            _ => {
+                let id = self.next_id;
+                self.next_id += 1;
+                self.undo.push(Transaction {
+                    id,
+                    edits,
+                    batch: true,
+                });
+            }
+        }
+        self.last_edit = Some(now);
+        self.sealed = false;
+    }
+
+    // Returns the edits to revert, most recent first.
+    pub fn undo(&mut self) -> Option<Vec<Edit>> {
+        let transaction = self.undo.pop()?;
+        let edits = transaction.edits.iter().rev().cloned().collect();
+        self.redo.push(transaction);
+        self.seal();
+        Some(edits)
+    }
+
+    // Returns the edits to re-apply, in their original order.
+    pub fn redo(&mut self) -> Option<Vec<Edit>> {
+        let transaction = self.redo.pop()?;
+        let edits = transaction.edits.clone();
+        self.undo.push(transaction);
+        self.seal();
+        Some(edits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(at: usize, text: &str) -> Vec<Edit> {
+        vec![Edit {
+            at,
+            removed: String::new(),
+            inserted: text.to_string(),
+        }]
+    }
+
+    fn inserted(edits: &[Edit]) -> Vec<&str> {
+        edits.iter().map(|e| e.inserted.as_str()).collect()
+    }
+
+    #[test]
+    fn typing_is_grouped_into_one_step() {
+        let mut history = History::default();
+        history.record_batch(typed(0, "a"));
+        history.record_batch(typed(1, "b"));
+        history.record_batch(typed(2, "c"));
+        assert_eq!(inserted(&history.undo().unwrap()), ["c", "b", "a"]);
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn batches_keep_every_caret() {
+        let mut history = History::default();
+        let mut edits = typed(0, "a");
+        edits.extend(typed(5, "a"));
+        history.record_batch(edits);
+        assert_eq!(history.undo().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn seal_and_newlines_start_a_new_step() {
+        let mut history = History::default();
+        history.record_batch(typed(0, "a"));
+        history.seal();
+        history.record_batch(typed(1, "b"));
+        history.record_batch(typed(2, "\n"));
+        history.record_batch(typed(3, "c"));
+        // Typing after the newline carries on in its step.
+        assert_eq!(inserted(&history.undo().unwrap()), ["c", "\n"]);
+        assert_eq!(inserted(&history.undo().unwrap()), ["b"]);
+        assert_eq!(inserted(&history.undo().unwrap()), ["a"]);
+    }
+
+    #[test]
+    fn groups_are_never_joined() {
+        let mut history = History::default();
+        history.record_batch(typed(0, "a"));
+        history.record_group(typed(1, "b"));
+        history.record_batch(typed(2, "c"));
+        assert_eq!(inserted(&history.undo().unwrap()), ["c"]);
+        assert_eq!(inserted(&history.undo().unwrap()), ["b"]);
+        assert_eq!(inserted(&history.undo().unwrap()), ["a"]);
+    }
+
+    #[test]
+    fn undo_and_redo_order() {
+        let mut history = History::default();
+        let mut edits = typed(0, "a");
+        edits.extend(typed(1, "b"));
+        history.record_group(edits);
+        assert_eq!(inserted(&history.undo().unwrap()), ["b", "a"]);
+        assert_eq!(inserted(&history.redo().unwrap()), ["a", "b"]);
+        assert!(history.redo().is_none());
+
+        // A new edit after an undo drops what could be redone.
+        history.undo();
+        history.record_batch(typed(0, "x"));
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn undoing_back_to_the_save_is_clean() {
+        let mut history = History::default();
+        assert!(!history.is_dirty());
+        history.record_batch(typed(0, "a"));
+        history.mark_saved();
+        assert!(!history.is_dirty());
+
+        history.record_batch(typed(1, "b"));
+        assert!(history.is_dirty());
+        history.undo();
+        assert!(!history.is_dirty());
+        history.undo();
+        assert!(history.is_dirty());
+        history.redo();
+        assert!(!history.is_dirty());
+    }
+
+    #[test]
+    fn unsaved_history_starts_dirty() {
+        let mut history = History::unsaved();
+        assert!(history.is_dirty());
+        history.record_batch(typed(0, "a"));
+        history.undo();
+        assert!(history.is_dirty());
+    }
+}
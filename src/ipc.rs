@@ -0,0 +1,197 @@
+// Single-instance support: a second invocation hands its arguments to the
+// running window over a local socket and exits.
+//
+// Protocol: the client writes its `CliArgs` as one JSON line. The server
+// answers `ok` once the targets are open, or `closed` once the client's files
+// have been closed again when it passed `--wait`.
+
+pub enum Startup {
+    // Another window took the request; this process should exit.
+    Forwarded,
+    // This process opens its own window, serving later invocations if a
+    // server could be set up.
+    Primary(Option<PendingServer>),
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::Startup;
+    use crate::cli::CliArgs;
+    use eframe::egui;
+    use std::fs;
+    use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::PathBuf;
+    use std::sync::mpsc::{self, Receiver};
+    use std::thread;
+    use std::time::Duration;
+
+    // How long a client gets to send its request.
+    const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+    // The socket lives in a directory only this user can get into, so no
+    // one else can put their own socket where ours is looked for, or have
+    // us remove theirs. XDG_RUNTIME_DIR is such a directory; without it one
+    // is made in the temp dir.
+    fn socket_path() -> io::Result<PathBuf> {
+        // SAFETY: getuid cannot fail and has no preconditions.
+        let uid = unsafe { libc::getuid() };
+        let dir = match dirs::runtime_dir() {
+            Some(dir) => dir,
+            None => {
+                let dir = std::env::temp_dir().join(format!("suruc-{}", uid));
+                match fs::DirBuilder::new().mode(0o700).create(&dir) {
+                    Err(e) if e.kind() != ErrorKind::AlreadyExists => return Err(e),
+                    _ => {}
+                }
+                dir
+            }
+        };
+        // It may have been there already, made by someone else.
+        let metadata = fs::symlink_metadata(&dir)?;
+        if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+            return Err(io::Error::new(
+                ErrorKind::PermissionDenied,
+                format!("{} is not private to this user", dir.display()),
+            ));
+        }
+        Ok(dir.join("suruc.sock"))
+    }
+
+    pub struct Client {
+        stream: UnixStream,
+    }
+
+    impl Client {
+        pub fn reply(mut self, message: &str) {
+            let _ = writeln!(self.stream, "{}", message);
+        }
+    }
+
+    pub struct PendingServer {
+        listener: UnixListener,
+        socket: PathBuf,
+    }
+
+    pub struct IpcServer {
+        receiver: Receiver<(CliArgs, Client)>,
+        socket: PathBuf,
+    }
+
+    fn forward(mut stream: UnixStream, args: &CliArgs) -> std::io::Result<()> {
+        let request = serde_json::to_string(args)?;
+        writeln!(stream, "{}", request)?;
+        // Blocks until the server answers; with `--wait` that is when the
+        // files are closed, or EOF if the window goes away first.
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply)?;
+        Ok(())
+    }
+
+    pub fn forward_or_listen(args: &CliArgs) -> Startup {
+        let Ok(socket) = socket_path() else {
+            return Startup::Primary(None);
+        };
+        match UnixStream::connect(&socket) {
+            Ok(stream) => {
+                if forward(stream, args).is_ok() {
+                    return Startup::Forwarded;
+                }
+                // A window is listening but the request went wrong; open
+                // another one, leaving its socket alone.
+                return Startup::Primary(None);
+            }
+            // Nobody is listening: whatever is left at the path is stale.
+            Err(e) if matches!(e.kind(), ErrorKind::ConnectionRefused | ErrorKind::NotFound) => {
+                let _ = fs::remove_file(&socket);
+            }
+            Err(_) => {}
+        }
+        let pending = UnixListener::bind(&socket)
+            .ok()
+            .map(|listener| PendingServer { listener, socket });
+        Startup::Primary(pending)
+    }
+
+    impl PendingServer {
+        pub fn start(self, ctx: egui::Context) -> IpcServer {
+            let (sender, receiver) = mpsc::channel();
+            let listener = self.listener;
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    // Read on their own, so a client that never sends its
+                    // line does not hold up the ones after it.
+                    let (sender, ctx) = (sender.clone(), ctx.clone());
+                    thread::spawn(move || {
+                        let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+                        let mut line = String::new();
+                        let Ok(reader) = stream.try_clone() else {
+                            return;
+                        };
+                        if BufReader::new(reader).read_line(&mut line).is_err() {
+                            return;
+                        }
+                        let Ok(args) = serde_json::from_str::<CliArgs>(&line) else {
+                            return;
+                        };
+                        if sender.send((args, Client { stream })).is_ok() {
+                            ctx.request_repaint();
+                        }
+                    });
+                }
+            });
+            IpcServer {
+                receiver,
+                socket: self.socket,
+            }
+        }
+    }
+
+    impl IpcServer {
+        pub fn try_recv(&self) -> Option<(CliArgs, Client)> {
+            self.receiver.try_recv().ok()
+        }
+    }
+
+    impl Drop for IpcServer {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.socket);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use super::Startup;
+    use crate::cli::CliArgs;
+    use eframe::egui;
+
+    pub struct Client;
+
+    impl Client {
+        pub fn reply(self, _message: &str) {}
+    }
+
+    pub struct PendingServer;
+
+    pub struct IpcServer;
+
+    pub fn forward_or_listen(_args: &CliArgs) -> Startup {
+        Startup::Primary(None)
+    }
+
+    impl PendingServer {
+        pub fn start(self, _ctx: egui::Context) -> IpcServer {
+            IpcServer
+        }
+    }
+
+    impl IpcServer {
+        pub fn try_recv(&self) -> Option<(CliArgs, Client)> {
+            None
+        }
+    }
+}
+
+pub use imp::{forward_or_listen, Client, IpcServer, PendingServer};
@@ -1,20 +1,66 @@
 mod app;
+mod buffer;
+mod cli;
+mod commands;
+mod encoding;
+mod find_in_files;
+mod fuzzy;
+mod goto;
+mod highlight;
+mod history;
+mod ipc;
+mod quick_panel;
+mod recent;
+mod replace_in_files;
+mod search;
+mod selection;
+mod session;
+mod settings;
 mod syntax;
+mod theme;
 mod ui;
 
 use app::SublimeRustApp;
+use cli::ParseOutcome;
 use eframe::egui;
+use ipc::Startup;
+use settings::SettingsLoader;
 
 fn main() -> eframe::Result<()> {
+    let args = match cli::parse(std::env::args().skip(1)) {
+        ParseOutcome::Run(args) => args,
+        ParseOutcome::Exit(message, 0) => {
+            println!("{}", message);
+            return Ok(());
+        }
+        ParseOutcome::Exit(message, code) => {
+            eprintln!("{}", message);
+            std::process::exit(code);
+        }
+    };
+
+    // `--new-window` always gets its own window, and so does `--wait` when
+    // nothing is running: that window would close with its files, so it must
+    // not become the one other invocations are sent to.
+    let mut server = None;
+    if !args.new_window {
+        match ipc::forward_or_listen(&args) {
+            Startup::Forwarded => return Ok(()),
+            Startup::Primary(pending) if !args.wait => server = pending,
+            Startup::Primary(_) => {}
+        }
+    }
+
+    let (settings, _) = SettingsLoader::new().load();
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
-            .with_inner_size([1024.0, 768.0])
+            .with_inner_size(settings.window_size)
             .with_title("SuRuC"),
         ..Default::default()
     };
     eframe::run_native(
         "sublime_rust_cpu",
         native_options,
-        Box::new(|cc| Box::new(SublimeRustApp::new(cc))),
+        Box::new(|cc| Box::new(SublimeRustApp::new(cc, args, server))),
     )
 }
@@ -0,0 +1,36 @@
+use crate::app::SublimeRustApp;
+use eframe::egui;
+
+// A list to choose one item from, for commands that need an argument
+// (Sublime's quick panel): an encoding, a syntax, a recent file.
+pub struct QuickPanel {
+    pub placeholder: &'static str,
+    pub items: Vec<String>,
+    pub query: String,
+    // Index into the filtered items.
+    pub selected: usize,
+    // The item to start on, e.g. the encoding in use.
+    pub current: Option<usize>,
+    pub just_activated: bool,
+    // Gets the index and text of the item chosen.
+    pub on_select: fn(&mut SublimeRustApp, &egui::Context, usize, &str),
+}
+
+impl QuickPanel {
+    pub fn new(
+        placeholder: &'static str,
+        items: Vec<String>,
+        current: Option<usize>,
+        on_select: fn(&mut SublimeRustApp, &egui::Context, usize, &str),
+    ) -> Self {
+        Self {
+            placeholder,
+            items,
+            query: String::new(),
+            selected: 0,
+            current,
+            just_activated: true,
+            on_select,
+        }
+    }
+}
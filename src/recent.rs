@@ -0,0 +1,75 @@
+use crate::settings::config_dir;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const RECENT_FILE: &str = "Recent.json";
+const MAX_ITEMS: usize = 10;
+
+// Most-recently-used files and folders, newest first. Saved on every change
+// so a crash does not lose them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecentItems {
+    pub files: Vec<PathBuf>,
+    pub folders: Vec<PathBuf>,
+}
+
+fn recent_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join(RECENT_FILE))
+}
+
+fn push_front(list: &mut Vec<PathBuf>, path: &Path) {
+    list.retain(|p| p != path);
+    list.insert(0, path.to_path_buf());
+    list.truncate(MAX_ITEMS);
+}
+
+impl RecentItems {
+    pub fn load() -> Self {
+        let mut recent: Self = recent_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+        recent.prune();
+        recent
+    }
+
+    fn save(&self) {
+        let Some(path) = recent_path() else {
+            return;
+        };
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        if let Ok(text) = serde_json::to_string_pretty(self) {
+            let _ = fs::write(path, text);
+        }
+    }
+
+    pub fn add_file(&mut self, path: &Path) {
+        push_front(&mut self.files, path);
+        self.save();
+    }
+
+    pub fn add_folder(&mut self, path: &Path) {
+        push_front(&mut self.folders, path);
+        self.save();
+    }
+
+    // Drops entries whose file or folder no longer exists.
+    pub fn prune(&mut self) {
+        let before = self.files.len() + self.folders.len();
+        self.files.retain(|p| p.is_file());
+        self.folders.retain(|p| p.is_dir());
+        if self.files.len() + self.folders.len() != before {
+            self.save();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.files.clear();
+        self.folders.clear();
+        self.save();
+    }
+}
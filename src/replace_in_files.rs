@@ -0,0 +1,170 @@
+use crate::encoding::{self, TextEncoding};
+use crate::search::{self, FindOptions};
+use regex::Regex;
+use std::fs;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+pub const PREVIEW_TAB: &str = "find://Replace Preview";
+
+// One proposed replacement and the lines it touches, before and after.
+pub struct Change {
+    // Bytes of the text the preview was made from.
+    pub range: Range<usize>,
+    pub replacement: String,
+    // Zero-based line of the first of `before`.
+    pub line: usize,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+    pub selected: bool,
+}
+
+pub struct FileChanges {
+    pub path: PathBuf,
+    // In an editor tab, which is edited instead of the file on disk.
+    pub open: bool,
+    // What the file is written back in.
+    pub encoding: TextEncoding,
+    // What the ranges refer to; nothing is replaced if the text has moved
+    // on since.
+    pub original: String,
+    pub changes: Vec<Change>,
+}
+
+impl FileChanges {
+    pub fn build(
+        path: PathBuf,
+        open: bool,
+        (original, encoding): (String, TextEncoding),
+        regex: &Regex,
+        replace: &str,
+        options: &FindOptions,
+    ) -> Option<Self> {
+        let mut changes = Vec::new();
+        // Lines are counted from the previous change on.
+        let (mut counted, mut line) = (0, 0);
+        for m in regex.find_iter(&original).filter(|m| !m.is_empty()) {
+            let replacement = search::replacement(regex, &original, m.range(), replace, options);
+            let start = original[..m.start()].rfind('\n').map_or(0, |i| i + 1);
+            let end = original[m.end()..]
+                .find('\n')
+                .map_or(original.len(), |i| m.end() + i);
+            line += original[counted..start].matches('\n').count();
+            counted = start;
+
+            let after = format!(
+                "{}{}{}",
+                &original[start..m.start()],
+                replacement,
+                &original[m.end()..end]
+            );
+            changes.push(Change {
+                range: m.range(),
+                line,
+                before: original[start..end].lines().map(str::to_string).collect(),
+                after: after.lines().map(str::to_string).collect(),
+                replacement,
+                selected: true,
+            });
+        }
+        if changes.is_empty() {
+            return None;
+        }
+        Some(Self {
+            path,
+            open,
+            encoding,
+            original,
+            changes,
+        })
+    }
+
+    pub fn selected(&self) -> usize {
+        self.changes.iter().filter(|c| c.selected).count()
+    }
+
+    pub fn select_all(&mut self, selected: bool) {
+        for change in &mut self.changes {
+            change.selected = selected;
+        }
+    }
+
+    // The selected changes as byte ranges and their replacements.
+    pub fn edits(&self) -> impl Iterator<Item = (Range<usize>, &str)> {
+        self.changes
+            .iter()
+            .filter(|c| c.selected)
+            .map(|c| (c.range.clone(), c.replacement.as_str()))
+    }
+
+    // The original text with the selected changes made.
+    pub fn replaced(&self) -> String {
+        let mut text = String::with_capacity(self.original.len());
+        let mut pos = 0;
+        for (range, replacement) in self.edits() {
+            text.push_str(&self.original[pos..range.start]);
+            text.push_str(replacement);
+            pos = range.end;
+        }
+        text.push_str(&self.original[pos..]);
+        text
+    }
+}
+
+// Replace in Files waits for the user to go through what would change.
+pub struct ReplacePreview {
+    pub query: String,
+    pub replace: String,
+    pub files: Vec<FileChanges>,
+}
+
+impl ReplacePreview {
+    pub fn selected(&self) -> usize {
+        self.files.iter().map(FileChanges::selected).sum()
+    }
+
+    pub fn total(&self) -> usize {
+        self.files.iter().map(|f| f.changes.len()).sum()
+    }
+}
+
+// A file as a Replace in Files left it, for undoing it as one step.
+pub struct ReplacedFile {
+    pub path: PathBuf,
+    pub before: String,
+    pub after: String,
+    pub encoding: TextEncoding,
+    // Set for open buffers: their version right after the replace, while
+    // it is still the last thing that happened to them.
+    pub version: Option<u64>,
+}
+
+// The text of a file on disk, in whatever encoding it is in.
+pub fn read(path: &Path) -> io::Result<(String, TextEncoding)> {
+    encoding::decode(&fs::read(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// Writes next to the file and renames over it, so it holds either the old
+// or the new text, never part of each.
+pub fn write_atomically(path: &Path, text: &str, encoding: TextEncoding) -> io::Result<()> {
+    let bytes = encoding::encode(text, encoding)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file"))?;
+    let temp = path.with_file_name(format!(".{}.suruc-tmp", name.to_string_lossy()));
+    let written = (|| {
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        if let Ok(metadata) = fs::metadata(path) {
+            fs::set_permissions(&temp, metadata.permissions())?;
+        }
+        fs::rename(&temp, path)
+    })();
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written
+}
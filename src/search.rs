@@ -0,0 +1,485 @@
+use crate::highlight::unchanged_bytes;
+use regex::{Regex, RegexBuilder};
+use regex_syntax::hir::{Class, Hir, HirKind};
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+// Toggles of the find panels.
+#[derive(Clone, Copy, Debug)]
+pub struct FindOptions {
+    pub regex: bool,
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    pub in_selection: bool,
+    pub wrap: bool,
+    pub preserve_case: bool,
+}
+
+impl Default for FindOptions {
+    fn default() -> Self {
+        Self {
+            regex: false,
+            case_sensitive: false,
+            whole_word: false,
+            in_selection: false,
+            wrap: true,
+            preserve_case: false,
+        }
+    }
+}
+
+const HISTORY_LEN: usize = 50;
+
+// Queries searched for, oldest first, browsed with Up and Down in the find
+// fields.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct QueryHistory {
+    entries: Vec<String>,
+    // The entry shown while browsing, and what was typed before.
+    #[serde(skip)]
+    position: Option<usize>,
+    #[serde(skip)]
+    draft: String,
+}
+
+impl QueryHistory {
+    pub fn push(&mut self, query: &str) {
+        self.position = None;
+        if query.is_empty() {
+            return;
+        }
+        self.entries.retain(|entry| entry != query);
+        self.entries.push(query.to_string());
+        let excess = self.entries.len().saturating_sub(HISTORY_LEN);
+        self.entries.drain(..excess);
+    }
+
+    pub fn older(&mut self, current: &str) -> Option<String> {
+        let position = match self.position {
+            Some(position) => position.checked_sub(1)?,
+            None => {
+                self.draft = current.to_string();
+                // The query just searched for is already in the field.
+                let newest = self.entries.len().checked_sub(1)?;
+                if self.entries[newest] == current {
+                    newest.checked_sub(1)?
+                } else {
+                    newest
+                }
+            }
+        };
+        self.position = Some(position);
+        Some(self.entries[position].clone())
+    }
+
+    pub fn newer(&mut self) -> Option<String> {
+        let position = self.position? + 1;
+        if position < self.entries.len() {
+            self.position = Some(position);
+            Some(self.entries[position].clone())
+        } else {
+            self.position = None;
+            Some(std::mem::take(&mut self.draft))
+        }
+    }
+
+    // Typing in the field ends browsing.
+    pub fn stop_browsing(&mut self) {
+        self.position = None;
+    }
+}
+
+// Compiles a query the way the find panels read it: literally unless regex
+// mode is on, and with `^` and `$` matching at every line like Sublime.
+pub fn build_regex(query: &str, options: &FindOptions) -> Result<Regex, String> {
+    let mut pattern = if options.regex {
+        query.to_string()
+    } else {
+        regex::escape(query)
+    };
+    if options.whole_word {
+        // `\b` needs a word char on one side of it, so it is left off the
+        // sides where the match has none: `->` and `.foo` are found too.
+        let hir = regex_syntax::parse(&pattern).ok();
+        let boundary = |start| match hir.as_ref().and_then(|hir| word_edge(hir, start)) {
+            Some(false) => "",
+            _ => r"\b",
+        };
+        pattern = format!(r"{}(?:{}){}", boundary(true), pattern, boundary(false));
+    }
+    RegexBuilder::new(&pattern)
+        .case_insensitive(!options.case_sensitive)
+        .multi_line(true)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+// Every non-empty match in `text`, as char ranges.
+pub fn find_all(regex: &Regex, text: &Rope) -> Vec<Range<usize>> {
+    find_in(regex, text, 0..text.len_chars())
+}
+
+fn find_in(regex: &Regex, text: &Rope, chars: Range<usize>) -> Vec<Range<usize>> {
+    let offset = text.char_to_byte(chars.start);
+    let slice = text.slice(chars).to_string();
+    regex
+        .find_iter(&slice)
+        .filter(|m| !m.is_empty())
+        .map(|m| text.byte_to_char(offset + m.start())..text.byte_to_char(offset + m.end()))
+        .collect()
+}
+
+// The matches in `new`, given the `matches` found in `old` and that `new`
+// is `old` edited. Unless a match can run across a line break, only the
+// lines the edit touched are searched again.
+pub fn rematch(
+    regex: &Regex,
+    old: &Rope,
+    new: &Rope,
+    matches: &[Range<usize>],
+) -> Vec<Range<usize>> {
+    let Some((head, tail)) = unchanged_bytes(old, new) else {
+        return matches.to_vec();
+    };
+    if crosses_lines(regex) {
+        return find_all(regex, new);
+    }
+    // What comes before `start` and after `end` is the same in both texts.
+    let start = line_start(new, new.byte_to_char(head));
+    let end = line_end(new, new.byte_to_char(new.len_bytes() - tail));
+    let after = new.len_chars() - end;
+    let old_end = old.len_chars() - after;
+
+    let kept_before = matches.partition_point(|m| m.end <= start);
+    let kept_after = matches.partition_point(|m| m.start < old_end);
+    let shift = |m: &Range<usize>| m.start + end - old_end..m.end + end - old_end;
+    let mut updated = matches[..kept_before].to_vec();
+    updated.extend(find_in(regex, new, start..end));
+    updated.extend(matches[kept_after..].iter().map(shift));
+    updated
+}
+
+fn line_start(text: &Rope, char_idx: usize) -> usize {
+    let mut chars = text.chars_at(char_idx);
+    let mut start = char_idx;
+    while chars.prev().is_some_and(|c| c != '\n') {
+        start -= 1;
+    }
+    start
+}
+
+// Just past the `\n` ending the line, or the end of the text.
+fn line_end(text: &Rope, char_idx: usize) -> usize {
+    let mut end = char_idx;
+    for c in text.chars_at(char_idx) {
+        end += 1;
+        if c == '\n' {
+            break;
+        }
+    }
+    end
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// Whether every match starts (or ends) with a word char: Some(true) if so,
+// Some(false) if none does, None if it depends on the text.
+fn word_edge(hir: &Hir, start: bool) -> Option<bool> {
+    match hir.kind() {
+        HirKind::Literal(literal) => {
+            let text = std::str::from_utf8(&literal.0).ok()?;
+            let mut chars = text.chars();
+            let edge = if start {
+                chars.next()
+            } else {
+                chars.next_back()
+            };
+            edge.map(is_word_char)
+        }
+        HirKind::Class(Class::Unicode(class)) => {
+            let mut ends = class
+                .ranges()
+                .iter()
+                .flat_map(|r| [r.start(), r.end()])
+                .map(is_word_char);
+            let first = ends.next()?;
+            ends.all(|word| word == first).then_some(first)
+        }
+        HirKind::Capture(capture) => word_edge(&capture.sub, start),
+        HirKind::Repetition(repetition) if repetition.min > 0 => word_edge(&repetition.sub, start),
+        HirKind::Concat(hirs) => {
+            let edge = if start { hirs.first() } else { hirs.last() }?;
+            // Something that can match nothing leaves the edge to what is
+            // next to it.
+            if edge.properties().minimum_len() == Some(0) {
+                return None;
+            }
+            word_edge(edge, start)
+        }
+        HirKind::Alternation(hirs) => {
+            let first = word_edge(hirs.first()?, start)?;
+            hirs[1..]
+                .iter()
+                .all(|hir| word_edge(hir, start) == Some(first))
+                .then_some(first)
+        }
+        _ => None,
+    }
+}
+
+// Whether a match can take in a `\n`. Patterns that fail to parse are
+// assumed to.
+fn crosses_lines(regex: &Regex) -> bool {
+    fn matches_newline(hir: &Hir) -> bool {
+        match hir.kind() {
+            HirKind::Empty | HirKind::Look(_) => false,
+            HirKind::Literal(literal) => literal.0.contains(&b'\n'),
+            HirKind::Class(Class::Unicode(class)) => class
+                .ranges()
+                .iter()
+                .any(|r| r.start() <= '\n' && '\n' <= r.end()),
+            HirKind::Class(Class::Bytes(class)) => class
+                .ranges()
+                .iter()
+                .any(|r| r.start() <= b'\n' && b'\n' <= r.end()),
+            HirKind::Repetition(repetition) => matches_newline(&repetition.sub),
+            HirKind::Capture(capture) => matches_newline(&capture.sub),
+            HirKind::Concat(hirs) | HirKind::Alternation(hirs) => hirs.iter().any(matches_newline),
+        }
+    }
+    regex_syntax::ParserBuilder::new()
+        .multi_line(true)
+        .build()
+        .parse(regex.as_str())
+        .map_or(true, |hir| matches_newline(&hir))
+}
+
+// The last line of a regex error, which says what is wrong without the
+// pattern excerpt above it.
+pub fn short_error(error: &str) -> &str {
+    let last = error.lines().last().unwrap_or(error);
+    last.strip_prefix("error: ").unwrap_or(last)
+}
+
+// The text replacing the match at `range` (bytes of `text`): in regex mode
+// `$1` and `${name}` refer to its groups, otherwise it is taken literally.
+pub fn replacement(
+    regex: &Regex,
+    text: &str,
+    range: Range<usize>,
+    replace: &str,
+    options: &FindOptions,
+) -> String {
+    let captures = options
+        .regex
+        .then(|| regex.captures_at(text, range.start))
+        .flatten()
+        .filter(|captures| captures.get(0).map(|m| m.range()) == Some(range.clone()));
+    let mut replaced = String::new();
+    match captures {
+        Some(captures) => captures.expand(replace, &mut replaced),
+        None => replaced.push_str(replace),
+    }
+    if options.preserve_case {
+        replaced = match_case(&text[range], &replaced);
+    }
+    replaced
+}
+
+// Gives the replacement the casing of the text it replaces: all upper, all
+// lower or capitalized.
+fn match_case(found: &str, replaced: &str) -> String {
+    let letters = || found.chars().filter(|c| c.is_alphabetic());
+    if letters().next().is_none() {
+        return replaced.to_string();
+    }
+    if letters().all(char::is_uppercase) && letters().nth(1).is_some() {
+        return replaced.to_uppercase();
+    }
+    if letters().all(char::is_lowercase) {
+        return replaced.to_lowercase();
+    }
+    if letters().next().is_some_and(char::is_uppercase) {
+        let mut chars = replaced.chars();
+        return chars.next().map_or_else(String::new, |first| {
+            first.to_uppercase().chain(chars).collect()
+        });
+    }
+    replaced.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regex(pattern: &str) -> Regex {
+        let options = FindOptions {
+            regex: true,
+            ..FindOptions::default()
+        };
+        build_regex(pattern, &options).unwrap()
+    }
+
+    // What `query` finds in `text` with the given toggles.
+    fn found(query: &str, text: &str, options: FindOptions) -> Vec<String> {
+        let regex = build_regex(query, &options).unwrap();
+        regex
+            .find_iter(text)
+            .map(|m| m.as_str().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn queries_are_literal_unless_regex_is_on() {
+        let options = FindOptions::default();
+        assert_eq!(found("a.c", "abc a.c", options), ["a.c"]);
+        assert_eq!(found("(x", "f(x)", options), ["(x"]);
+        let regex = FindOptions {
+            regex: true,
+            ..options
+        };
+        assert_eq!(found("a.c", "abc a.c", regex), ["abc", "a.c"]);
+        assert!(build_regex("(x", &regex).is_err());
+    }
+
+    #[test]
+    fn case_sensitivity() {
+        let options = FindOptions::default();
+        assert_eq!(found("foo", "Foo FOO foo", options), ["Foo", "FOO", "foo"]);
+        let sensitive = FindOptions {
+            case_sensitive: true,
+            ..options
+        };
+        assert_eq!(found("foo", "Foo FOO foo", sensitive), ["foo"]);
+    }
+
+    #[test]
+    fn whole_words() {
+        let options = FindOptions {
+            whole_word: true,
+            ..FindOptions::default()
+        };
+        assert_eq!(
+            found("foo", "foo foobar barfoo (foo)", options),
+            ["foo", "foo"]
+        );
+        let regex = FindOptions {
+            regex: true,
+            ..options
+        };
+        // Alternatives are whole words as a group.
+        assert_eq!(found("a|ab", "ab a", regex), ["ab", "a"]);
+
+        // Only the sides with a word char need a word boundary.
+        assert_eq!(found("->", "a->b c -> d", options), ["->", "->"]);
+        assert_eq!(found(".foo", "x.foo .foobar", options), [".foo"]);
+        assert_eq!(found("foo(", "foo( barfoo(", options), ["foo("]);
+        assert_eq!(found(r"\.\w+", "a.b .cd", regex), [".b", ".cd"]);
+        assert_eq!(found(r"\w+\.", "xab. cd.e", regex), ["xab.", "cd."]);
+    }
+
+    #[test]
+    fn lines_are_anchored() {
+        let regex = FindOptions {
+            regex: true,
+            ..FindOptions::default()
+        };
+        assert_eq!(
+            found(
+                "^x$", "x
+y
+x", regex
+            ),
+            ["x", "x"]
+        );
+    }
+
+    // Every match in `text` replaced with `replace`.
+    fn replaced(query: &str, replace: &str, text: &str, options: FindOptions) -> String {
+        let regex = build_regex(query, &options).unwrap();
+        let mut result = String::new();
+        let mut pos = 0;
+        for m in regex.find_iter(text) {
+            result.push_str(&text[pos..m.start()]);
+            result.push_str(&replacement(&regex, text, m.range(), replace, &options));
+            pos = m.end();
+        }
+        result.push_str(&text[pos..]);
+        result
+    }
+
+    #[test]
+    fn groups_are_expanded_only_in_regex_mode() {
+        let options = FindOptions::default();
+        assert_eq!(replaced("a", "$0$0", "a b a", options), "$0$0 b $0$0");
+        let regex = FindOptions {
+            regex: true,
+            ..options
+        };
+        assert_eq!(
+            replaced(r"(\w+)=(?P<v>\d+)", "${v}=$1", "x=1, yy=22", regex),
+            "1=x, 22=yy"
+        );
+    }
+
+    #[test]
+    fn preserve_case_follows_the_replaced_text() {
+        let options = FindOptions {
+            preserve_case: true,
+            ..FindOptions::default()
+        };
+        assert_eq!(
+            replaced("foo", "bar", "foo Foo FOO fOO", options),
+            "bar Bar BAR bar"
+        );
+        // A single capital reads as capitalized, not all upper.
+        assert_eq!(replaced("a", "xyz", "A a", options), "Xyz xyz");
+        // Without letters there is no case to follow.
+        assert_eq!(replaced("12", "Ab", "12", options), "Ab");
+    }
+
+    #[test]
+    fn rematch_agrees_with_searching_everything() {
+        let pieces = ["foo", " ", "\n", "\r\n", "fo", "o", "x", "é", ""];
+        for pattern in [r"fo+", r"\bfoo\b", r"^x", r"o$", r"o\s+f", "é"] {
+            let regex = regex(pattern);
+            let mut text = Rope::from("foo x\nxfoo\r\nfoo foo\n");
+            let mut matches = find_all(&regex, &text);
+            // A fixed sequence of pseudo-random edits.
+            let mut seed = 17u32;
+            let mut next = |n: usize| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 8) as usize % n.max(1)
+            };
+            for _ in 0..500 {
+                let old = text.clone();
+                let start = next(text.len_chars() + 1);
+                let end = (start + next(4)).min(text.len_chars());
+                text.remove(start..end);
+                text.insert(start, pieces[next(pieces.len())]);
+
+                matches = rematch(&regex, &old, &text, &matches);
+                assert_eq!(
+                    matches,
+                    find_all(&regex, &text),
+                    "{:?} in {:?}",
+                    pattern,
+                    text
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn patterns_that_can_match_a_line_break_cross_lines() {
+        assert!(crosses_lines(&regex(r"a\nb")));
+        assert!(crosses_lines(&regex(r"a\s+b")));
+        assert!(crosses_lines(&regex(r"[^x]")));
+        assert!(!crosses_lines(&regex(r"fo+.")));
+        assert!(!crosses_lines(&regex(r"^\w+$")));
+    }
+}
@@ -0,0 +1,599 @@
+use crate::buffer::Buffer;
+use std::ops::Range;
+
+// A caret plus the text it selects; `anchor == head` is a bare caret. Both
+// are char indices, `head` being the end that moves.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Selection {
+    pub anchor: usize,
+    pub head: usize,
+    // The display column Up and Down aim for, kept while moving from line to
+    // line so a shorter line on the way does not pull the caret left.
+    goal: Option<usize>,
+}
+
+impl Selection {
+    pub fn new(anchor: usize, head: usize) -> Self {
+        Self {
+            anchor,
+            head,
+            goal: None,
+        }
+    }
+
+    pub fn caret(pos: usize) -> Self {
+        Self::new(pos, pos)
+    }
+
+    pub fn start(&self) -> usize {
+        self.anchor.min(self.head)
+    }
+
+    pub fn end(&self) -> usize {
+        self.anchor.max(self.head)
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start()..self.end()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+}
+
+pub enum Motion {
+    Left,
+    Right,
+    WordLeft,
+    WordRight,
+    // By a number of lines, e.g. a page.
+    Up(usize),
+    Down(usize),
+    LineStart,
+    LineEnd,
+    DocStart,
+    DocEnd,
+}
+
+// Every caret of the active editor, sorted by position and never
+// overlapping. The primary one is what single-caret features (find, the
+// footer, scrolling) look at; it is the most recently added.
+#[derive(Clone, Debug)]
+pub struct Selections {
+    ranges: Vec<Selection>,
+    primary: usize,
+}
+
+impl Default for Selections {
+    fn default() -> Self {
+        Self::single(Selection::caret(0))
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// Ctrl+Left/Right stop at the edges of words and of runs of punctuation.
+fn word_boundary_right(buffer: &Buffer, mut pos: usize) -> usize {
+    while buffer.char_at(pos).is_some_and(char::is_whitespace) {
+        pos += 1;
+    }
+    let word = buffer.char_at(pos).is_some_and(is_word_char);
+    while buffer
+        .char_at(pos)
+        .is_some_and(|c| !c.is_whitespace() && is_word_char(c) == word)
+    {
+        pos += 1;
+    }
+    pos
+}
+
+fn word_boundary_left(buffer: &Buffer, mut pos: usize) -> usize {
+    let before = |pos: usize| pos.checked_sub(1).and_then(|p| buffer.char_at(p));
+    while before(pos).is_some_and(char::is_whitespace) {
+        pos -= 1;
+    }
+    let word = before(pos).is_some_and(is_word_char);
+    while before(pos).is_some_and(|c| !c.is_whitespace() && is_word_char(c) == word) {
+        pos -= 1;
+    }
+    pos
+}
+
+impl Selections {
+    pub fn single(selection: Selection) -> Self {
+        Self {
+            ranges: vec![selection],
+            primary: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Selection> {
+        self.ranges.iter()
+    }
+
+    pub fn primary(&self) -> Selection {
+        self.ranges[self.primary]
+    }
+
+    // Adds a selection and makes it the primary one.
+    pub fn add(&mut self, selection: Selection) {
+        self.ranges.push(selection);
+        self.primary = self.ranges.len() - 1;
+        self.normalize();
+    }
+
+    // Drops every caret but the primary one.
+    pub fn collapse(&mut self) {
+        *self = Self::single(self.primary());
+    }
+
+    // Sorts the selections and merges the ones that overlap or touch.
+    fn normalize(&mut self) {
+        let primary = self.primary();
+        let mut sorted = std::mem::take(&mut self.ranges);
+        sorted.sort_by_key(|s| (s.start(), s.end()));
+
+        self.primary = 0;
+        for sel in sorted {
+            let is_primary = sel == primary;
+            if let Some(last) = self.ranges.last_mut() {
+                let overlaps = sel.start() < last.end()
+                    || (sel.start() == last.end() && (sel.is_empty() || last.is_empty()));
+                if overlaps {
+                    let (start, end) = (last.start(), last.end().max(sel.end()));
+                    *last = if last.head < last.anchor {
+                        Selection::new(end, start)
+                    } else {
+                        Selection::new(start, end)
+                    };
+                    if is_primary {
+                        self.primary = self.ranges.len() - 1;
+                    }
+                    continue;
+                }
+            }
+            if is_primary {
+                self.primary = self.ranges.len();
+            }
+            self.ranges.push(sel);
+        }
+    }
+
+    // Replaces each selection with the text `edit` returns for it, as one
+    // step of a typing burst, and leaves a caret after every insertion.
+    fn replace_each(
+        &mut self,
+        buffer: &mut Buffer,
+        mut edit: impl FnMut(&Buffer, usize, Selection) -> (Range<usize>, String),
+    ) {
+        let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+        let mut prev_end = 0;
+        for (idx, sel) in self.ranges.iter().enumerate() {
+            let (range, text) = edit(buffer, idx, *sel);
+            // Neighbouring carets may ask to delete the same char.
+            let start = range.start.max(prev_end);
+            let end = range.end.max(start);
+            prev_end = end;
+            edits.push((start..end, text));
+        }
+
+        let mut delta = 0isize;
+        for (sel, (range, text)) in self.ranges.iter_mut().zip(&edits) {
+            let inserted = text.chars().count();
+            let start = (range.start as isize + delta) as usize;
+            *sel = Selection::caret(start + inserted);
+            delta += inserted as isize - range.len() as isize;
+        }
+        buffer.edit_many(edits);
+        self.normalize();
+    }
+
+    pub fn insert(&mut self, buffer: &mut Buffer, text: &str) {
+        self.replace_each(buffer, |_, _, sel| (sel.range(), text.to_string()));
+    }
+
+    // One piece of text per selection, e.g. pasting as many lines as there
+    // are carets.
+    pub fn insert_each(&mut self, buffer: &mut Buffer, texts: &[&str]) {
+        self.replace_each(buffer, |_, idx, sel| {
+            (sel.range(), texts.get(idx).unwrap_or(&"").to_string())
+        });
+    }
+
+    // Indents every caret to its next tab stop.
+    pub fn insert_tab(&mut self, buffer: &mut Buffer, tab_size: usize, spaces: bool) {
+        self.replace_each(buffer, |buffer, _, sel| {
+            let text = if spaces {
+                let col = buffer.display_col(sel.start(), tab_size);
+                " ".repeat(tab_size - col % tab_size)
+            } else {
+                "\t".to_string()
+            };
+            (sel.range(), text)
+        });
+    }
+
+    pub fn backspace(&mut self, buffer: &mut Buffer) {
+        self.replace_each(buffer, |_, _, sel| {
+            if sel.is_empty() {
+                (sel.head.saturating_sub(1)..sel.head, String::new())
+            } else {
+                (sel.range(), String::new())
+            }
+        });
+    }
+
+    pub fn delete_forward(&mut self, buffer: &mut Buffer) {
+        self.replace_each(buffer, |buffer, _, sel| {
+            if sel.is_empty() {
+                (
+                    sel.head..(sel.head + 1).min(buffer.len_chars()),
+                    String::new(),
+                )
+            } else {
+                (sel.range(), String::new())
+            }
+        });
+    }
+
+    // The selected text of every selection, one per line.
+    pub fn copy(&self, buffer: &Buffer) -> String {
+        self.ranges
+            .iter()
+            .map(|sel| buffer.slice(sel.range()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn move_carets(&mut self, buffer: &Buffer, motion: Motion, extend: bool, tab_size: usize) {
+        for sel in &mut self.ranges {
+            // Without Shift, Left/Right first collapse a selection.
+            if !extend && !sel.is_empty() {
+                match motion {
+                    Motion::Left => {
+                        *sel = Selection::caret(sel.start());
+                        continue;
+                    }
+                    Motion::Right => {
+                        *sel = Selection::caret(sel.end());
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            let (line, _) = buffer.char_to_line_col(sel.head);
+            let goal = sel
+                .goal
+                .unwrap_or_else(|| buffer.display_col(sel.head, tab_size));
+            let head = match motion {
+                Motion::Left => sel.head.saturating_sub(1),
+                Motion::Right => (sel.head + 1).min(buffer.len_chars()),
+                Motion::WordLeft => word_boundary_left(buffer, sel.head),
+                Motion::WordRight => word_boundary_right(buffer, sel.head),
+                Motion::Up(n) if line < n => 0,
+                Motion::Up(n) => buffer.display_col_to_char(line - n, goal, tab_size),
+                Motion::Down(n) if line + n >= buffer.len_lines() => buffer.len_chars(),
+                Motion::Down(n) => buffer.display_col_to_char(line + n, goal, tab_size),
+                Motion::LineStart => {
+                    // Like Sublime, Home goes to the indentation first.
+                    let start = buffer.line_to_char(line);
+                    let mut indent = start;
+                    while buffer
+                        .char_at(indent)
+                        .is_some_and(|c| c == ' ' || c == '\t')
+                    {
+                        indent += 1;
+                    }
+                    if sel.head == indent {
+                        start
+                    } else {
+                        indent
+                    }
+                }
+                Motion::LineEnd => buffer.line_col_to_char(line, usize::MAX),
+                Motion::DocStart => 0,
+                Motion::DocEnd => buffer.len_chars(),
+            };
+            *sel = if extend {
+                Selection::new(sel.anchor, head)
+            } else {
+                Selection::caret(head)
+            };
+            if matches!(motion, Motion::Up(_) | Motion::Down(_)) {
+                sel.goal = Some(goal);
+            }
+        }
+        self.normalize();
+    }
+
+    // Moves the primary caret's head, e.g. while dragging the mouse.
+    pub fn set_primary_head(&mut self, head: usize) {
+        let anchor = self.ranges[self.primary].anchor;
+        self.ranges[self.primary] = Selection::new(anchor, head);
+        self.normalize();
+    }
+
+    pub fn select_all(&mut self, buffer: &Buffer) {
+        *self = Self::single(Selection::new(0, buffer.len_chars()));
+    }
+
+    // The line at `pos`, including its line ending.
+    pub fn line_at(buffer: &Buffer, pos: usize) -> Selection {
+        let (line, _) = buffer.char_to_line_col(pos);
+        Selection::new(buffer.line_to_char(line), buffer.line_to_char(line + 1))
+    }
+
+    pub fn word_at(buffer: &Buffer, pos: usize) -> Selection {
+        let mut start = pos;
+        while start > 0 && buffer.char_at(start - 1).is_some_and(is_word_char) {
+            start -= 1;
+        }
+        let mut end = pos;
+        while buffer.char_at(end).is_some_and(is_word_char) {
+            end += 1;
+        }
+        Selection::new(start, end)
+    }
+
+    // Ctrl+D: the first press selects the word under each caret, later ones
+    // add the next occurrence of the primary selection.
+    pub fn add_next_occurrence(&mut self, buffer: &Buffer) {
+        if self.primary().is_empty() {
+            for sel in &mut self.ranges {
+                if sel.is_empty() {
+                    *sel = Self::word_at(buffer, sel.head);
+                }
+            }
+            self.normalize();
+            return;
+        }
+
+        let needle = buffer.slice(self.primary().range());
+        let text = buffer.text();
+        let from = buffer.char_to_byte(self.primary().end());
+        let found = text[from..]
+            .match_indices(&needle)
+            .map(|(i, _)| from + i)
+            .chain(text[..from].match_indices(&needle).map(|(i, _)| i))
+            .map(|byte| buffer.byte_to_char(byte))
+            .find(|&start| {
+                let end = start + needle.chars().count();
+                !self
+                    .ranges
+                    .iter()
+                    .any(|s| s.start() == start && s.end() == end)
+            });
+        if let Some(start) = found {
+            self.add(Selection::new(start, start + needle.chars().count()));
+        }
+    }
+
+    // Alt+F3: selects every occurrence of the primary selection, or of the
+    // word under the caret.
+    pub fn select_all_occurrences(&mut self, buffer: &Buffer) {
+        let primary = if self.primary().is_empty() {
+            Self::word_at(buffer, self.primary().head)
+        } else {
+            self.primary()
+        };
+        if primary.is_empty() {
+            return;
+        }
+        let needle = buffer.slice(primary.range());
+        let len = needle.chars().count();
+        for (byte, _) in buffer.text().match_indices(&needle) {
+            let start = buffer.byte_to_char(byte);
+            self.ranges.push(Selection::new(start, start + len));
+        }
+        self.normalize();
+    }
+
+    // Ctrl+Alt+Up/Down: adds a caret on the line above the topmost caret or
+    // below the bottommost one, in the same column.
+    pub fn add_line(&mut self, buffer: &Buffer, up: bool, tab_size: usize) {
+        let edge = if up {
+            self.ranges.first()
+        } else {
+            self.ranges.last()
+        };
+        let Some(edge) = edge else {
+            return;
+        };
+        let (line, _) = buffer.char_to_line_col(edge.head);
+        let col = buffer.display_col(edge.head, tab_size);
+        let target = if up {
+            line.checked_sub(1)
+        } else {
+            Some(line + 1).filter(|&l| l < buffer.len_lines())
+        };
+        if let Some(target) = target {
+            self.add(Selection::caret(
+                buffer.display_col_to_char(target, col, tab_size),
+            ));
+        }
+    }
+
+    // Ctrl+Shift+L: one selection per line of every multi-line selection.
+    pub fn split_into_lines(&mut self, buffer: &Buffer) {
+        let mut split = Vec::new();
+        for sel in &self.ranges {
+            let (first, _) = buffer.char_to_line_col(sel.start());
+            let (last, last_col) = buffer.char_to_line_col(sel.end());
+            if first == last {
+                split.push(*sel);
+                continue;
+            }
+            for line in first..=last {
+                // A selection ending at the start of a line does not reach
+                // into it.
+                if line == last && last_col == 0 {
+                    break;
+                }
+                let start = sel.start().max(buffer.line_to_char(line));
+                let end = sel.end().min(buffer.line_col_to_char(line, usize::MAX));
+                split.push(Selection::new(start, end));
+            }
+        }
+        self.ranges = split;
+        self.primary = self.ranges.len() - 1;
+        self.normalize();
+    }
+
+    // Alt+drag: a rectangular block from the (line, display column) the drag
+    // started at to where the pointer is. Lines too short to reach the block
+    // are skipped.
+    pub fn column(
+        buffer: &Buffer,
+        from: (usize, usize),
+        to: (usize, usize),
+        tab_size: usize,
+    ) -> Self {
+        let left = from.1.min(to.1);
+        let lines: Vec<usize> = if from.0 <= to.0 {
+            (from.0..=to.0).collect()
+        } else {
+            (to.0..=from.0).rev().collect()
+        };
+
+        let ranges: Vec<Selection> = lines
+            .into_iter()
+            .filter(|&line| {
+                let end = buffer.line_col_to_char(line, usize::MAX);
+                line == to.0 || buffer.display_col(end, tab_size) >= left
+            })
+            .map(|line| {
+                Selection::new(
+                    buffer.display_col_to_char(line, from.1, tab_size),
+                    buffer.display_col_to_char(line, to.1, tab_size),
+                )
+            })
+            .collect();
+        // The line under the pointer comes last and is the primary one.
+        let mut selections = Self {
+            primary: ranges.len() - 1,
+            ranges,
+        };
+        selections.normalize();
+        selections
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(selections: &Selections) -> Vec<(usize, usize)> {
+        selections.iter().map(|s| (s.anchor, s.head)).collect()
+    }
+
+    #[test]
+    fn added_selections_are_sorted_and_the_newest_is_primary() {
+        let mut selections = Selections::single(Selection::caret(10));
+        selections.add(Selection::caret(2));
+        selections.add(Selection::new(5, 7));
+        assert_eq!(ranges(&selections), [(2, 2), (5, 7), (10, 10)]);
+        assert_eq!(selections.primary(), Selection::new(5, 7));
+    }
+
+    #[test]
+    fn overlapping_selections_merge_keeping_their_direction() {
+        let mut selections = Selections::single(Selection::new(6, 2));
+        selections.add(Selection::new(4, 9));
+        assert_eq!(ranges(&selections), [(9, 2)]);
+        assert_eq!(selections.primary(), Selection::new(9, 2));
+
+        let mut selections = Selections::single(Selection::caret(3));
+        selections.add(Selection::caret(3));
+        selections.add(Selection::new(1, 5));
+        assert_eq!(ranges(&selections), [(1, 5)]);
+    }
+
+    #[test]
+    fn touching_selections_stay_apart_unless_one_is_a_caret() {
+        let mut selections = Selections::single(Selection::new(0, 3));
+        selections.add(Selection::new(3, 6));
+        assert_eq!(ranges(&selections), [(0, 3), (3, 6)]);
+        selections.add(Selection::caret(6));
+        assert_eq!(ranges(&selections), [(0, 3), (3, 6)]);
+    }
+
+    #[test]
+    fn typing_at_every_caret() {
+        let mut buffer = Buffer::from("ab\ncd");
+        let mut selections = Selections::single(Selection::caret(0));
+        selections.add(Selection::caret(3));
+        selections.insert(&mut buffer, "x");
+        assert_eq!(buffer.text(), "xab\nxcd");
+        assert_eq!(ranges(&selections), [(1, 1), (5, 5)]);
+
+        selections.backspace(&mut buffer);
+        selections.backspace(&mut buffer);
+        assert_eq!(buffer.text(), "abcd");
+        assert_eq!(ranges(&selections), [(0, 0), (2, 2)]);
+    }
+
+    #[test]
+    fn neighbouring_carets_delete_a_char_once() {
+        let mut buffer = Buffer::from("abc");
+        let mut selections = Selections::single(Selection::caret(1));
+        selections.add(Selection::new(1, 2));
+        selections.delete_forward(&mut buffer);
+        assert_eq!(buffer.text(), "ac");
+        assert_eq!(selections.len(), 1);
+    }
+
+    #[test]
+    fn all_occurrences_of_the_word_at_the_caret() {
+        let buffer = Buffer::from("foo bar foo foobar");
+        let mut selections = Selections::single(Selection::caret(1));
+        selections.select_all_occurrences(&buffer);
+        assert_eq!(ranges(&selections), [(0, 3), (8, 11), (12, 15)]);
+    }
+
+    #[test]
+    fn display_columns_count_tabs_to_the_next_stop() {
+        let buffer = Buffer::from("\tab\tc\nx");
+        let cols: Vec<usize> = (0..6).map(|i| buffer.display_col(i, 4)).collect();
+        assert_eq!(cols, [0, 4, 5, 6, 8, 9]);
+        assert_eq!(buffer.display_col(1, 2), 2);
+
+        assert_eq!(buffer.display_col_to_char(0, 1, 4), 0);
+        assert_eq!(buffer.display_col_to_char(0, 3, 4), 1);
+        assert_eq!(buffer.display_col_to_char(0, 5, 4), 2);
+        assert_eq!(buffer.display_col_to_char(0, 40, 4), 5);
+        assert_eq!(buffer.display_col_to_char(1, 3, 4), 7);
+    }
+
+    #[test]
+    fn up_and_down_keep_the_display_column() {
+        let buffer = Buffer::from("\tx = 1;\nab\n        y\n");
+        let mut selections = Selections::single(Selection::caret(2));
+        // Below `x`, past the end of the short line, then back to column 5.
+        selections.move_carets(&buffer, Motion::Down(1), false, 4);
+        assert_eq!(ranges(&selections), [(10, 10)]);
+        selections.move_carets(&buffer, Motion::Down(1), false, 4);
+        assert_eq!(ranges(&selections), [(16, 16)]);
+        selections.move_carets(&buffer, Motion::Up(2), false, 4);
+        assert_eq!(ranges(&selections), [(2, 2)]);
+
+        // Any other motion forgets the column.
+        selections.move_carets(&buffer, Motion::Left, false, 4);
+        selections.move_carets(&buffer, Motion::Down(2), false, 4);
+        assert_eq!(ranges(&selections), [(15, 15)]);
+    }
+
+    #[test]
+    fn column_blocks_line_up_across_tabs() {
+        let buffer = Buffer::from("\tab\n    cd\nx\n");
+        let selections = Selections::column(&buffer, (0, 4), (2, 6), 4);
+        // The short last line is kept because the pointer is on it.
+        assert_eq!(ranges(&selections), [(1, 3), (8, 10), (12, 12)]);
+    }
+}
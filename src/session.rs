@@ -0,0 +1,172 @@
+use crate::app::{is_untitled, SublimeRustApp};
+use crate::buffer::Buffer;
+use crate::encoding::TextEncoding;
+use crate::search::QueryHistory;
+use crate::settings::config_dir;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+const SESSION_FILE: &str = "Session.sublime_session";
+
+// Where the caret and viewport of a tab were when it was last shown.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct TabView {
+    pub cursor: usize,
+    pub scroll: [f32; 2],
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SessionTab {
+    path: PathBuf,
+    // Content of dirty and untitled buffers, restored on top of the file.
+    unsaved: Option<String>,
+    view: TabView,
+    // Set with View → Syntax.
+    #[serde(default)]
+    syntax: Option<String>,
+    // Set with Reopen or Save with Encoding; detected again otherwise.
+    #[serde(default)]
+    encoding: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    current_dir: Option<PathBuf>,
+    expanded_dirs: Vec<PathBuf>,
+    sidebar_visible: bool,
+    tabs: Vec<SessionTab>,
+    active_tab: Option<usize>,
+    untitled_counter: usize,
+    find_query: String,
+    find_in_files_find_query: String,
+    find_in_files_where_query: String,
+    find_in_files_replace_query: String,
+    find_history: QueryHistory,
+    find_in_files_history: QueryHistory,
+}
+
+fn session_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join(SESSION_FILE))
+}
+
+fn is_results_tab(path: &std::path::Path) -> bool {
+    path.to_str().unwrap_or("").starts_with("find://")
+}
+
+impl Session {
+    pub fn load() -> Option<Self> {
+        let text = fs::read_to_string(session_path()?).ok()?;
+        serde_json::from_str(&text).ok()
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let Some(path) = session_path() else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn capture(app: &SublimeRustApp) -> Self {
+        let mut tabs = Vec::new();
+        let mut active_tab = None;
+        for (idx, path) in app.open_tabs.iter().enumerate() {
+            if is_results_tab(path) {
+                continue;
+            }
+            if Some(idx) == app.active_tab_index {
+                active_tab = Some(tabs.len());
+            }
+            let unsaved = app
+                .tab_contents
+                .get(path)
+                .filter(|_| app.dirty_files.contains(path))
+                .map(|buffer| buffer.text());
+            tabs.push(SessionTab {
+                path: path.clone(),
+                unsaved,
+                view: app.tab_views.get(path).copied().unwrap_or_default(),
+                syntax: app.syntax_overrides.get(path).cloned(),
+                encoding: app
+                    .tab_contents
+                    .get(path)
+                    .map(|buffer| buffer.encoding())
+                    .filter(|encoding| *encoding != TextEncoding::default())
+                    .map(|encoding| encoding.name()),
+            });
+        }
+
+        Self {
+            current_dir: app.current_dir.clone(),
+            expanded_dirs: app.expanded_dirs.iter().cloned().collect(),
+            sidebar_visible: app.sidebar_visible,
+            tabs,
+            active_tab,
+            untitled_counter: app.untitled_counter,
+            find_query: app.find_query.clone(),
+            find_in_files_find_query: app.find_in_files_find_query.clone(),
+            find_in_files_where_query: app.find_in_files_where_query.clone(),
+            find_in_files_replace_query: app.find_in_files_replace_query.clone(),
+            find_history: app.find_history.clone(),
+            find_in_files_history: app.find_in_files_history.clone(),
+        }
+    }
+
+    pub fn restore(self, app: &mut SublimeRustApp) {
+        if let Some(dir) = self.current_dir.filter(|d| d.is_dir()) {
+            app.set_project_dir(dir);
+        }
+        app.expanded_dirs
+            .extend(self.expanded_dirs.into_iter().filter(|d| d.is_dir()));
+        app.sidebar_visible = self.sidebar_visible;
+        app.untitled_counter = self.untitled_counter;
+        app.find_query = self.find_query;
+        app.find_in_files_find_query = self.find_in_files_find_query;
+        app.find_in_files_where_query = self.find_in_files_where_query;
+        app.find_in_files_replace_query = self.find_in_files_replace_query;
+        app.find_history = self.find_history;
+        app.find_in_files_history = self.find_in_files_history;
+
+        for (idx, tab) in self.tabs.into_iter().enumerate() {
+            // Untitled tabs only ever existed in the session, so their name is
+            // not looked up on disk. Files that vanished since the last run are
+            // only kept when they had unsaved changes.
+            let encoding = tab.encoding.as_deref().and_then(TextEncoding::from_name);
+            let read = match encoding {
+                _ if is_untitled(&tab.path) => Ok(Buffer::new()),
+                Some(encoding) => Buffer::from_file_as(&tab.path, encoding.encoding),
+                None => Buffer::from_file(&tab.path),
+            };
+            let mut buffer = match read {
+                Ok(buffer) => buffer,
+                Err(_) if tab.unsaved.is_some() => Buffer::new(),
+                Err(_) => continue,
+            };
+            if let Some(encoding) = encoding {
+                buffer.set_encoding(encoding);
+            }
+            if let Some(text) = &tab.unsaved {
+                buffer.set_text(text);
+            }
+            if buffer.is_dirty() {
+                app.dirty_files.insert(tab.path.clone());
+            }
+            app.tab_contents.insert(tab.path.clone(), buffer);
+            app.tab_views.insert(tab.path.clone(), tab.view);
+            if let Some(syntax) = tab.syntax {
+                app.syntax_overrides.insert(tab.path.clone(), syntax);
+            }
+            app.open_tabs.push(tab.path);
+            if Some(idx) == self.active_tab {
+                app.active_tab_index = Some(app.open_tabs.len() - 1);
+            }
+        }
+        if app.active_tab_index.is_none() && !app.open_tabs.is_empty() {
+            app.active_tab_index = Some(0);
+        }
+    }
+}
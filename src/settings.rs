@@ -0,0 +1,354 @@
+use crate::theme::DEFAULT_THEME;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+pub const SETTINGS_FILE: &str = "Preferences.sublime-settings";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // Path to a .ttf/.otf file; the bundled JetBrains Mono is used when empty.
+    pub font_face: String,
+    pub font_size: f32,
+    pub theme: String,
+    // What "Toggle Light/Dark Color Scheme" switches between.
+    pub light_theme: String,
+    pub dark_theme: String,
+    pub tab_size: usize,
+    pub translate_tabs_to_spaces: bool,
+    pub word_wrap: bool,
+    pub respect_gitignore: bool,
+    // Lines shown around each Find in Files match when context is on.
+    pub find_in_files_context_lines: usize,
+    pub window_size: [f32; 2],
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            font_face: String::new(),
+            font_size: 14.0,
+            theme: DEFAULT_THEME.to_string(),
+            light_theme: "InspiredGitHub".to_string(),
+            dark_theme: DEFAULT_THEME.to_string(),
+            tab_size: 4,
+            translate_tabs_to_spaces: false,
+            word_wrap: false,
+            respect_gitignore: true,
+            find_in_files_context_lines: 2,
+            window_size: [1024.0, 768.0],
+        }
+    }
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("suruc"))
+}
+
+pub fn user_settings_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join(SETTINGS_FILE))
+}
+
+pub fn project_settings_path(project_dir: &Path) -> PathBuf {
+    project_dir.join(".suruc").join(SETTINGS_FILE)
+}
+
+// Writes the defaults to the user settings file if it does not exist yet,
+// so "Preferences → Settings" has something to open.
+pub fn ensure_user_settings() -> Option<PathBuf> {
+    let path = user_settings_path()?;
+    if !path.exists() {
+        fs::create_dir_all(path.parent()?).ok()?;
+        let defaults = serde_json::to_string_pretty(&Settings::default()).ok()?;
+        fs::write(&path, defaults).ok()?;
+    }
+    Some(path)
+}
+
+// Settings files follow Sublime's lenient JSON: comments and trailing commas
+// are allowed.
+pub fn strip_json_extensions(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            if c == '\\' {
+                if let Some(next) = chars.next() {
+                    out.push(next);
+                }
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                out.push(c);
+            }
+            ('/', Some('/')) => {
+                while chars.peek().is_some_and(|&n| n != '\n') {
+                    chars.next();
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = '\0';
+                for next in chars.by_ref() {
+                    if prev == '*' && next == '/' {
+                        break;
+                    }
+                    prev = next;
+                }
+            }
+            (',', _) => {
+                let rest = chars.clone().find(|n| !n.is_whitespace());
+                if !matches!(rest, Some('}') | Some(']')) {
+                    out.push(c);
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+// Where the value of a top-level string `key` sits in a settings file, or
+// None if the key isn't there.
+fn find_string_value(text: &str, key: &str) -> Option<Range<usize>> {
+    let bytes = text.as_bytes();
+    let quoted = format!("\"{}\"", key);
+    let mut depth = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                let end = string_end(bytes, i)?;
+                if depth == 1 && text[i..end] == quoted {
+                    let rest = &text[end..];
+                    let colon = rest.find(|c: char| !c.is_whitespace())?;
+                    if rest[colon..].starts_with(':') {
+                        let after = end + colon + 1;
+                        let value = after + text[after..].find(|c: char| !c.is_whitespace())?;
+                        if bytes[value] != b'"' {
+                            return None;
+                        }
+                        return Some(value..string_end(bytes, value)?);
+                    }
+                }
+                i = end;
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i = text[i..].find('\n').map_or(bytes.len(), |n| i + n);
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = text[i + 2..].find("*/").map_or(bytes.len(), |n| i + n + 4);
+                continue;
+            }
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => depth -= 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+// End (exclusive) of the string literal starting at `start`.
+fn string_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return Some(i + 1),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+// Sets string settings in the user file, editing it in place so comments
+// and formatting survive.
+pub fn set_user_settings(values: &[(&str, &str)]) -> Result<(), String> {
+    let path = ensure_user_settings().ok_or("No settings directory")?;
+    let mut text = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    for (key, value) in values {
+        let literal = Value::from(*value).to_string();
+        match find_string_value(&text, key) {
+            Some(range) => text.replace_range(range, &literal),
+            None => {
+                let open = text.find('{').ok_or("Settings file is not an object")?;
+                text.insert_str(open + 1, &format!("\n    \"{}\": {},", key, literal));
+            }
+        }
+    }
+    fs::write(&path, text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                merge(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+// Applies a settings file key by key, so a mistyped value only loses that
+// one setting. Returns the first key that was rejected.
+fn merge_settings(merged: &mut Value, layer: Value) -> Result<(), String> {
+    let Value::Object(layer) = layer else {
+        return Err("Settings file is not an object".to_string());
+    };
+    let mut error = Ok(());
+    for (key, value) in layer {
+        let mut candidate = merged.clone();
+        merge(&mut candidate[key.as_str()], value);
+        match Settings::deserialize(&candidate) {
+            Ok(_) => *merged = candidate,
+            Err(e) => {
+                if error.is_ok() {
+                    error = Err(format!("\"{}\": {}", key, e));
+                }
+            }
+        }
+    }
+    error
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Builds `Settings` from the defaults, the user file and the open project's
+// file, and notices when either file changes on disk.
+pub struct SettingsLoader {
+    project_dir: Option<PathBuf>,
+    stamps: Vec<(PathBuf, Option<SystemTime>)>,
+    last_poll: Instant,
+}
+
+impl SettingsLoader {
+    pub fn new() -> Self {
+        Self {
+            project_dir: None,
+            stamps: Vec::new(),
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn set_project_dir(&mut self, dir: Option<PathBuf>) {
+        self.project_dir = dir;
+    }
+
+    fn sources(&self) -> Vec<PathBuf> {
+        user_settings_path()
+            .into_iter()
+            .chain(self.project_dir.as_deref().map(project_settings_path))
+            .collect()
+    }
+
+    // Returns the merged settings and the first error, if any. A file that
+    // fails to parse is skipped so the other layers still apply, and a value
+    // of the wrong type leaves that setting as the layers below had it.
+    pub fn load(&mut self) -> (Settings, Option<String>) {
+        let mut merged = serde_json::to_value(Settings::default()).unwrap_or(Value::Null);
+        let mut error = None;
+
+        self.stamps.clear();
+        for path in self.sources() {
+            self.stamps.push((path.clone(), modified(&path)));
+            let Ok(text) = fs::read_to_string(&path) else {
+                continue;
+            };
+            let applied = serde_json::from_str::<Value>(&strip_json_extensions(&text))
+                .map_err(|e| e.to_string())
+                .and_then(|layer| merge_settings(&mut merged, layer));
+            if let Err(e) = applied {
+                error.get_or_insert_with(|| format!("{}: {}", path.display(), e));
+            }
+        }
+
+        match Settings::deserialize(&merged) {
+            Ok(settings) => (settings, error),
+            Err(e) => (
+                Settings::default(),
+                Some(format!("Invalid settings: {}", e)),
+            ),
+        }
+    }
+
+    // Reloads when a settings file was created, changed or removed since the
+    // last load. Checks the disk at most once per `POLL_INTERVAL`.
+    pub fn poll(&mut self) -> Option<(Settings, Option<String>)> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = Instant::now();
+        let changed = self
+            .stamps
+            .iter()
+            .any(|(path, stamp)| modified(path) != *stamp);
+        changed.then(|| self.load())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Value {
+        serde_json::from_str(&strip_json_extensions(text)).unwrap()
+    }
+
+    #[test]
+    fn a_bad_value_only_loses_its_own_setting() {
+        let mut merged = serde_json::to_value(Settings::default()).unwrap();
+        let layer = parse(r#"{"font_size": "big", "tab_size": 2, "word_wrap": true}"#);
+        let error = merge_settings(&mut merged, layer).unwrap_err();
+        assert!(error.starts_with("\"font_size\""), "{}", error);
+
+        let settings = Settings::deserialize(&merged).unwrap();
+        assert_eq!(settings.font_size, Settings::default().font_size);
+        assert_eq!(settings.tab_size, 2);
+        assert!(settings.word_wrap);
+    }
+
+    #[test]
+    fn comments_are_dropped() {
+        let text = r#"{
+            // the size
+            "font_size": 12, /* inline */ "tab_size": 4
+            /* spread
+               over lines */
+        }"#;
+        assert_eq!(
+            parse(text),
+            serde_json::json!({"font_size": 12, "tab_size": 4})
+        );
+    }
+
+    #[test]
+    fn trailing_commas_are_dropped() {
+        assert_eq!(
+            parse("{\"a\": [1, 2, ], \"b\": {\"c\": 3,\n},\n}"),
+            serde_json::json!({"a": [1, 2], "b": {"c": 3}})
+        );
+    }
+
+    #[test]
+    fn strings_are_left_alone() {
+        let text = r#"{"url": "http://a/*b*/", "quote": "\",]", "trail": "x,}"}"#;
+        assert_eq!(strip_json_extensions(text), text);
+    }
+}
@@ -1,6 +1,323 @@
+use crate::buffer::Buffer;
+use crate::settings::config_dir;
+use ignore::WalkBuilder;
 use once_cell::sync::Lazy;
-use syntect::highlighting::ThemeSet;
-use syntect::parsing::SyntaxSet;
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use syntect::parsing::{SyntaxDefinition, SyntaxReference, SyntaxSet};
 
-pub static SYNTAX_SET: Lazy<SyntaxSet> = Lazy::new(SyntaxSet::load_defaults_newlines);
-pub static THEME_SET: Lazy<ThemeSet> = Lazy::new(ThemeSet::load_defaults);
+pub static SYNTAX_SET: Lazy<SyntaxSet> = Lazy::new(|| {
+    let (syntaxes, errors) = load_syntaxes();
+    *LOAD_ERRORS.lock().unwrap() = errors;
+    syntaxes
+});
+static LOAD_ERRORS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// Extra grammars, found anywhere below this directory.
+pub fn packages_dir() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("packages"))
+}
+
+// Grammars from the packages directory that failed to load.
+pub fn load_errors() -> Vec<String> {
+    Lazy::force(&SYNTAX_SET);
+    LOAD_ERRORS.lock().unwrap().clone()
+}
+
+// Names of the syntaxes to choose from, sorted.
+pub fn names() -> Vec<&'static str> {
+    let mut names: Vec<&str> = SYNTAX_SET
+        .syntaxes()
+        .iter()
+        .filter(|s| !s.hidden)
+        .map(|s| s.name.as_str())
+        .collect();
+    names.sort_by_key(|name| name.to_lowercase());
+    names.dedup();
+    names
+}
+
+// Picks the grammar for a file by its full name (`Makefile`, `Cargo.lock`),
+// then by its extensions from the longest (`d.ts`), then by its first line
+// (shebangs, `<?xml` and the like).
+pub fn detect_syntax(path: &Path, buffer: Option<&Buffer>) -> &'static SyntaxReference {
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    let suffixes = std::iter::once(file_name)
+        .chain(
+            file_name
+                .match_indices('.')
+                .map(|(i, _)| &file_name[i + 1..]),
+        )
+        .filter(|suffix| !suffix.is_empty());
+    for suffix in suffixes {
+        if let Some(syntax) = SYNTAX_SET.find_syntax_by_extension(suffix) {
+            return syntax;
+        }
+    }
+    buffer
+        .and_then(|buffer| {
+            let first_line = buffer.slice(0..buffer.line_len(0).min(256));
+            SYNTAX_SET.find_syntax_by_first_line(&first_line)
+        })
+        .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text())
+}
+
+fn load_syntaxes() -> (SyntaxSet, Vec<String>) {
+    let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+    let mut errors = Vec::new();
+
+    let mut files: Vec<PathBuf> = packages_dir()
+        .filter(|dir| dir.is_dir())
+        .map(|dir| {
+            WalkBuilder::new(dir)
+                .standard_filters(false)
+                .build()
+                .flatten()
+                .map(|entry| entry.into_path())
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+
+    for path in files {
+        let name = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .map(|s| s.trim_end_matches(".tmLanguage").to_string());
+        let loaded = match path.extension().and_then(|e| e.to_str()) {
+            Some("sublime-syntax") => fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| {
+                    SyntaxDefinition::load_from_str(&text, true, name.as_deref())
+                        .map_err(|e| e.to_string())
+                }),
+            Some("tmLanguage") => plist::from_file(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|grammar| convert_tm_language(&grammar, name.as_deref())),
+            Some("json") if path.to_string_lossy().ends_with(".tmLanguage.json") => {
+                fs::read_to_string(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|text| serde_json::from_str(&text).map_err(|e| e.to_string()))
+                    .and_then(|grammar| convert_tm_language(&grammar, name.as_deref()))
+            }
+            _ => continue,
+        };
+        match loaded {
+            Ok(syntax) => builder.add(syntax),
+            Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+    (builder.build(), errors)
+}
+
+// TextMate grammars, as a plist or as JSON, are translated into the
+// equivalent sublime-syntax, the same way Sublime treats them. The result
+// goes through syntect's own loader.
+fn convert_tm_language(grammar: &Value, name: Option<&str>) -> Result<SyntaxDefinition, String> {
+    let mut contexts = Map::new();
+    contexts.insert("main".into(), json!(convert_patterns(&grammar["patterns"])));
+    for (key, rule) in grammar["repository"].as_object().into_iter().flatten() {
+        let context = if rule.get("match").is_some() || rule.get("begin").is_some() {
+            convert_rule(rule)
+        } else {
+            convert_patterns(&rule["patterns"])
+        };
+        contexts.entry(key.clone()).or_insert(json!(context));
+    }
+
+    let mut syntax = json!({
+        "name": grammar["name"].as_str().or(name).unwrap_or("Untitled"),
+        "scope": grammar["scopeName"].as_str().ok_or("Missing scopeName")?,
+        "file_extensions": grammar["fileTypes"].as_array().cloned().unwrap_or_default(),
+        "contexts": contexts,
+    });
+    if let Some(first_line) = grammar["firstLineMatch"].as_str() {
+        syntax["first_line_match"] = json!(first_line);
+    }
+    SyntaxDefinition::load_from_str(&to_yaml(&syntax, false), true, name).map_err(|e| e.to_string())
+}
+
+// JSON is valid YAML, except that syntect wants capture groups as integer
+// keys, which JSON cannot have; those are written unquoted.
+fn to_yaml(value: &Value, integer_keys: bool) -> String {
+    match value {
+        Value::Object(map) => {
+            let entries: Vec<String> = map
+                .iter()
+                .map(|(key, value)| {
+                    let key_text = if integer_keys && key.parse::<i64>().is_ok() {
+                        key.clone()
+                    } else {
+                        Value::from(key.as_str()).to_string()
+                    };
+                    format!("{}: {}", key_text, to_yaml(value, key == "captures"))
+                })
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(|item| to_yaml(item, false)).collect();
+            format!("[{}]", items.join(", "))
+        }
+        other => other.to_string(),
+    }
+}
+
+fn convert_patterns(patterns: &Value) -> Vec<Value> {
+    patterns
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(convert_rule)
+        .collect()
+}
+
+fn convert_captures(captures: &Value) -> Value {
+    let names = captures.as_object().into_iter().flatten();
+    Value::Object(
+        names
+            .map(|(group, capture)| (group.clone(), capture["name"].clone()))
+            .filter(|(_, name)| name.is_string())
+            .collect(),
+    )
+}
+
+fn convert_rule(rule: &Value) -> Vec<Value> {
+    if let Some(include) = rule["include"].as_str() {
+        let target = match include {
+            "$self" | "$base" => "main".to_string(),
+            _ => match include.strip_prefix('#') {
+                Some(name) => name.to_string(),
+                None => format!("scope:{}", include),
+            },
+        };
+        return vec![json!({ "include": target })];
+    }
+
+    let mut converted = Map::new();
+    if let Some(regex) = rule["match"].as_str() {
+        converted.insert("match".into(), json!(regex));
+        if let Some(scope) = rule["name"].as_str() {
+            converted.insert("scope".into(), json!(scope));
+        }
+        converted.insert("captures".into(), convert_captures(&rule["captures"]));
+    } else if let Some(begin) = rule["begin"].as_str() {
+        // `while` blocks last as long as each line matches; approximated by
+        // leaving at the first line that doesn't.
+        let end = match (rule["end"].as_str(), rule["while"].as_str()) {
+            (Some(end), _) => end.to_string(),
+            (None, Some(condition)) => format!("^(?!{})", condition),
+            (None, None) => return Vec::new(),
+        };
+        let either = |key: &str| {
+            if rule[key].is_object() {
+                &rule[key]
+            } else {
+                &rule["captures"]
+            }
+        };
+
+        let mut body = Vec::new();
+        if let Some(scope) = rule["name"].as_str() {
+            body.push(json!({ "meta_scope": scope }));
+        }
+        if let Some(scope) = rule["contentName"].as_str() {
+            body.push(json!({ "meta_content_scope": scope }));
+        }
+        let end_rule = json!({
+            "match": end,
+            "captures": convert_captures(either("endCaptures")),
+            "pop": true,
+        });
+        let patterns = convert_patterns(&rule["patterns"]);
+        let end_last = &rule["applyEndPatternLast"];
+        if end_last.as_bool() == Some(true) || end_last.as_i64() == Some(1) {
+            body.extend(patterns);
+            body.push(end_rule);
+        } else {
+            body.push(end_rule);
+            body.extend(patterns);
+        }
+
+        converted.insert("match".into(), json!(begin));
+        converted.insert("captures".into(), convert_captures(either("beginCaptures")));
+        converted.insert("push".into(), json!(body));
+    } else {
+        // Just a group of patterns.
+        return convert_patterns(&rule["patterns"]);
+    }
+    vec![Value::Object(converted)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntect::parsing::{ParseState, Scope, ScopeStack};
+
+    // The scopes at each byte of `line` that has any, in order.
+    fn scopes(syntax: SyntaxDefinition, line: &str) -> Vec<(usize, Vec<Scope>)> {
+        let mut builder = SyntaxSet::new().into_builder();
+        builder.add(syntax);
+        let set = builder.build();
+        let mut state = ParseState::new(&set.syntaxes()[0]);
+        let mut stack = ScopeStack::new();
+        let mut found = Vec::new();
+        for (offset, op) in state.parse_line(line, &set).unwrap() {
+            stack.apply(&op).unwrap();
+            found.push((offset, stack.as_slice().to_vec()));
+        }
+        found
+    }
+
+    fn scope_at(found: &[(usize, Vec<Scope>)], offset: usize) -> Vec<String> {
+        found
+            .iter()
+            .rev()
+            .find(|(at, _)| *at <= offset)
+            .map(|(_, stack)| stack.iter().map(|s| s.build_string()).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn tm_language_captures_are_applied() {
+        let grammar = serde_json::from_str(
+            r#"{
+                "name": "Test",
+                "scopeName": "source.test",
+                "patterns": [
+                    {
+                        "match": "(let) (\\w+)",
+                        "captures": {
+                            "1": { "name": "keyword.test" },
+                            "2": { "name": "variable.test" }
+                        }
+                    },
+                    {
+                        "begin": "(\")",
+                        "end": "(\")",
+                        "name": "string.test",
+                        "beginCaptures": { "1": { "name": "punctuation.begin.test" } },
+                        "endCaptures": { "1": { "name": "punctuation.end.test" } }
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let syntax = convert_tm_language(&grammar, None).unwrap();
+        let found = scopes(syntax, "let x \"s\"\n");
+
+        assert_eq!(scope_at(&found, 0), ["source.test", "keyword.test"]);
+        assert_eq!(scope_at(&found, 4), ["source.test", "variable.test"]);
+        assert_eq!(
+            scope_at(&found, 6),
+            ["source.test", "string.test", "punctuation.begin.test"]
+        );
+        assert_eq!(scope_at(&found, 7), ["source.test", "string.test"]);
+        assert_eq!(
+            scope_at(&found, 8),
+            ["source.test", "string.test", "punctuation.end.test"]
+        );
+    }
+}
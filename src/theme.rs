@@ -0,0 +1,316 @@
+use crate::highlight::to_color32;
+use crate::settings::{config_dir, strip_json_extensions};
+use eframe::egui;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use syntect::highlighting::{
+    Color, FontStyle, ScopeSelectors, StyleModifier, Theme, ThemeItem, ThemeSet, ThemeSettings,
+};
+
+pub const DEFAULT_THEME: &str = "base16-ocean.dark";
+
+static DEFAULT_THEMES: Lazy<ThemeSet> = Lazy::new(ThemeSet::load_defaults);
+static THEMES: Lazy<RwLock<BTreeMap<String, Arc<Theme>>>> =
+    Lazy::new(|| RwLock::new(load_themes().0));
+
+// User color schemes live here, named after their file.
+pub fn themes_dir() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("themes"))
+}
+
+pub fn theme_names() -> Vec<String> {
+    THEMES.read().unwrap().keys().cloned().collect()
+}
+
+pub fn find_theme(name: &str) -> Arc<Theme> {
+    let themes = THEMES.read().unwrap();
+    themes
+        .get(name)
+        .or_else(|| themes.get(DEFAULT_THEME))
+        .cloned()
+        .unwrap_or_default()
+}
+
+// Scans the themes directory again, returning the files that failed to load.
+pub fn reload_themes() -> Vec<String> {
+    let (themes, errors) = load_themes();
+    *THEMES.write().unwrap() = themes;
+    errors
+}
+
+fn load_themes() -> (BTreeMap<String, Arc<Theme>>, Vec<String>) {
+    let mut themes: BTreeMap<_, _> = DEFAULT_THEMES
+        .themes
+        .iter()
+        .map(|(name, theme)| (name.clone(), Arc::new(theme.clone())))
+        .collect();
+    let mut errors = Vec::new();
+    let entries = themes_dir().and_then(|dir| fs::read_dir(dir).ok());
+    for entry in entries.into_iter().flatten().flatten() {
+        let path = entry.path();
+        let loaded = match path.extension().and_then(|e| e.to_str()) {
+            Some("tmTheme") => ThemeSet::get_theme(&path).map_err(|e| e.to_string()),
+            Some("sublime-color-scheme") => load_color_scheme(&path),
+            _ => continue,
+        };
+        let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("?");
+        match loaded {
+            Ok(theme) => {
+                themes.insert(name.to_string(), Arc::new(theme));
+            }
+            Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+    (themes, errors)
+}
+
+// Sublime's JSON color schemes: `variables`, `globals` for the editor colors
+// and `rules` mapping scopes to styles.
+fn load_color_scheme(path: &Path) -> Result<Theme, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let scheme: Value =
+        serde_json::from_str(&strip_json_extensions(&text)).map_err(|e| e.to_string())?;
+
+    let variables: HashMap<String, String> = scheme["variables"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(k, v)| Some((k.clone(), v.as_str()?.to_string())))
+        .collect();
+    let color = |value: &Value| parse_color(value.as_str()?, &variables, 0);
+
+    let globals = &scheme["globals"];
+    let global = |key: &str| color(&globals[key]);
+    let settings = ThemeSettings {
+        foreground: global("foreground"),
+        background: global("background"),
+        caret: global("caret"),
+        line_highlight: global("line_highlight"),
+        accent: global("accent"),
+        gutter: global("gutter"),
+        gutter_foreground: global("gutter_foreground"),
+        selection: global("selection"),
+        selection_foreground: global("selection_foreground"),
+        selection_border: global("selection_border"),
+        inactive_selection: global("inactive_selection"),
+        find_highlight: global("find_highlight"),
+        find_highlight_foreground: global("find_highlight_foreground"),
+        highlight: global("highlight"),
+        guide: global("guide"),
+        active_guide: global("active_guide"),
+        ..ThemeSettings::default()
+    };
+
+    let mut scopes = Vec::new();
+    for rule in scheme["rules"].as_array().into_iter().flatten() {
+        let Some(Ok(scope)) = rule["scope"].as_str().map(ScopeSelectors::from_str) else {
+            continue;
+        };
+        let font_style = rule["font_style"].as_str().map(|styles| {
+            styles
+                .split_whitespace()
+                .fold(FontStyle::empty(), |style, name| match name {
+                    "bold" => style | FontStyle::BOLD,
+                    "italic" => style | FontStyle::ITALIC,
+                    "underline" => style | FontStyle::UNDERLINE,
+                    _ => style,
+                })
+        });
+        scopes.push(ThemeItem {
+            scope,
+            style: StyleModifier {
+                foreground: color(&rule["foreground"]),
+                background: color(&rule["background"]),
+                font_style,
+            },
+        });
+    }
+
+    Ok(Theme {
+        name: scheme["name"].as_str().map(str::to_string),
+        author: scheme["author"].as_str().map(str::to_string),
+        settings,
+        scopes,
+    })
+}
+
+// Hex, `rgb()`/`rgba()`, `hsl()`/`hsla()`, a few names and `var()`. The
+// `color()` adjusters are not supported; such entries are left out.
+fn parse_color(text: &str, variables: &HashMap<String, String>, depth: usize) -> Option<Color> {
+    let text = text.trim();
+    if let Some(name) = text.strip_prefix("var(").and_then(|t| t.strip_suffix(')')) {
+        let value = variables.get(name.trim())?;
+        return (depth < 8).then(|| parse_color(value, variables, depth + 1))?;
+    }
+    if let Some(hex) = text.strip_prefix('#') {
+        let digit = |i: usize| u8::from_str_radix(hex.get(i..i + 1)?, 16).ok();
+        let pair = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        let (r, g, b, a) = match hex.len() {
+            3 | 4 => (
+                digit(0)? * 17,
+                digit(1)? * 17,
+                digit(2)? * 17,
+                if hex.len() == 4 { digit(3)? * 17 } else { 255 },
+            ),
+            6 | 8 => (
+                pair(0)?,
+                pair(2)?,
+                pair(4)?,
+                if hex.len() == 8 { pair(6)? } else { 255 },
+            ),
+            _ => return None,
+        };
+        return Some(Color { r, g, b, a });
+    }
+    if let Some((function, args)) = text.strip_suffix(')').and_then(|t| t.split_once('(')) {
+        let args: Vec<&str> = args.split(',').map(str::trim).collect();
+        let alpha = match args.get(3) {
+            Some(a) => (a.parse::<f32>().ok()? * 255.0).round() as u8,
+            None => 255,
+        };
+        let number = |i: usize| args.get(i)?.trim_end_matches('%').parse::<f32>().ok();
+        let (r, g, b) = match (function.trim(), args.len()) {
+            ("rgb" | "rgba", 3 | 4) => (number(0)?, number(1)?, number(2)?),
+            ("hsl" | "hsla", 3 | 4) => {
+                hsl_to_rgb(number(0)?, number(1)? / 100.0, number(2)? / 100.0)
+            }
+            _ => return None,
+        };
+        return Some(Color {
+            r: r.round() as u8,
+            g: g.round() as u8,
+            b: b.round() as u8,
+            a: alpha,
+        });
+    }
+    match text {
+        "white" => Some(Color::WHITE),
+        "black" => Some(Color::BLACK),
+        "transparent" => Some(Color {
+            a: 0,
+            ..Color::BLACK
+        }),
+        _ => None,
+    }
+}
+
+fn hsl_to_rgb(hue: f32, saturation: f32, lightness: f32) -> (f32, f32, f32) {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let h = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    ((r + m) * 255.0, (g + m) * 255.0, (b + m) * 255.0)
+}
+
+// Colors for everything around the text, derived from the color scheme so
+// panels, tabs and the gutter match the editor.
+#[derive(Clone, Copy)]
+pub struct Chrome {
+    pub dark: bool,
+    pub background: egui::Color32,
+    pub foreground: egui::Color32,
+    // Sidebar, tab bar and other panels.
+    pub panel: egui::Color32,
+    pub weak: egui::Color32,
+    pub accent: egui::Color32,
+    pub selection: egui::Color32,
+    pub caret: egui::Color32,
+    // Find matches, drawn translucent behind the text.
+    pub find_highlight: egui::Color32,
+    pub gutter: egui::Color32,
+    pub gutter_foreground: egui::Color32,
+}
+
+fn mix(a: egui::Color32, b: egui::Color32, t: f32) -> egui::Color32 {
+    let channel = |x: u8, y: u8| (x as f32 + (y as f32 - x as f32) * t).round() as u8;
+    egui::Color32::from_rgb(
+        channel(a.r(), b.r()),
+        channel(a.g(), b.g()),
+        channel(a.b(), b.b()),
+    )
+}
+
+impl Chrome {
+    pub fn from_theme(theme: &Theme) -> Self {
+        let settings = &theme.settings;
+        let background = settings
+            .background
+            .map_or(egui::Color32::from_rgb(0x1e, 0x1e, 0x1e), to_color32);
+        let [r, g, b, _] = background.to_array();
+        let luma = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+        let dark = luma < 128.0;
+        let foreground = settings.foreground.map_or(
+            if dark {
+                egui::Color32::from_rgb(0xcc, 0xcc, 0xcc)
+            } else {
+                egui::Color32::from_rgb(0x33, 0x33, 0x33)
+            },
+            to_color32,
+        );
+        Self {
+            dark,
+            background,
+            foreground,
+            panel: mix(background, egui::Color32::BLACK, 0.15),
+            weak: mix(foreground, background, 0.5),
+            accent: settings
+                .accent
+                .map_or(egui::Color32::from_rgb(0x4e, 0x9f, 0xff), to_color32),
+            selection: settings
+                .selection
+                .map_or(mix(background, foreground, 0.25), to_color32),
+            caret: settings.caret.map_or(foreground, to_color32),
+            find_highlight: settings
+                .find_highlight
+                .map_or(egui::Color32::from_rgb(0xff, 0xe7, 0x92), to_color32),
+            gutter: settings.gutter.map_or(background, to_color32),
+            gutter_foreground: settings
+                .gutter_foreground
+                .map_or(mix(foreground, background, 0.6), to_color32),
+        }
+    }
+
+    pub fn visuals(&self) -> egui::Visuals {
+        let mut visuals = if self.dark {
+            egui::Visuals::dark()
+        } else {
+            egui::Visuals::light()
+        };
+        visuals.window_fill = mix(self.panel, self.foreground, 0.05);
+        visuals.panel_fill = self.panel;
+        visuals.extreme_bg_color = self.background;
+        visuals.faint_bg_color = mix(self.panel, self.foreground, 0.03);
+
+        visuals.widgets.noninteractive.bg_fill = self.panel;
+        visuals.widgets.inactive.bg_fill = egui::Color32::TRANSPARENT;
+        visuals.widgets.hovered.bg_fill = mix(self.panel, self.foreground, 0.15);
+        visuals.widgets.active.bg_fill = mix(self.panel, self.foreground, 0.1);
+
+        visuals.widgets.noninteractive.fg_stroke = egui::Stroke::new(1.0, self.foreground);
+        visuals.widgets.inactive.fg_stroke = egui::Stroke::new(1.0, self.foreground);
+
+        visuals.selection.bg_fill = self.selection;
+        visuals.text_cursor.color = self.caret;
+        visuals.hyperlink_color = self.accent;
+        visuals
+    }
+}
+
+impl Default for Chrome {
+    fn default() -> Self {
+        Self::from_theme(&find_theme(DEFAULT_THEME))
+    }
+}
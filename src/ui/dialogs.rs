@@ -43,3 +43,25 @@ pub fn render_close_confirmation(app: &mut SublimeRustApp, ctx: &egui::Context)
         }
     }
 }
+
+pub fn render_about(app: &mut SublimeRustApp, ctx: &egui::Context) {
+    let mut open = app.about_visible;
+    egui::Window::new("About SuRuC")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.heading("SuRuC");
+                ui.label(format!("Version {}", env!("CARGO_PKG_VERSION")));
+                ui.add_space(10.0);
+                ui.label("A Sublime Text style editor written in Rust.");
+                ui.add_space(10.0);
+                if ui.button("Close").clicked() {
+                    app.about_visible = false;
+                }
+            });
+        });
+    app.about_visible &= open;
+}
@@ -1,9 +1,12 @@
 use crate::app::SublimeRustApp;
-use crate::syntax::{SYNTAX_SET, THEME_SET};
+use crate::buffer::Buffer;
+use crate::replace_in_files::PREVIEW_TAB;
+use crate::selection::{Selection, Selections};
+use crate::ui::find_results::render_find_results;
+use crate::ui::replace_preview::render_replace_preview;
+use crate::ui::text_view::{Decorations, TextView};
 use eframe::egui;
-use syntect::easy::HighlightLines;
-use syntect::highlighting::Style;
-use syntect::util::LinesWithEndings;
+use std::ops::Range;
 
 pub fn render_editor_pane(app: &mut SublimeRustApp, ui: &mut egui::Ui) {
     // ── Tab Bar ──────────────────────────────────────────
@@ -29,14 +32,14 @@ pub fn render_editor_pane(app: &mut SublimeRustApp, ui: &mut egui::Ui) {
                     };
 
                     let bg_color = if is_active {
-                        egui::Color32::from_rgb(0x23, 0x23, 0x23)
+                        app.chrome.background
                     } else {
-                        egui::Color32::from_rgb(0x18, 0x18, 0x18)
+                        app.chrome.panel
                     };
                     let text_color = if is_active {
-                        egui::Color32::from_rgb(0xcc, 0xcc, 0xcc)
+                        app.chrome.foreground
                     } else {
-                        egui::Color32::from_rgb(0x88, 0x88, 0x88)
+                        app.chrome.weak
                     };
 
                     let response = ui.add(
@@ -80,148 +83,74 @@ pub fn render_editor_pane(app: &mut SublimeRustApp, ui: &mut egui::Ui) {
     // ── Editor Pane ──────────────────────────────────────
     if let Some(idx) = app.active_tab_index {
         if let Some(path) = app.open_tabs.get(idx).cloned() {
-            if path.to_str().unwrap_or("").starts_with("find://") {
-                egui::ScrollArea::both()
-                    .id_source("find_results_scroll")
-                    .show(ui, |ui| {
-                        // Handle PageUp/PageDown for scrolling
-                        let page_height = ui.available_height();
-                        if ui.input(|i| i.key_pressed(egui::Key::PageUp)) {
-                            ui.scroll_with_delta(egui::vec2(0.0, page_height));
-                        }
-                        if ui.input(|i| i.key_pressed(egui::Key::PageDown)) {
-                            ui.scroll_with_delta(egui::vec2(0.0, -page_height));
-                        }
-
-                        let title = path.to_str().unwrap_or("").replace("find://", "");
-                        ui.label(
-                            egui::RichText::new(title)
-                                .font(egui::TextStyle::Monospace.resolve(ui.style()))
-                                .strong(),
-                        );
-                        ui.separator();
-                        if let Some(results) = &app.find_in_files_results {
-                            ui.add(
-                                egui::TextEdit::multiline(&mut results.clone())
-                                    .font(egui::TextStyle::Monospace)
-                                    .desired_width(f32::INFINITY),
-                            );
-                        }
-                    });
-            } else if let Some(content) = app.tab_contents.get_mut(&path) {
-                egui::ScrollArea::both() // Changed to both() for horizontal scrolling
-                    .id_source("editor_scroll")
-                    .show(ui, |ui| {
-                        // Handle PageUp/PageDown for scrolling
-                        let is_focused =
-                            ui.memory(|mem| mem.has_focus(egui::Id::new("main_editor")));
-                        if is_focused {
-                            let page_height = ui.available_height();
-                            if ui.input(|i| i.key_pressed(egui::Key::PageUp)) {
-                                ui.scroll_with_delta(egui::vec2(0.0, page_height));
-                            }
-                            if ui.input(|i| i.key_pressed(egui::Key::PageDown)) {
-                                ui.scroll_with_delta(egui::vec2(0.0, -page_height));
-                            }
-                        }
-
-                        ui.horizontal_top(|ui| {
-                            let line_count = content.chars().filter(|&c| c == '\n').count() + 1;
-                            let mut line_numbers = String::new();
-                            for i in 1..=line_count {
-                                line_numbers.push_str(&format!("{:>3}\n", i));
-                            }
-
-                            ui.add(egui::Label::new(
-                                egui::RichText::new(line_numbers)
-                                    .font(egui::TextStyle::Monospace.resolve(ui.style()))
-                                    .color(egui::Color32::from_rgb(0x55, 0x55, 0x55)),
-                            ));
-
-                            let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-                            let syntax = SYNTAX_SET
-                                .find_syntax_by_extension(extension)
-                                .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text());
-                            let theme = &THEME_SET.themes["base16-ocean.dark"];
-
-                            let mut layouter = |ui: &egui::Ui, string: &str, _wrap_width: f32| {
-                                let mut job = egui::text::LayoutJob::default();
-                                job.wrap.break_anywhere = false; // Disable wrapping
-                                job.wrap.max_width = f32::INFINITY;
-                                // job.wrap.overflow_gaps = true; // No longer needed
-                                // job.wrap.word_wrap = false; // No longer needed
-
-                                let mut highlighter = HighlightLines::new(syntax, theme);
-                                for line in LinesWithEndings::from(string) {
-                                    let ranges: Vec<(Style, &str)> =
-                                        highlighter.highlight_line(line, &SYNTAX_SET).unwrap();
-                                    for (style, text) in ranges {
-                                        let color = egui::Color32::from_rgba_unmultiplied(
-                                            style.foreground.r,
-                                            style.foreground.g,
-                                            style.foreground.b,
-                                            style.foreground.a,
-                                        );
-                                        job.append(
-                                            text,
-                                            0.0,
-                                            egui::TextFormat {
-                                                font_id: egui::TextStyle::Monospace
-                                                    .resolve(ui.style()),
-                                                color,
-                                                ..Default::default()
-                                            },
-                                        );
-                                    }
-                                }
-                                ui.fonts(|f| f.layout_job(job))
-                            };
-
-                            let min_height = ui.available_height();
-                            let output = egui::TextEdit::multiline(content)
-                                .id(egui::Id::new("main_editor"))
-                                .code_editor()
-                                .font(egui::TextStyle::Monospace) // Use monospace font
-                                .desired_width(f32::INFINITY) // Let it be as wide as content
-                                .min_size(egui::vec2(0.0, min_height))
-                                .lock_focus(true)
-                                .layouter(&mut layouter)
-                                .show(ui);
-
-                            if output.response.changed() {
-                                app.dirty_files.insert(path.clone());
-                            }
-
-                            if app.find_scroll_requested {
-                                if let Some(range) = output.cursor_range {
-                                    let rect = output.galley.pos_from_cursor(&range.primary);
-                                    ui.scroll_to_rect(
-                                        rect.translate(output.galley_pos.to_vec2()),
-                                        Some(egui::Align::Center),
-                                    );
-                                    app.find_scroll_requested = false;
-                                }
-                            }
-
-                            if let Some(range) = output.cursor_range {
-                                let char_idx = range.primary.ccursor.index;
-                                let mut line = 1;
-                                let mut col = 1;
-                                for (i, c) in content.chars().enumerate() {
-                                    if i >= char_idx {
-                                        break;
-                                    }
-                                    if c == '\n' {
-                                        line += 1;
-                                        col = 1;
-                                    } else {
-                                        col += 1;
-                                    }
-                                }
-                                app.cursor_pos = (line, col);
-                            }
-                        });
-                    });
+            if path.as_os_str() == PREVIEW_TAB {
+                render_replace_preview(app, ui);
+            } else if path.to_str().unwrap_or("").starts_with("find://") {
+                render_find_results(app, ui);
+            } else if app.tab_contents.contains_key(&path) {
+                if app.find_active {
+                    app.refresh_find_when_idle(ui.ctx());
+                }
+                let decorations = find_decorations(app);
+                let syntax = app.syntax_for(&path);
+                let content = app.tab_contents.get_mut(&path).unwrap();
+                // All tabs share one editor, so put back the caret and
+                // viewport this tab had when switching to it.
+                let switched = app.viewed_tab.as_ref() != Some(&path);
+                let view = app.tab_views.get(&path).copied().unwrap_or_default();
+                if switched {
+                    app.viewed_tab = Some(path.clone());
+                    app.selections = Selections::single(Selection::caret(view.cursor));
+                }
+
+                let ctx = ui.ctx().clone();
+                let highlighter = &mut app.highlighter;
+                let theme_name = &app.settings.theme;
+                let mut highlight = |buffer: &Buffer, lines: Range<usize>| {
+                    highlighter.styles(&ctx, &path, buffer, syntax, theme_name, lines)
+                };
+                let (matches, current) = if app.find_active {
+                    (&app.find_matches[..], app.current_match_index)
+                } else {
+                    (&[][..], None)
+                };
+                let color = app.chrome.find_highlight;
+                let mut find_backgrounds =
+                    |visible: Range<usize>| find_highlights(matches, current, color, visible);
+
+                let mut text_view =
+                    TextView::new(egui::Id::new("main_editor"), content, &mut app.selections)
+                        .highlighter(&mut highlight)
+                        .backgrounds(&mut find_backgrounds)
+                        .decorations(&decorations)
+                        .colors(app.chrome.background, app.chrome.foreground)
+                        .gutter_colors(app.chrome.gutter, app.chrome.gutter_foreground)
+                        .word_wrap(app.settings.word_wrap)
+                        .tabs(app.settings.tab_size, app.settings.translate_tabs_to_spaces)
+                        .center_on_caret(app.find_scroll_requested);
+                if switched {
+                    text_view = text_view.scroll_offset(egui::vec2(view.scroll[0], view.scroll[1]));
+                }
+                let output = text_view.show(ui);
+                app.find_scroll_requested = false;
+
+                if output.changed {
+                    // Find matches are refreshed once typing pauses.
+                    if app.find_active {
+                        ctx.request_repaint();
+                    }
+                    if content.is_dirty() {
+                        app.dirty_files.insert(path.clone());
+                    } else {
+                        app.dirty_files.remove(&path);
+                    }
+                }
+                let head = app.selections.primary().head;
+                let tab_view = app.tab_views.entry(path.clone()).or_default();
+                tab_view.cursor = head;
+                tab_view.scroll = [output.scroll_offset.x, output.scroll_offset.y];
+                let (line, col) = content.char_to_line_col(head);
+                app.cursor_pos = (line + 1, col + 1);
             }
         }
     } else {
@@ -230,3 +159,41 @@ pub fn render_editor_pane(app: &mut SublimeRustApp, ui: &mut egui::Ui) {
         });
     }
 }
+
+// The find matches in the visible chars, the current one more strongly.
+// `matches` are sorted and do not overlap.
+fn find_highlights(
+    matches: &[Range<usize>],
+    current: Option<usize>,
+    color: egui::Color32,
+    visible: Range<usize>,
+) -> Vec<(Range<usize>, egui::Color32)> {
+    let first = matches.partition_point(|m| m.end < visible.start);
+    matches[first..]
+        .iter()
+        .enumerate()
+        .take_while(|(_, range)| range.start <= visible.end)
+        .map(|(idx, range)| {
+            let strength = if Some(first + idx) == current {
+                0.7
+            } else {
+                0.3
+            };
+            (range.clone(), color.gamma_multiply(strength))
+        })
+        .collect()
+}
+
+// A tick on the scroll bar for each line with a find match.
+fn find_decorations(app: &SublimeRustApp) -> Decorations {
+    let mut decorations = Decorations::default();
+    if app.find_active {
+        let color = app.chrome.find_highlight;
+        decorations.scrollbar_marks = app
+            .find_match_lines
+            .iter()
+            .map(|&line| (line, color))
+            .collect();
+    }
+    decorations
+}
@@ -18,9 +18,9 @@ pub fn render_project_explorer(app: &mut SublimeRustApp, ui: &mut egui::Ui, path
     };
 
     let text_color = if is_ignored {
-        egui::Color32::from_gray(100)
+        app.chrome.weak
     } else {
-        egui::Color32::from_rgb(0xcc, 0xcc, 0xcc)
+        app.chrome.foreground
     };
 
     let response = egui::CollapsingHeader::new(egui::RichText::new(&dir_name).color(text_color))
@@ -63,9 +63,9 @@ pub fn render_project_explorer(app: &mut SublimeRustApp, ui: &mut egui::Ui, path
                     };
 
                     let entry_text_color = if is_entry_ignored {
-                        egui::Color32::from_gray(100)
+                        app.chrome.weak
                     } else {
-                        egui::Color32::from_rgb(0xcc, 0xcc, 0xcc)
+                        app.chrome.foreground
                     };
 
                     if is_dir {
@@ -88,16 +88,9 @@ pub fn render_project_explorer(app: &mut SublimeRustApp, ui: &mut egui::Ui, path
                                 .sense(egui::Sense::click()),
                             )
                             .clicked()
+                            && app.open_path(entry_path.clone())
                         {
-                            if let Some(pos) = app.open_tabs.iter().position(|p| p == &entry_path) {
-                                app.active_tab_index = Some(pos);
-                            } else {
-                                if let Ok(content) = fs::read_to_string(&entry_path) {
-                                    app.tab_contents.insert(entry_path.clone(), content);
-                                    app.open_tabs.push(entry_path.clone());
-                                    app.active_tab_index = Some(app.open_tabs.len() - 1);
-                                }
-                            }
+                            app.recent.add_file(&entry_path);
                         }
                     }
                 }
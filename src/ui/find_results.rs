@@ -0,0 +1,158 @@
+use crate::app::SublimeRustApp;
+use eframe::egui;
+
+enum Row {
+    File(usize),
+    Line(usize, usize),
+    // Between hunks of context that are not adjacent.
+    Divider,
+    Gap,
+}
+
+// The Find in Files results tab: a header per file and its matching lines,
+// with the matches highlighted. Double-clicking a line opens it.
+pub fn render_find_results(app: &mut SublimeRustApp, ui: &mut egui::Ui) {
+    let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+    ui.label(
+        egui::RichText::new(app.find_in_files_summary())
+            .font(font_id.clone())
+            .strong(),
+    );
+    ui.separator();
+
+    // Flattened so only the rows in view are laid out.
+    let context = app
+        .find_in_files_job
+        .as_ref()
+        .is_some_and(|job| job.context > 0);
+    let mut rows = Vec::new();
+    for (f, file) in app.find_in_files_results.iter().enumerate() {
+        rows.push(Row::File(f));
+        for (l, line) in file.lines.iter().enumerate() {
+            let after_gap = l > 0 && file.lines[l - 1].line + 1 != line.line;
+            if context && after_gap {
+                rows.push(Row::Divider);
+            }
+            rows.push(Row::Line(f, l));
+        }
+        rows.push(Row::Gap);
+    }
+    let digits = app
+        .find_in_files_results
+        .iter()
+        .flat_map(|file| file.lines.last())
+        .map(|line| (line.line + 1).to_string().len())
+        .max()
+        .unwrap_or(1);
+
+    let chrome = app.chrome;
+    let row_height = ui.fonts(|f| f.row_height(&font_id));
+    let mut opened = None;
+    egui::ScrollArea::both()
+        .id_source("find_results_scroll")
+        .auto_shrink([false, false])
+        .show_rows(ui, row_height, rows.len(), |ui, visible| {
+            // Handle PageUp/PageDown for scrolling
+            let page_height = ui.clip_rect().height();
+            if ui.input(|i| i.key_pressed(egui::Key::PageUp)) {
+                ui.scroll_with_delta(egui::vec2(0.0, page_height));
+            }
+            if ui.input(|i| i.key_pressed(egui::Key::PageDown)) {
+                ui.scroll_with_delta(egui::vec2(0.0, -page_height));
+            }
+
+            for row in &rows[visible] {
+                match *row {
+                    Row::File(f) => {
+                        let path = &app.find_in_files_results[f].path;
+                        ui.label(
+                            egui::RichText::new(format!("{}:", path.display()))
+                                .font(font_id.clone())
+                                .color(chrome.accent)
+                                .strong(),
+                        );
+                    }
+                    Row::Line(f, l) => {
+                        let line = &app.find_in_files_results[f].lines[l];
+                        let current = app
+                            .find_in_files_current
+                            .is_some_and(|(cf, cl, _)| (cf, cl) == (f, l));
+                        let mut job = egui::text::LayoutJob::default();
+                        let separator = if line.matches.is_empty() { ' ' } else { ':' };
+                        job.append(
+                            &format!(
+                                "{:>width$}{} ",
+                                line.line + 1,
+                                separator,
+                                width = digits + 2
+                            ),
+                            0.0,
+                            egui::TextFormat::simple(font_id.clone(), chrome.weak),
+                        );
+                        let mut pos = 0;
+                        for range in &line.matches {
+                            job.append(
+                                &line.text[pos..range.start],
+                                0.0,
+                                egui::TextFormat::simple(font_id.clone(), chrome.foreground),
+                            );
+                            job.append(
+                                &line.text[range.clone()],
+                                0.0,
+                                egui::TextFormat {
+                                    background: chrome.find_highlight.gamma_multiply(0.4),
+                                    ..egui::TextFormat::simple(font_id.clone(), chrome.foreground)
+                                },
+                            );
+                            pos = range.end;
+                        }
+                        job.append(
+                            &line.text[pos..],
+                            0.0,
+                            egui::TextFormat::simple(font_id.clone(), chrome.foreground),
+                        );
+
+                        // Reserved so the current line's background goes under
+                        // its text.
+                        let background = ui.painter().add(egui::Shape::Noop);
+                        let response = ui.add(
+                            egui::Label::new(job)
+                                .wrap(false)
+                                .selectable(false)
+                                .sense(egui::Sense::click()),
+                        );
+                        if current {
+                            let rect = response.rect.with_max_x(ui.max_rect().right());
+                            ui.painter().set(
+                                background,
+                                egui::Shape::rect_filled(rect, 0.0, chrome.selection),
+                            );
+                        }
+                        if response.hovered() {
+                            ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+                        }
+                        if response.clicked() {
+                            app.find_in_files_current = Some((f, l, 0));
+                        }
+                        if response.double_clicked() {
+                            opened = Some((f, l));
+                        }
+                    }
+                    Row::Divider => {
+                        ui.label(
+                            egui::RichText::new(format!("{:>width$}", "..", width = digits + 2))
+                                .font(font_id.clone())
+                                .color(chrome.weak),
+                        );
+                    }
+                    Row::Gap => {
+                        ui.label(egui::RichText::new(" ").font(font_id.clone()));
+                    }
+                }
+            }
+        });
+
+    if let Some((file, line)) = opened {
+        app.open_result(file, line, 0);
+    }
+}
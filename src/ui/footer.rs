@@ -1,26 +1,9 @@
 use crate::app::SublimeRustApp;
-use crate::syntax::SYNTAX_SET;
+use crate::search::{self, QueryHistory};
+use crate::ui::menu::render_encoding_menus;
 use eframe::egui;
-use egui::{FontData, FontDefinitions, FontFamily};
 
 pub fn render_footer(app: &mut SublimeRustApp, ctx: &egui::Context) {
-    let mut fonts = FontDefinitions::default();
-
-    fonts.font_data.insert(
-        "unicode_font".to_owned(),
-        FontData::from_static(include_bytes!(concat!(
-            env!("CARGO_MANIFEST_DIR"),
-            "/assets/JetBrainsMonoNerdFont-Regular.ttf"
-        ))),
-    );
-    fonts
-        .families
-        .entry(FontFamily::Proportional)
-        .or_default()
-        .insert(0, "unicode_font".to_owned());
-
-    ctx.set_fonts(fonts);
-
     egui::TopBottomPanel::bottom("footer").show(ctx, |ui| {
         ui.add_space(3.0);
         ui.horizontal(|ui| {
@@ -35,21 +18,51 @@ pub fn render_footer(app: &mut SublimeRustApp, ctx: &egui::Context) {
             if app.find_in_files_active {
                 let remaining_width = ui.available_width() - icon_button_width * 13.0;
                 ui.vertical(|ui| {
+                    let mut field_left = 0.0;
                     ui.horizontal(|ui| {
-                        ui.label("Find :  ");
-                        ui.add(
+                        let options = &mut app.find_in_files_options;
+                        option_toggle(ui, &mut options.regex, "\u{f0451}", "Regular expression");
+                        option_toggle(
+                            ui,
+                            &mut options.case_sensitive,
+                            "\u{f0b34}",
+                            "Case sensitive",
+                        );
+                        option_toggle(ui, &mut options.whole_word, "\u{f0fb9}", "Whole word");
+                        option_toggle(
+                            ui,
+                            &mut app.find_in_files_show_context,
+                            "\u{f054f}",
+                            "Show context",
+                        );
+                        field_left = ui.label("Find :  ").rect.left();
+                        let find_id = ui.make_persistent_id("find_in_files_input");
+                        browse_history(
+                            ui,
+                            find_id,
+                            &mut app.find_in_files_history,
+                            &mut app.find_in_files_find_query,
+                        );
+                        let response = ui.add(
                             egui::TextEdit::singleline(&mut app.find_in_files_find_query)
+                                .id(find_id)
                                 .desired_width(remaining_width),
                         );
+                        if response.changed() {
+                            app.find_in_files_history.stop_browsing();
+                        }
                     });
                     ui.horizontal(|ui| {
+                        ui.add_space(field_left - ui.cursor().left());
                         ui.label("Where:  ");
                         ui.add(
                             egui::TextEdit::singleline(&mut app.find_in_files_where_query)
+                                .hint_text("<open folders>, *.rs, -*/target/*, <open files>")
                                 .desired_width(remaining_width),
                         );
                     });
                     ui.horizontal(|ui| {
+                        ui.add_space(field_left - ui.cursor().left());
                         ui.label("Replace:");
                         ui.add(
                             egui::TextEdit::singleline(&mut app.find_in_files_replace_query)
@@ -62,8 +75,12 @@ pub fn render_footer(app: &mut SublimeRustApp, ctx: &egui::Context) {
                     .num_columns(2)
                     .spacing([8.0, 4.0])
                     .show(ui, |ui| {
-                        if ui.button("Search ").clicked() {
-                            app.perform_find_in_files();
+                        if app.find_in_files_running() {
+                            if ui.button("Cancel\u{eba5} ").clicked() {
+                                app.cancel_find_in_files();
+                            }
+                        } else if ui.button("Search ").clicked() {
+                            app.perform_find_in_files(ui.ctx());
                         }
 
                         if ui.button("x").on_hover_text("Close(Esc)").clicked() {
@@ -73,7 +90,7 @@ pub fn render_footer(app: &mut SublimeRustApp, ctx: &egui::Context) {
                         ui.end_row();
 
                         if ui.button("Replace").clicked() {
-                            app.perform_replace_in_files();
+                            app.perform_replace_in_files(ui.ctx());
                         }
 
                         //code for gitignore here: https://www.nerdfonts.com/cheat-sheet ie \ue725
@@ -88,35 +105,28 @@ pub fn render_footer(app: &mut SublimeRustApp, ctx: &egui::Context) {
 
                         ui.end_row();
                     });
-            } else if app.find_active {
-                ui.label("Find:");
-                let find_id = ui.make_persistent_id("find_input");
-                let remaining_width = ui.available_width() - icon_button_width * 13.0;
-                let response = ui.add(
-                    egui::TextEdit::singleline(&mut app.find_query)
-                        .id(find_id)
-                        .desired_width(remaining_width),
-                );
-
-                if app.find_just_activated {
-                    ui.ctx().memory_mut(|mem| mem.request_focus(find_id));
-                    app.find_just_activated = false;
-                }
 
-                if response.changed() {
-                    app.perform_find();
-                }
-
-                if !app.find_matches.is_empty() {
-                    let curr = app.current_match_index.unwrap_or(0) + 1;
-                    ui.label(format!("{} of {} matches", curr, app.find_matches.len()));
-                } else if !app.find_query.is_empty() {
-                    ui.label("No matches");
-                }
-
-                if ui.button("x").on_hover_text("Close(Esc)").clicked() {
-                    app.find_active = false;
+                if let Some(error) = &app.find_in_files_error {
+                    ui.label(
+                        egui::RichText::new(error).color(egui::Color32::from_rgb(0xe0, 0x6c, 0x75)),
+                    );
+                } else if let Some(job) = app.find_in_files_job.as_ref().filter(|job| !job.done) {
+                    ui.spinner();
+                    ui.label(format!("{} files, {} matches", job.searched(), job.matches));
+                } else if let Some(status) = &app.find_in_files_status {
+                    ui.label(status);
                 }
+            } else if app.find_active {
+                ui.vertical(|ui| {
+                    let field_left = ui
+                        .horizontal(|ui| render_find_row(app, ui, icon_button_width))
+                        .inner;
+                    if app.replace_active {
+                        ui.horizontal(|ui| {
+                            render_replace_row(app, ui, icon_button_width, field_left)
+                        });
+                    }
+                });
             } else {
                 if app.active_tab_index.is_some() {
                     ui.label(format!(
@@ -129,16 +139,31 @@ pub fn render_footer(app: &mut SublimeRustApp, ctx: &egui::Context) {
             }
 
             ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if let Some(error) = &app.settings_error {
+                    ui.label(
+                        egui::RichText::new("Settings error")
+                            .color(egui::Color32::from_rgb(0xe0, 0x6c, 0x75)),
+                    )
+                    .on_hover_text(error);
+                    ui.separator();
+                }
+                if let Some(error) = &app.file_error {
+                    ui.label(
+                        egui::RichText::new(error).color(egui::Color32::from_rgb(0xe0, 0x6c, 0x75)),
+                    );
+                    ui.separator();
+                }
                 if let Some(idx) = app.active_tab_index {
                     if let Some(path) = app.open_tabs.get(idx) {
                         let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
                         if extension != "find-results" {
-                            let syntax = SYNTAX_SET
-                                .find_syntax_by_extension(extension)
-                                .or_else(|| SYNTAX_SET.find_syntax_by_first_line(extension)) // fallback
-                                .map(|s| s.name.as_str())
-                                .unwrap_or("Plain Text");
-                            ui.label(format!("Language: {}", syntax));
+                            let syntax = app.syntax_for(path);
+                            ui.label(format!("Language: {}", syntax.name));
+                        }
+                        let results = path.to_str().unwrap_or("").starts_with("find://");
+                        if let Some(buffer) = app.tab_contents.get(path).filter(|_| !results) {
+                            let name = buffer.encoding().name();
+                            ui.menu_button(name, |ui| render_encoding_menus(app, ui));
                         }
                     }
                 } else {
@@ -146,36 +171,19 @@ pub fn render_footer(app: &mut SublimeRustApp, ctx: &egui::Context) {
                 }
 
                 if app.find_active {
-                    // let find_id = ui.make_persistent_id("find_input");
-                    if ctx.input(|i| i.key_pressed(egui::Key::Enter)) {
-                        if !app.find_matches.is_empty() {
-                            let next_idx =
-                                (app.current_match_index.unwrap_or(0) + 1) % app.find_matches.len();
-                            app.current_match_index = Some(next_idx);
-                            app.move_to_match(ctx);
+                    if app.replace_active {
+                        if ui.button("Replace All").clicked() {
+                            app.replace_all();
+                        }
+                        if ui.button("Replace").clicked() {
+                            app.replace_next();
                         }
                     }
-                    if ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Enter)) {}
                     if ui.button("Find Next").clicked() {
-                        if !app.find_matches.is_empty() {
-                            let next_idx =
-                                (app.current_match_index.unwrap_or(0) + 1) % app.find_matches.len();
-                            app.current_match_index = Some(next_idx);
-                            app.move_to_match(ctx);
-                            // ctx.memory_mut(|mem| mem.request_focus(find_id));
-                        }
+                        app.find_next();
                     }
                     if ui.button("Find Prev").clicked() {
-                        if !app.find_matches.is_empty() {
-                            let prev_idx = if app.current_match_index.unwrap_or(0) == 0 {
-                                app.find_matches.len() - 1
-                            } else {
-                                app.current_match_index.unwrap() - 1
-                            };
-                            app.current_match_index = Some(prev_idx);
-                            app.move_to_match(ctx);
-                            // ctx.memory_mut(|mem| mem.request_focus(find_id));
-                        }
+                        app.find_prev();
                     }
                     ui.separator();
                 }
@@ -184,3 +192,154 @@ pub fn render_footer(app: &mut SublimeRustApp, ctx: &egui::Context) {
         ui.add_space(3.0);
     });
 }
+
+// Returns where the field labels start, so the replace row can line up.
+fn render_find_row(app: &mut SublimeRustApp, ui: &mut egui::Ui, icon_button_width: f32) -> f32 {
+    let options = &mut app.find_options;
+    let mut options_changed = false;
+    options_changed |= option_toggle(ui, &mut options.regex, "\u{f0451}", "Regular expression");
+    options_changed |= option_toggle(
+        ui,
+        &mut options.case_sensitive,
+        "\u{f0b34}",
+        "Case sensitive",
+    );
+    options_changed |= option_toggle(ui, &mut options.whole_word, "\u{f0fb9}", "Whole word");
+    option_toggle(ui, &mut options.wrap, "\u{f05b6}", "Wrap");
+    if option_toggle(ui, &mut options.in_selection, "\u{f0489}", "In selection") {
+        app.capture_find_scope();
+        options_changed = true;
+    }
+    if app.replace_active {
+        let preserve_case = &mut app.find_options.preserve_case;
+        option_toggle(ui, preserve_case, "\u{eb2e}", "Preserve case");
+    }
+
+    let field_left = ui.label("Find:   ").rect.left();
+    let find_id = ui.make_persistent_id("find_input");
+    let browsed = browse_history(ui, find_id, &mut app.find_history, &mut app.find_query);
+    let remaining_width = ui.available_width() - icon_button_width * 13.0;
+    let response = ui.add(
+        egui::TextEdit::singleline(&mut app.find_query)
+            .id(find_id)
+            .desired_width(remaining_width),
+    );
+
+    if app.find_just_activated {
+        ui.ctx().memory_mut(|mem| mem.request_focus(find_id));
+        app.find_just_activated = false;
+    }
+
+    if response.changed() {
+        app.find_history.stop_browsing();
+    }
+    if response.changed() || options_changed || browsed {
+        app.perform_find();
+    }
+    // Enter finds the next match, Shift+Enter the previous one;
+    // either way the field keeps focus for the next press.
+    if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+        if ui.input(|i| i.modifiers.shift) {
+            app.find_prev();
+        } else {
+            app.find_next();
+        }
+        ui.ctx().memory_mut(|mem| mem.request_focus(find_id));
+    }
+
+    if let Some(error) = &app.find_error {
+        ui.label(
+            egui::RichText::new(search::short_error(error))
+                .color(egui::Color32::from_rgb(0xe0, 0x6c, 0x75)),
+        )
+        .on_hover_text(egui::RichText::new(error).monospace());
+    } else if let Some(status) = &app.find_status {
+        ui.label(status);
+    } else if !app.find_matches.is_empty() {
+        let curr = app.current_match_index.unwrap_or(0) + 1;
+        ui.label(format!("{} of {} matches", curr, app.find_matches.len()));
+    } else if !app.find_query.is_empty() {
+        ui.label("No matches");
+    }
+
+    if ui.button("x").on_hover_text("Close(Esc)").clicked() {
+        app.find_active = false;
+    }
+    field_left
+}
+
+fn render_replace_row(
+    app: &mut SublimeRustApp,
+    ui: &mut egui::Ui,
+    icon_button_width: f32,
+    field_left: f32,
+) {
+    ui.add_space(field_left - ui.cursor().left());
+    ui.label("Replace:");
+    let replace_id = ui.make_persistent_id("replace_input");
+    let remaining_width = ui.available_width() - icon_button_width * 13.0;
+    let response = ui.add(
+        egui::TextEdit::singleline(&mut app.replace_query)
+            .id(replace_id)
+            .hint_text(if app.find_options.regex {
+                "$1 for groups"
+            } else {
+                ""
+            })
+            .desired_width(remaining_width),
+    );
+    if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+        app.replace_next();
+        ui.ctx().memory_mut(|mem| mem.request_focus(replace_id));
+    }
+}
+
+// Up and Down in a focused query field step through its history. Returns
+// whether the query changed.
+fn browse_history(
+    ui: &mut egui::Ui,
+    id: egui::Id,
+    history: &mut QueryHistory,
+    query: &mut String,
+) -> bool {
+    if !ui.memory(|mem| mem.has_focus(id)) {
+        return false;
+    }
+    let (up, down) = ui.input_mut(|i| {
+        (
+            i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+            i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+        )
+    });
+    let entry = if up {
+        history.older(query)
+    } else if down {
+        history.newer()
+    } else {
+        None
+    };
+    let Some(entry) = entry else {
+        return false;
+    };
+    *query = entry;
+    if let Some(mut state) = egui::TextEdit::load_state(ui.ctx(), id) {
+        let end = egui::text::CCursor::new(query.chars().count());
+        state
+            .cursor
+            .set_char_range(Some(egui::text::CCursorRange::one(end)));
+        state.store(ui.ctx(), id);
+    }
+    true
+}
+
+// An icon button for one of the find options; returns whether it was flipped.
+fn option_toggle(ui: &mut egui::Ui, value: &mut bool, icon: &str, hint: &str) -> bool {
+    let clicked = ui
+        .selectable_label(*value, icon)
+        .on_hover_text(hint)
+        .clicked();
+    if clicked {
+        *value = !*value;
+    }
+    clicked
+}
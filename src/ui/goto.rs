@@ -0,0 +1,209 @@
+use crate::app::SublimeRustApp;
+use crate::fuzzy::fuzzy_match;
+use crate::goto::{self, GotoQuery};
+use crate::ui::palette::highlighted_label;
+use eframe::egui;
+use std::fs;
+use std::path::PathBuf;
+
+struct GotoItem {
+    path: PathBuf,
+    label: String,
+    detail: Option<String>,
+    positions: Vec<usize>,
+    // Zero-based line and column to jump to.
+    position: Option<(usize, usize)>,
+}
+
+fn collect_items(app: &mut SublimeRustApp) -> Vec<GotoItem> {
+    let query = GotoQuery::parse(&app.goto_query);
+    let return_tab = app
+        .goto_return_tab
+        .and_then(|i| app.open_tabs.get(i))
+        .cloned();
+    // At most MAX_RESULTS entries, so copying them out is cheap.
+    let ranked = match app.goto_index.as_mut() {
+        Some(index) => {
+            index.poll();
+            index.rank(&query.file).to_vec()
+        }
+        None => Vec::new(),
+    };
+
+    if let Some(symbol_query) = &query.symbol {
+        let target = if query.file.is_empty() {
+            return_tab
+        } else {
+            ranked.first().map(|(path, _, _)| path.clone())
+        };
+        let Some(target) = target else {
+            return Vec::new();
+        };
+
+        if app.goto_symbols.as_ref().map(|(p, _)| p) != Some(&target) {
+            let text = match app.tab_contents.get(&target) {
+                Some(buffer) => buffer.text(),
+                None => fs::read_to_string(&target).unwrap_or_default(),
+            };
+            app.goto_symbols = Some((
+                target.clone(),
+                goto::symbols(&text, app.syntax_for(&target)),
+            ));
+        }
+        let symbols = app
+            .goto_symbols
+            .as_ref()
+            .map(|(_, s)| s.as_slice())
+            .unwrap_or(&[]);
+
+        let mut items: Vec<(i64, GotoItem)> = symbols
+            .iter()
+            .filter_map(|symbol| {
+                let (score, positions) = fuzzy_match(symbol_query, &symbol.name)?;
+                Some((
+                    score,
+                    GotoItem {
+                        path: target.clone(),
+                        label: symbol.name.clone(),
+                        detail: Some(format!("line {}", symbol.line + 1)),
+                        positions,
+                        position: Some((symbol.line, symbol.col)),
+                    },
+                ))
+            })
+            .collect();
+        items.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        return items.into_iter().map(|(_, item)| item).collect();
+    }
+
+    let position = query.line.map(|line| {
+        (
+            line.saturating_sub(1),
+            query.col.unwrap_or(1).saturating_sub(1),
+        )
+    });
+
+    if query.file.is_empty() && position.is_some() {
+        return return_tab
+            .map(|path| GotoItem {
+                label: format!("Go to line {}", query.line.unwrap_or(1)),
+                detail: None,
+                positions: Vec::new(),
+                position,
+                path,
+            })
+            .into_iter()
+            .collect();
+    }
+
+    ranked
+        .into_iter()
+        .map(|(path, relative, positions)| GotoItem {
+            path,
+            label: relative,
+            detail: None,
+            positions,
+            position,
+        })
+        .collect()
+}
+
+pub fn render_goto_anything(app: &mut SublimeRustApp, ctx: &egui::Context) {
+    if !app.goto_active {
+        return;
+    }
+
+    let items = collect_items(app);
+    if items.is_empty() {
+        app.goto_selected = 0;
+    } else {
+        app.goto_selected = app.goto_selected.min(items.len() - 1);
+    }
+
+    let (up, down, enter) = ctx.input_mut(|i| {
+        (
+            i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+            i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+            i.consume_key(egui::Modifiers::NONE, egui::Key::Enter),
+        )
+    });
+    if up && app.goto_selected > 0 {
+        app.goto_selected -= 1;
+    }
+    if down && app.goto_selected + 1 < items.len() {
+        app.goto_selected += 1;
+    }
+
+    let mut clicked = None;
+    egui::Window::new("Goto Anything")
+        .title_bar(false)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 40.0))
+        .fixed_size(egui::vec2(560.0, 0.0))
+        .show(ctx, |ui| {
+            let input_id = ui.make_persistent_id("goto_input");
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut app.goto_query)
+                    .id(input_id)
+                    .hint_text("file, file:line:col or file@symbol")
+                    .desired_width(f32::INFINITY),
+            );
+            if app.goto_just_activated {
+                ui.ctx().memory_mut(|mem| mem.request_focus(input_id));
+                app.goto_just_activated = false;
+            }
+            if response.changed() {
+                app.goto_selected = 0;
+            }
+            if let Some(index) = app.goto_index.as_ref().filter(|index| !index.done) {
+                ui.weak(format!("Indexing… {} files", index.len()));
+            }
+            ui.separator();
+
+            egui::ScrollArea::vertical()
+                .max_height(360.0)
+                .show(ui, |ui| {
+                    let color = app.chrome.foreground;
+                    for (idx, item) in items.iter().enumerate() {
+                        let selected = idx == app.goto_selected;
+                        ui.push_id(idx, |ui| {
+                            ui.horizontal(|ui| {
+                                let label = highlighted_label(
+                                    ui,
+                                    &item.label,
+                                    &item.positions,
+                                    color,
+                                    app.chrome.accent,
+                                );
+                                let row = ui.add(egui::SelectableLabel::new(selected, label));
+                                if selected && (up || down) {
+                                    row.scroll_to_me(None);
+                                }
+                                if row.clicked() {
+                                    clicked = Some(idx);
+                                }
+                                if let Some(detail) = &item.detail {
+                                    ui.with_layout(
+                                        egui::Layout::right_to_left(egui::Align::Center),
+                                        |ui| {
+                                            ui.weak(detail);
+                                        },
+                                    );
+                                }
+                            });
+                        });
+                    }
+                });
+        });
+
+    if let Some(idx) = clicked {
+        app.goto_selected = idx;
+    }
+    if let Some(item) = items.get(app.goto_selected) {
+        app.preview_goto(item.path.clone(), item.position);
+        if enter || clicked.is_some() {
+            app.finish_goto(ctx, true);
+        }
+    }
+}
@@ -1,124 +1,172 @@
 use crate::app::SublimeRustApp;
+use crate::commands::{self, Command, COMMANDS, MENUS};
+use crate::encoding::{self, TextEncoding};
+use crate::syntax;
 use eframe::egui;
 
 pub fn render_menu_bar(app: &mut SublimeRustApp, ctx: &egui::Context) {
     egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
         egui::menu::bar(ui, |ui| {
-            ui.menu_button("File", |ui| {
-                if ui.button("New File (Ctrl+N)").clicked() {
-                    app.new_file();
-                    ui.close_menu();
-                }
-                ui.separator();
-                if ui.button("Open File... (Ctrl+O)").clicked() {
-                    app.open_file();
-                    ui.close_menu();
-                }
-                if ui.button("Open Folder... (Ctrl+Shift+O)").clicked() {
-                    app.open_folder();
-                    ui.close_menu();
-                }
-                ui.menu_button("Open Recent", |_| {});
-                ui.separator();
-                if ui.button("Save (Ctrl+S)").clicked() {
-                    app.save_active_file();
-                    ui.close_menu();
-                }
-                if ui.button("Save As... (Ctrl+Shift+S)").clicked() {
-                    app.save_as_active_file();
-                    ui.close_menu();
-                }
-                if ui.button("Save All").clicked() {
-                    app.save_all_files();
-                    ui.close_menu();
-                }
-                ui.separator();
-                if ui.button("Exit (Alt+F4)").clicked() {
-                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
-                }
-            });
+            for menu in MENUS {
+                ui.menu_button(menu, |ui| render_menu_items(app, ui, ctx, menu));
+            }
+        });
+    });
+}
 
-            ui.menu_button("Edit", |ui| {
-                if ui.button("Undo (Ctrl+Z)").clicked() {
-                    ui.close_menu();
-                }
-                if ui.button("Redo (Ctrl+Y)").clicked() {
-                    ui.close_menu();
-                }
-                ui.separator();
-                if ui.button("Cut (Ctrl+X)").clicked() {
-                    ui.close_menu();
-                }
-                if ui.button("Copy (Ctrl+C)").clicked() {
-                    ui.close_menu();
-                }
-                if ui.button("Paste (Ctrl+V)").clicked() {
-                    ui.close_menu();
-                }
-            });
+fn render_menu_items(app: &mut SublimeRustApp, ui: &mut egui::Ui, ctx: &egui::Context, menu: &str) {
+    let mut last_group = None;
+    for command in COMMANDS.iter() {
+        let Some((command_menu, group)) = command.menu else {
+            continue;
+        };
+        if command_menu != menu {
+            continue;
+        }
+        if last_group.is_some_and(|last| last != group) {
+            ui.separator();
+        }
+        last_group = Some(group);
+        render_command(app, ui, ctx, command);
+    }
+}
 
-            ui.menu_button("Selection", |ui| {
-                if ui.button("Select All (Ctrl+A)").clicked() {
-                    ui.close_menu();
-                }
+fn render_command(
+    app: &mut SublimeRustApp,
+    ui: &mut egui::Ui,
+    ctx: &egui::Context,
+    command: &Command,
+) {
+    match command.submenu {
+        Some(submenu) => {
+            ui.add_enabled_ui((command.enabled)(app), |ui| {
+                ui.menu_button(command.title, |ui| submenu(app, ui));
             });
+        }
+        None => command_button(app, ui, ctx, command, command.title),
+    }
+}
 
-            ui.menu_button("Find", |ui| {
-                if ui.button("Find... (Ctrl+F)").clicked() {
-                    app.find_active = true;
-                    app.find_just_activated = true;
-                    ui.close_menu();
-                }
-                if ui.button("Find in Files... (Ctrl+Shift+F)").clicked() {
-                    app.find_in_files_active = !app.find_in_files_active;
-                    if app.find_in_files_active {
-                        if let Some(dir) = &app.current_dir {
-                            app.find_in_files_where_query = dir.to_str().unwrap_or("").to_string();
-                        }
-                    }
-                    ui.close_menu();
-                }
-            });
+fn command_button(
+    app: &mut SublimeRustApp,
+    ui: &mut egui::Ui,
+    ctx: &egui::Context,
+    command: &Command,
+    label: &str,
+) {
+    let mut button = egui::Button::new(label);
+    if let Some(shortcut) = command.shortcuts.first() {
+        button = button.shortcut_text(ctx.format_shortcut(shortcut));
+    }
+    if ui.add_enabled((command.enabled)(app), button).clicked() {
+        ui.close_menu();
+        commands::execute(app, ctx, command);
+    }
+}
 
-            ui.menu_button("View", |ui| {
-                ui.menu_button("Side Bar", |ui| {
-                    let label = if app.sidebar_visible { "Hide" } else { "Show" };
-                    if ui.button(label).clicked() {
-                        app.sidebar_visible = !app.sidebar_visible;
-                        ui.close_menu();
-                    }
-                });
-            });
+pub fn render_syntax_menu(app: &mut SublimeRustApp, ui: &mut egui::Ui) {
+    let path = app.active_tab_index.and_then(|i| app.open_tabs.get(i));
+    let current = path.map(|p| app.syntax_for(p).name.as_str());
+    let overridden = path.is_some_and(|p| app.syntax_overrides.contains_key(p));
 
-            ui.menu_button("Goto", |ui| {
-                if ui.button("Goto Anything... (Ctrl+P)").clicked() {
-                    ui.close_menu();
-                }
-            });
+    let mut chosen = None;
+    if ui.selectable_label(!overridden, "Auto Detect").clicked() {
+        chosen = Some(None);
+    }
+    ui.separator();
 
-            ui.menu_button("Tools", |ui| {
-                if ui.button("Command Palette... (Ctrl+Shift+P)").clicked() {
-                    ui.close_menu();
-                }
-            });
+    let names = syntax::names();
+    egui::ScrollArea::vertical()
+        .max_height(480.0)
+        .show(ui, |ui| {
+            for name in names {
+                let selected = overridden && current == Some(name);
+                if ui.selectable_label(selected, name).clicked() {
+                    chosen = Some(Some(name.to_string()));
+                }
+            }
+        });
 
-            ui.menu_button("Project", |ui| {
-                if ui.button("Open Project...").clicked() {
-                    ui.close_menu();
-                }
-            });
+    if let Some(name) = chosen {
+        ui.close_menu();
+        app.set_syntax(name);
+    }
+}
 
-            ui.menu_button("Preferences", |ui| {
-                if ui.button("Settings").clicked() {
-                    ui.close_menu();
-                }
-            });
+// The footer's encoding menu.
+pub fn render_encoding_menus(app: &mut SublimeRustApp, ui: &mut egui::Ui) {
+    let ctx = ui.ctx().clone();
+    for id in ["reopen_with_encoding", "save_with_encoding"] {
+        if let Some(command) = commands::find(id) {
+            render_command(app, ui, &ctx, command);
+        }
+    }
+}
 
-            ui.menu_button("Help", |ui| {
-                if ui.button("About Sublime Text").clicked() {
-                    ui.close_menu();
-                }
-            });
-        });
-    });
+pub fn render_reopen_with_encoding(app: &mut SublimeRustApp, ui: &mut egui::Ui) {
+    let current = app.active_encoding();
+    for choice in encoding::CHOICES {
+        let name = TextEncoding::new(choice(), false).name();
+        let selected = current.is_some_and(|c| c.encoding == choice());
+        if ui.selectable_label(selected, name).clicked() {
+            ui.close_menu();
+            app.reopen_with_encoding(choice());
+        }
+    }
+}
+
+pub fn render_save_with_encoding(app: &mut SublimeRustApp, ui: &mut egui::Ui) {
+    let current = app.active_encoding();
+    for encoding in encoding::save_choices() {
+        if ui
+            .selectable_label(current == Some(encoding), encoding.name())
+            .clicked()
+        {
+            ui.close_menu();
+            app.save_with_encoding(encoding);
+        }
+    }
+}
+
+pub fn render_open_recent(app: &mut SublimeRustApp, ui: &mut egui::Ui) {
+    let ctx = &ui.ctx().clone();
+    app.recent.prune();
+
+    if let Some(command) = commands::find("reopen_last_file") {
+        command_button(app, ui, ctx, command, command.title);
+    }
+    ui.separator();
+
+    let mut open_file = None;
+    for path in &app.recent.files {
+        if ui.button(path.display().to_string()).clicked() {
+            open_file = Some(path.clone());
+        }
+    }
+    if !app.recent.files.is_empty() {
+        ui.separator();
+    }
+
+    let mut open_folder = None;
+    for path in &app.recent.folders {
+        if ui.button(path.display().to_string()).clicked() {
+            open_folder = Some(path.clone());
+        }
+    }
+    if !app.recent.folders.is_empty() {
+        ui.separator();
+    }
+
+    if let Some(command) = commands::find("clear_recent_files") {
+        command_button(app, ui, ctx, command, "Clear Items");
+    }
+
+    if let Some(path) = open_file {
+        ui.close_menu();
+        app.open_file_path(path);
+    }
+    if let Some(path) = open_folder {
+        ui.close_menu();
+        app.open_folder_path(path);
+    }
 }
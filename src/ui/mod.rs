@@ -1,11 +1,22 @@
 pub mod dialogs;
 pub mod editor;
 pub mod explorer;
+pub mod find_results;
 pub mod footer;
+pub mod goto;
 pub mod menu;
+pub mod palette;
+pub mod quick_panel;
+pub mod replace_preview;
+pub mod text_view;
+pub mod theme_picker;
 
-pub use dialogs::render_close_confirmation;
+pub use dialogs::{render_about, render_close_confirmation};
 pub use editor::render_editor_pane;
 pub use explorer::render_project_explorer;
 pub use footer::render_footer;
+pub use goto::render_goto_anything;
 pub use menu::render_menu_bar;
+pub use palette::render_command_palette;
+pub use quick_panel::render_quick_panel;
+pub use theme_picker::render_theme_picker;
@@ -0,0 +1,138 @@
+use crate::app::SublimeRustApp;
+use crate::commands::{self, Command, COMMANDS};
+use crate::fuzzy::fuzzy_match;
+use eframe::egui;
+
+// Title with the fuzzy-matched characters drawn in the accent color.
+pub fn highlighted_label(
+    ui: &egui::Ui,
+    text: &str,
+    positions: &[usize],
+    color: egui::Color32,
+    accent: egui::Color32,
+) -> egui::text::LayoutJob {
+    let font_id = egui::TextStyle::Button.resolve(ui.style());
+    let mut job = egui::text::LayoutJob::default();
+    for (i, c) in text.chars().enumerate() {
+        let color = if positions.contains(&i) {
+            accent
+        } else {
+            color
+        };
+        job.append(
+            c.encode_utf8(&mut [0; 4]),
+            0.0,
+            egui::TextFormat::simple(font_id.clone(), color),
+        );
+    }
+    job
+}
+
+pub fn render_command_palette(app: &mut SublimeRustApp, ctx: &egui::Context) {
+    if !app.palette_active {
+        return;
+    }
+
+    let mut matches: Vec<(i64, Vec<usize>, &'static Command)> = COMMANDS
+        .iter()
+        .filter_map(|c| fuzzy_match(&app.palette_query, c.title).map(|(s, p)| (s, p, c)))
+        .collect();
+    matches.sort_by_key(|(score, _, _)| std::cmp::Reverse(*score));
+
+    if matches.is_empty() {
+        app.palette_selected = 0;
+    } else {
+        app.palette_selected = app.palette_selected.min(matches.len() - 1);
+    }
+
+    let (up, down, enter) = ctx.input_mut(|i| {
+        (
+            i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+            i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+            i.consume_key(egui::Modifiers::NONE, egui::Key::Enter),
+        )
+    });
+    if up && app.palette_selected > 0 {
+        app.palette_selected -= 1;
+    }
+    if down && app.palette_selected + 1 < matches.len() {
+        app.palette_selected += 1;
+    }
+
+    let mut chosen = None;
+    if enter {
+        chosen = matches.get(app.palette_selected).map(|(_, _, c)| *c);
+    }
+
+    egui::Window::new("Command Palette")
+        .title_bar(false)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 40.0))
+        .fixed_size(egui::vec2(480.0, 0.0))
+        .show(ctx, |ui| {
+            let input_id = ui.make_persistent_id("palette_input");
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut app.palette_query)
+                    .id(input_id)
+                    .hint_text("Type a command")
+                    .desired_width(f32::INFINITY),
+            );
+            if app.palette_just_activated {
+                ui.ctx().memory_mut(|mem| mem.request_focus(input_id));
+                app.palette_just_activated = false;
+            }
+            if response.changed() {
+                app.palette_selected = 0;
+            }
+            ui.separator();
+
+            egui::ScrollArea::vertical()
+                .max_height(320.0)
+                .show(ui, |ui| {
+                    for (idx, (_, positions, command)) in matches.iter().enumerate() {
+                        let enabled = (command.enabled)(app);
+                        let selected = idx == app.palette_selected;
+                        let color = if enabled {
+                            app.chrome.foreground
+                        } else {
+                            app.chrome.weak
+                        };
+                        ui.push_id(command.id, |ui| {
+                            ui.horizontal(|ui| {
+                                let label = highlighted_label(
+                                    ui,
+                                    command.title,
+                                    positions,
+                                    color,
+                                    app.chrome.accent,
+                                );
+                                let row = ui.add_enabled(
+                                    enabled,
+                                    egui::SelectableLabel::new(selected, label),
+                                );
+                                if selected && (up || down) {
+                                    row.scroll_to_me(None);
+                                }
+                                if row.clicked() {
+                                    chosen = Some(*command);
+                                }
+                                if let Some(shortcut) = command.shortcuts.first() {
+                                    ui.with_layout(
+                                        egui::Layout::right_to_left(egui::Align::Center),
+                                        |ui| {
+                                            ui.weak(ui.ctx().format_shortcut(shortcut));
+                                        },
+                                    );
+                                }
+                            });
+                        });
+                    }
+                });
+        });
+
+    if let Some(command) = chosen {
+        app.palette_active = false;
+        commands::execute(app, ctx, command);
+    }
+}
@@ -0,0 +1,104 @@
+use crate::app::SublimeRustApp;
+use crate::fuzzy::fuzzy_match;
+use crate::ui::palette::highlighted_label;
+use eframe::egui;
+
+pub fn render_quick_panel(app: &mut SublimeRustApp, ctx: &egui::Context) {
+    let Some(panel) = app.quick_panel.as_mut() else {
+        return;
+    };
+
+    let mut matches: Vec<(i64, Vec<usize>, usize)> = panel
+        .items
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, item)| fuzzy_match(&panel.query, item).map(|(s, p)| (s, p, idx)))
+        .collect();
+    matches.sort_by_key(|(score, _, _)| std::cmp::Reverse(*score));
+
+    if panel.just_activated {
+        panel.selected = matches
+            .iter()
+            .position(|(_, _, idx)| Some(*idx) == panel.current)
+            .unwrap_or(0);
+    }
+    if matches.is_empty() {
+        panel.selected = 0;
+    } else {
+        panel.selected = panel.selected.min(matches.len() - 1);
+    }
+
+    let (up, down, enter) = ctx.input_mut(|i| {
+        (
+            i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+            i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+            i.consume_key(egui::Modifiers::NONE, egui::Key::Enter),
+        )
+    });
+    if up && panel.selected > 0 {
+        panel.selected -= 1;
+    }
+    if down && panel.selected + 1 < matches.len() {
+        panel.selected += 1;
+    }
+
+    let mut chosen = None;
+    if enter {
+        chosen = matches.get(panel.selected).map(|(_, _, idx)| *idx);
+    }
+
+    // Keep the selected row in view, including the one started on.
+    let scroll = up || down || panel.just_activated;
+    let chrome = app.chrome;
+    egui::Window::new("Quick Panel")
+        .title_bar(false)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 40.0))
+        .fixed_size(egui::vec2(480.0, 0.0))
+        .show(ctx, |ui| {
+            let input_id = ui.make_persistent_id("quick_panel_input");
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut panel.query)
+                    .id(input_id)
+                    .hint_text(panel.placeholder)
+                    .desired_width(f32::INFINITY),
+            );
+            if panel.just_activated {
+                ui.ctx().memory_mut(|mem| mem.request_focus(input_id));
+                panel.just_activated = false;
+            }
+            if response.changed() {
+                panel.selected = 0;
+            }
+            ui.separator();
+
+            egui::ScrollArea::vertical()
+                .max_height(320.0)
+                .show(ui, |ui| {
+                    for (row, (_, positions, idx)) in matches.iter().enumerate() {
+                        let selected = row == panel.selected;
+                        let label = highlighted_label(
+                            ui,
+                            &panel.items[*idx],
+                            positions,
+                            chrome.foreground,
+                            chrome.accent,
+                        );
+                        let response = ui.add(egui::SelectableLabel::new(selected, label));
+                        if selected && scroll {
+                            response.scroll_to_me(None);
+                        }
+                        if response.clicked() {
+                            chosen = Some(*idx);
+                        }
+                    }
+                });
+        });
+
+    if let Some(idx) = chosen {
+        if let Some(panel) = app.quick_panel.take() {
+            (panel.on_select)(app, ctx, idx, &panel.items[idx]);
+        }
+    }
+}
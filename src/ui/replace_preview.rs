@@ -0,0 +1,141 @@
+use crate::app::SublimeRustApp;
+use eframe::egui;
+
+enum Row {
+    File(usize),
+    // The change, and the line within its before and after.
+    Before(usize, usize, usize),
+    After(usize, usize, usize),
+    Gap,
+}
+
+const REMOVED: egui::Color32 = egui::Color32::from_rgb(0xe0, 0x6c, 0x75);
+const ADDED: egui::Color32 = egui::Color32::from_rgb(0x98, 0xc3, 0x79);
+
+// What Replace in Files would do, as a diff of each change with a box to
+// leave it out. Nothing is touched until Apply.
+pub fn render_replace_preview(app: &mut SublimeRustApp, ui: &mut egui::Ui) {
+    let Some(preview) = &app.replace_preview else {
+        return;
+    };
+    let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+    let selected = preview.selected();
+    let mut apply = false;
+    let mut discard = false;
+    ui.horizontal(|ui| {
+        ui.label(
+            egui::RichText::new(format!(
+                "Replace \"{}\" with \"{}\": {} of {} changes selected",
+                preview.query,
+                preview.replace,
+                selected,
+                preview.total()
+            ))
+            .font(font_id.clone())
+            .strong(),
+        );
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            discard = ui.button("Discard").clicked();
+            apply = ui
+                .add_enabled(selected > 0, egui::Button::new("Apply"))
+                .clicked();
+        });
+    });
+    ui.separator();
+
+    let mut rows = Vec::new();
+    for (f, file) in preview.files.iter().enumerate() {
+        rows.push(Row::File(f));
+        for (c, change) in file.changes.iter().enumerate() {
+            rows.extend((0..change.before.len()).map(|k| Row::Before(f, c, k)));
+            rows.extend((0..change.after.len()).map(|k| Row::After(f, c, k)));
+        }
+        rows.push(Row::Gap);
+    }
+    let digits = preview
+        .files
+        .iter()
+        .flat_map(|file| file.changes.last())
+        .map(|change| (change.line + change.before.len()).to_string().len())
+        .max()
+        .unwrap_or(1);
+
+    let chrome = app.chrome;
+    let row_height = ui.spacing().interact_size.y;
+    let indent = ui.spacing().icon_width + ui.spacing().item_spacing.x;
+    let preview = app.replace_preview.as_mut().unwrap();
+    egui::ScrollArea::both()
+        .id_source("replace_preview_scroll")
+        .auto_shrink([false, false])
+        .show_rows(ui, row_height, rows.len(), |ui, visible| {
+            for row in &rows[visible] {
+                ui.horizontal(|ui| {
+                    ui.set_min_height(row_height);
+                    match *row {
+                        Row::File(f) => {
+                            let file = &mut preview.files[f];
+                            let mut all = file.selected() == file.changes.len();
+                            if ui.checkbox(&mut all, "").changed() {
+                                file.select_all(all);
+                            }
+                            ui.label(
+                                egui::RichText::new(format!("{}:", file.path.display()))
+                                    .font(font_id.clone())
+                                    .color(chrome.accent)
+                                    .strong(),
+                            );
+                            if file.open {
+                                ui.label(egui::RichText::new("(open)").color(chrome.weak))
+                                    .on_hover_text("Changed in its tab, not saved");
+                            }
+                        }
+                        Row::Before(f, c, k) | Row::After(f, c, k) => {
+                            let change = &mut preview.files[f].changes[c];
+                            ui.add_space(indent);
+                            if matches!(row, Row::Before(..)) && k == 0 {
+                                ui.checkbox(&mut change.selected, "");
+                            } else {
+                                ui.add_space(indent);
+                            }
+                            let (sign, text, color) = match row {
+                                Row::Before(..) => ('-', &change.before[k], REMOVED),
+                                _ => ('+', &change.after[k], ADDED),
+                            };
+                            let faded = |color: egui::Color32| {
+                                if change.selected {
+                                    color
+                                } else {
+                                    color.gamma_multiply(0.4)
+                                }
+                            };
+                            ui.label(
+                                egui::RichText::new(format!(
+                                    "{:>width$} {}",
+                                    change.line + k + 1,
+                                    sign,
+                                    width = digits
+                                ))
+                                .font(font_id.clone())
+                                .color(faded(chrome.weak)),
+                            );
+                            ui.add(
+                                egui::Label::new(
+                                    egui::RichText::new(text)
+                                        .font(font_id.clone())
+                                        .color(faded(color)),
+                                )
+                                .wrap(false),
+                            );
+                        }
+                        Row::Gap => {}
+                    }
+                });
+            }
+        });
+
+    if apply {
+        app.apply_replace_in_files(ui.ctx());
+    } else if discard {
+        app.discard_replace_preview();
+    }
+}
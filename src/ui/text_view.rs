@@ -0,0 +1,721 @@
+use crate::buffer::{col_after, Buffer};
+use crate::highlight::{unchanged_lines, LineStyles};
+use crate::selection::{Motion, Selection, Selections};
+use eframe::egui;
+use ropey::{Rope, RopeSlice};
+use std::ops::Range;
+use std::sync::Arc;
+
+type Highlighter<'a> = dyn FnMut(&Buffer, Range<usize>) -> Vec<LineStyles> + 'a;
+type Backgrounds<'a> = dyn FnMut(Range<usize>) -> Vec<(Range<usize>, egui::Color32)> + 'a;
+
+// Things features draw into the view without owning it. Ranges are char
+// indices into the buffer, lines are zero-based.
+#[derive(Default)]
+pub struct Decorations {
+    // Dots in the gutter next to a line.
+    pub gutter_markers: Vec<(usize, egui::Color32)>,
+    // Text shown after the end of a line without being part of it.
+    pub virtual_text: Vec<(usize, String, egui::Color32)>,
+    // Ticks on the vertical scroll bar at a line.
+    pub scrollbar_marks: Vec<(usize, egui::Color32)>,
+}
+
+pub struct TextViewOutput {
+    pub changed: bool,
+    pub scroll_offset: egui::Vec2,
+}
+
+// Geometry derived from the text. After an edit only the lines that differ
+// from the text it was measured for are measured again.
+#[derive(Clone, Default)]
+struct LineMetrics {
+    version: u64,
+    text: Rope,
+    tab_size: usize,
+    wrap_cols: usize,
+    // Display columns of each line.
+    cols: Vec<usize>,
+    max_cols: usize,
+    // Visual rows above each line, plus the total at the end. Only filled
+    // when wrapping; otherwise every line is one row.
+    row_starts: Vec<usize>,
+}
+
+impl LineMetrics {
+    fn update(&mut self, buffer: &Buffer, tab_size: usize, wrap_cols: usize) {
+        if self.version == buffer.version()
+            && self.tab_size == tab_size
+            && self.wrap_cols == wrap_cols
+        {
+            return;
+        }
+        let text = buffer.snapshot();
+        let measure = |line: usize| display_cols(text.line(line), tab_size);
+        if self.tab_size != tab_size || self.cols.len() != self.text.len_lines() {
+            self.cols = (0..text.len_lines()).map(measure).collect();
+        } else if let Some((prefix, suffix)) = unchanged_lines(&self.text, &text) {
+            let changed = prefix..text.len_lines() - suffix;
+            self.cols
+                .splice(prefix..self.cols.len() - suffix, changed.map(measure));
+        }
+
+        self.max_cols = self.cols.iter().copied().max().unwrap_or(0);
+        self.row_starts.clear();
+        if wrap_cols > 0 {
+            let mut rows = 0;
+            for cols in &self.cols {
+                self.row_starts.push(rows);
+                rows += (*cols).max(1).div_ceil(wrap_cols);
+            }
+            self.row_starts.push(rows);
+        }
+        self.text = text;
+        self.version = buffer.version();
+        self.tab_size = tab_size;
+        self.wrap_cols = wrap_cols;
+    }
+
+    fn rows_before(&self, line: usize) -> usize {
+        if self.row_starts.is_empty() {
+            line
+        } else {
+            self.row_starts[line.min(self.row_starts.len() - 1)]
+        }
+    }
+
+    fn total_rows(&self, len_lines: usize) -> usize {
+        self.row_starts.last().copied().unwrap_or(len_lines)
+    }
+
+    fn line_at_row(&self, row: usize, len_lines: usize) -> usize {
+        let line = if self.row_starts.is_empty() {
+            row
+        } else {
+            self.row_starts
+                .partition_point(|&r| r <= row)
+                .saturating_sub(1)
+        };
+        line.min(len_lines.saturating_sub(1))
+    }
+}
+
+#[derive(Clone, Default)]
+struct ViewState {
+    metrics: Arc<LineMetrics>,
+    // Alt+drag: the (line, column) the block selection started at.
+    column_anchor: Option<(usize, usize)>,
+    // The primary selection last frame; when it moves, it is scrolled to.
+    last_primary: Option<Selection>,
+}
+
+fn display_cols(line: RopeSlice, tab_size: usize) -> usize {
+    line.chars()
+        .take_while(|&c| c != '\n' && c != '\r')
+        .fold(0, |col, c| col_after(c, col, tab_size))
+}
+
+// The editor: draws the lines in view with a gutter, and turns keyboard and
+// mouse input into edits and selection changes on the buffer it is given.
+pub struct TextView<'a> {
+    id: egui::Id,
+    buffer: &'a mut Buffer,
+    selections: &'a mut Selections,
+    highlighter: Option<&'a mut Highlighter<'a>>,
+    backgrounds: Option<&'a mut Backgrounds<'a>>,
+    decorations: Option<&'a Decorations>,
+    background: egui::Color32,
+    text_color: egui::Color32,
+    gutter: egui::Color32,
+    line_number_color: egui::Color32,
+    word_wrap: bool,
+    tab_size: usize,
+    translate_tabs_to_spaces: bool,
+    scroll_offset: Option<egui::Vec2>,
+    center_on_caret: bool,
+}
+
+impl<'a> TextView<'a> {
+    pub fn new(id: egui::Id, buffer: &'a mut Buffer, selections: &'a mut Selections) -> Self {
+        Self {
+            id,
+            buffer,
+            selections,
+            highlighter: None,
+            backgrounds: None,
+            decorations: None,
+            background: egui::Color32::from_rgb(0x1e, 0x1e, 0x1e),
+            text_color: egui::Color32::from_rgb(0xcc, 0xcc, 0xcc),
+            gutter: egui::Color32::from_rgb(0x1e, 0x1e, 0x1e),
+            line_number_color: egui::Color32::from_rgb(0x55, 0x55, 0x55),
+            word_wrap: false,
+            tab_size: 4,
+            translate_tabs_to_spaces: false,
+            scroll_offset: None,
+            center_on_caret: false,
+        }
+    }
+
+    // Called with the lines about to be drawn; returns their colors.
+    pub fn highlighter(mut self, highlighter: &'a mut Highlighter<'a>) -> Self {
+        self.highlighter = Some(highlighter);
+        self
+    }
+
+    // Called with the chars about to be drawn; returns what to fill behind
+    // them, e.g. find matches.
+    pub fn backgrounds(mut self, backgrounds: &'a mut Backgrounds<'a>) -> Self {
+        self.backgrounds = Some(backgrounds);
+        self
+    }
+
+    pub fn decorations(mut self, decorations: &'a Decorations) -> Self {
+        self.decorations = Some(decorations);
+        self
+    }
+
+    pub fn colors(mut self, background: egui::Color32, text: egui::Color32) -> Self {
+        self.background = background;
+        self.text_color = text;
+        self
+    }
+
+    // Line numbers on caret lines use the text color.
+    pub fn gutter_colors(mut self, background: egui::Color32, line_numbers: egui::Color32) -> Self {
+        self.gutter = background;
+        self.line_number_color = line_numbers;
+        self
+    }
+
+    pub fn word_wrap(mut self, word_wrap: bool) -> Self {
+        self.word_wrap = word_wrap;
+        self
+    }
+
+    pub fn tabs(mut self, tab_size: usize, translate_tabs_to_spaces: bool) -> Self {
+        self.tab_size = tab_size.max(1);
+        self.translate_tabs_to_spaces = translate_tabs_to_spaces;
+        self
+    }
+
+    pub fn scroll_offset(mut self, offset: egui::Vec2) -> Self {
+        self.scroll_offset = Some(offset);
+        self
+    }
+
+    // Scrolls so the primary caret is in the middle of the view, e.g. after
+    // jumping to a find match.
+    pub fn center_on_caret(mut self, center: bool) -> Self {
+        self.center_on_caret = center;
+        self
+    }
+
+    pub fn show(mut self, ui: &mut egui::Ui) -> TextViewOutput {
+        let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+        let (row_height, char_width) =
+            ui.fonts(|f| (f.row_height(&font_id), f.glyph_width(&font_id, ' ')));
+        let digits = self.buffer.len_lines().to_string().len().max(3);
+        // Room for a marker, the line number and some padding.
+        let gutter_width = (digits as f32 + 3.0) * char_width;
+
+        // Taken out rather than copied, so the metrics are not shared and can
+        // be updated in place.
+        let mut state: ViewState = ui.data_mut(|d| d.remove_temp(self.id)).unwrap_or_default();
+        let has_focus = ui.memory(|m| m.has_focus(self.id));
+        let mut changed = false;
+        if has_focus {
+            ui.memory_mut(|m| {
+                m.set_focus_lock_filter(
+                    self.id,
+                    egui::EventFilter {
+                        tab: true,
+                        horizontal_arrows: true,
+                        vertical_arrows: true,
+                        escape: true,
+                    },
+                )
+            });
+            let page = (ui.available_height() / row_height).max(1.0) as usize;
+            changed = self.handle_keys(ui, page);
+        }
+
+        let wrap_cols = if self.word_wrap {
+            let width = ui.available_width() - gutter_width - ui.spacing().scroll.bar_width;
+            ((width / char_width).floor() as usize).max(1)
+        } else {
+            0
+        };
+        Arc::make_mut(&mut state.metrics).update(self.buffer, self.tab_size, wrap_cols);
+        let metrics = state.metrics.clone();
+        let len_lines = self.buffer.len_lines();
+        let wrap_width = if self.word_wrap {
+            wrap_cols as f32 * char_width + 0.5
+        } else {
+            f32::INFINITY
+        };
+
+        let mut area = egui::ScrollArea::both()
+            .id_source("editor_scroll")
+            .auto_shrink([false, false])
+            .drag_to_scroll(false);
+        if let Some(offset) = self.scroll_offset {
+            area = area.scroll_offset(offset);
+        }
+
+        let output = area.show_viewport(ui, |ui, viewport| {
+            let text_width = if self.word_wrap {
+                wrap_width
+            } else {
+                (metrics.max_cols + 2) as f32 * char_width
+            };
+            let size = egui::vec2(
+                gutter_width + text_width,
+                // Allow scrolling the last line up to the middle of the view.
+                metrics.total_rows(len_lines) as f32 * row_height + viewport.height() / 2.0,
+            )
+            .max(viewport.size());
+            let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+            let response = ui.interact(rect, self.id, egui::Sense::click_and_drag());
+            let text_left = rect.min.x + gutter_width;
+            let line_top = |line: usize| rect.min.y + metrics.rows_before(line) as f32 * row_height;
+            let line_at = |y: f32| {
+                let row = ((y - rect.min.y) / row_height).max(0.0) as usize;
+                metrics.line_at_row(row, len_lines)
+            };
+            let layout = |ui: &egui::Ui, text: &str, styles: &[(Range<usize>, egui::Color32)]| {
+                layout_line(
+                    ui,
+                    text,
+                    styles,
+                    &font_id,
+                    self.text_color,
+                    wrap_width,
+                    self.tab_size,
+                )
+            };
+
+            // ── Mouse ────────────────────────────────────────
+            let char_at = |ui: &egui::Ui, buffer: &Buffer, pos: egui::Pos2| {
+                let line = line_at(pos.y);
+                let galley = layout(ui, &buffer.line_text(line), &[]);
+                let local = pos - egui::pos2(text_left, line_top(line));
+                buffer.line_to_char(line) + galley.cursor_from_pos(local).ccursor.index
+            };
+            // Block selections use display columns, which go on past the end
+            // of a line.
+            let column_at = |pos: egui::Pos2| {
+                let col = ((pos.x - text_left) / char_width).round().max(0.0);
+                (line_at(pos.y), col as usize)
+            };
+
+            if response.hovered() {
+                ui.ctx().set_cursor_icon(egui::CursorIcon::Text);
+            }
+            let (pressed, pointer_down, modifiers) =
+                ui.input(|i| (i.pointer.any_pressed(), i.pointer.any_down(), i.modifiers));
+            let pointer = ui.input(|i| i.pointer.interact_pos());
+            if let Some(pos) = pointer.filter(|_| pressed && response.hovered()) {
+                ui.memory_mut(|m| m.request_focus(self.id));
+                let buffer = &*self.buffer;
+                let at = char_at(ui, buffer, pos);
+                if modifiers.alt {
+                    let start = column_at(pos);
+                    state.column_anchor = Some(start);
+                    *self.selections = Selections::column(buffer, start, start, self.tab_size);
+                } else if modifiers.shift {
+                    self.selections.set_primary_head(at);
+                } else if modifiers.command {
+                    self.selections.add(Selection::caret(at));
+                } else {
+                    *self.selections = Selections::single(Selection::caret(at));
+                }
+                self.buffer.seal_history();
+            } else if let Some(pos) = pointer.filter(|_| response.dragged()) {
+                let buffer = &*self.buffer;
+                match state.column_anchor {
+                    Some(anchor) => {
+                        *self.selections =
+                            Selections::column(buffer, anchor, column_at(pos), self.tab_size)
+                    }
+                    None => self.selections.set_primary_head(char_at(ui, buffer, pos)),
+                }
+                // Dragging past the edge scrolls.
+                ui.scroll_to_rect(
+                    egui::Rect::from_center_size(pos, egui::Vec2::splat(1.0)),
+                    None,
+                );
+            }
+            if !pointer_down {
+                state.column_anchor = None;
+            }
+            if let Some(pos) = pointer {
+                let buffer = &*self.buffer;
+                if response.triple_clicked() {
+                    *self.selections =
+                        Selections::single(Selections::line_at(buffer, char_at(ui, buffer, pos)));
+                } else if response.double_clicked() {
+                    *self.selections =
+                        Selections::single(Selections::word_at(buffer, char_at(ui, buffer, pos)));
+                }
+            }
+
+            // ── Lines in view ────────────────────────────────
+            let buffer = &*self.buffer;
+            let first = line_at(rect.min.y + viewport.min.y);
+            let last = line_at(rect.min.y + viewport.max.y);
+            let styles = match self.highlighter.as_mut() {
+                Some(highlight) => highlight(buffer, first..last + 1),
+                None => Vec::new(),
+            };
+            let screen = viewport.translate(rect.min.to_vec2());
+            let text_clip = egui::Rect::from_min_max(
+                egui::pos2(screen.min.x + gutter_width, screen.min.y),
+                screen.max,
+            );
+            ui.painter().rect_filled(screen, 0.0, self.background);
+            let painter = ui.painter_at(text_clip);
+            let selection_fill = ui.visuals().selection.bg_fill;
+            let caret_stroke = egui::Stroke::new(2.0, ui.visuals().text_cursor.color);
+            let decorations = self.decorations;
+            let mut caret_lines = Vec::new();
+            let visible = buffer.line_to_char(first)..buffer.line_to_char(last + 1);
+            let highlights = match self.backgrounds.as_mut() {
+                Some(backgrounds) => backgrounds(visible),
+                None => Vec::new(),
+            };
+
+            for line in first..=last {
+                let text = buffer.line_text(line);
+                let galley = layout(
+                    ui,
+                    &text,
+                    styles.get(line - first).map_or(&[][..], |s| &s[..]),
+                );
+                let origin = egui::pos2(text_left, line_top(line));
+                let line_start = buffer.line_to_char(line);
+                let line_end = line_start + text.chars().count();
+                let local = |range: &Range<usize>| {
+                    // A selection running past the line end covers the newline.
+                    let past_end = range.end > line_end && line + 1 < len_lines;
+                    (range.start.max(line_start) - line_start)
+                        ..(range.end.min(line_end).max(range.start.max(line_start)) - line_start)
+                            + past_end as usize
+                };
+
+                for (range, color) in &highlights {
+                    if range.start <= line_end && range.end > line_start {
+                        paint_range(&painter, &galley, origin, local(range), char_width, *color);
+                    }
+                }
+                for sel in self.selections.iter() {
+                    if !sel.is_empty() && sel.start() <= line_end && sel.end() > line_start {
+                        paint_range(
+                            &painter,
+                            &galley,
+                            origin,
+                            local(&sel.range()),
+                            char_width,
+                            selection_fill,
+                        );
+                    }
+                }
+
+                painter.galley(origin, galley.clone(), self.text_color);
+
+                if let Some(decorations) = decorations {
+                    for (_, label, color) in decorations.virtual_text.iter().filter(|v| v.0 == line)
+                    {
+                        let end = galley.rect.right_bottom() + origin.to_vec2();
+                        painter.text(
+                            egui::pos2(end.x + 2.0 * char_width, end.y - row_height),
+                            egui::Align2::LEFT_TOP,
+                            label,
+                            font_id.clone(),
+                            *color,
+                        );
+                    }
+                }
+                for sel in self.selections.iter() {
+                    if (line_start..=line_end).contains(&sel.head) {
+                        let caret = galley
+                            .pos_from_ccursor(egui::text::CCursor::new(sel.head - line_start))
+                            .translate(origin.to_vec2());
+                        painter.line_segment(
+                            [caret.center_top(), caret.center_bottom()],
+                            caret_stroke,
+                        );
+                        caret_lines.push(line);
+                    }
+                }
+            }
+
+            // ── Gutter ───────────────────────────────────────
+            // Painted last and pinned to the left so text scrolled
+            // horizontally goes under it.
+            let gutter =
+                egui::Rect::from_min_size(screen.min, egui::vec2(gutter_width, screen.height()));
+            let painter = ui.painter_at(gutter);
+            painter.rect_filled(gutter, 0.0, self.gutter);
+            for line in first..=last {
+                let y = line_top(line);
+                let color = if caret_lines.contains(&line) {
+                    self.text_color
+                } else {
+                    self.line_number_color
+                };
+                painter.text(
+                    egui::pos2(gutter.max.x - 1.5 * char_width, y),
+                    egui::Align2::RIGHT_TOP,
+                    (line + 1).to_string(),
+                    font_id.clone(),
+                    color,
+                );
+            }
+            if let Some(decorations) = decorations {
+                for (line, color) in &decorations.gutter_markers {
+                    if (first..=last).contains(line) {
+                        let center = egui::pos2(
+                            gutter.min.x + char_width * 0.8,
+                            line_top(*line) + row_height / 2.0,
+                        );
+                        painter.circle_filled(center, char_width * 0.3, *color);
+                    }
+                }
+            }
+
+            // ── Keep the caret in view ───────────────────────
+            let primary = self.selections.primary();
+            if self.center_on_caret || state.last_primary != Some(primary) {
+                let (line, col) = buffer.char_to_line_col(primary.head);
+                let galley = layout(ui, &buffer.line_text(line), &[]);
+                let caret = galley
+                    .pos_from_ccursor(egui::text::CCursor::new(col))
+                    .translate(egui::vec2(text_left, line_top(line)));
+                // Keep clear of the gutter when scrolling sideways.
+                let caret = caret.expand2(egui::vec2(gutter_width, 0.0));
+                let align = self.center_on_caret.then_some(egui::Align::Center);
+                ui.scroll_to_rect(caret, align);
+                state.last_primary = Some(primary);
+            }
+        });
+
+        if let Some(decorations) = self.decorations {
+            let content_height = output.content_size.y.max(1.0);
+            let track = output.inner_rect;
+            let bar_width = ui.spacing().scroll.bar_width;
+            let left = if ui.spacing().scroll.floating {
+                track.right() - bar_width
+            } else {
+                track.right()
+            };
+            let painter = ui.painter();
+            for (line, color) in &decorations.scrollbar_marks {
+                let y = metrics.rows_before(*line) as f32 * row_height;
+                let top = track.top() + y / content_height * track.height();
+                let tick = egui::Rect::from_min_size(
+                    egui::pos2(left, top.min(track.bottom() - 2.0)),
+                    egui::vec2(bar_width, 2.0),
+                );
+                painter.rect_filled(tick, 0.0, *color);
+            }
+        }
+
+        ui.data_mut(|d| d.insert_temp(self.id, state));
+        TextViewOutput {
+            changed,
+            scroll_offset: output.state.offset,
+        }
+    }
+
+    // Applies the typing, deleting and caret movement keys at every caret.
+    // Returns whether the text changed.
+    fn handle_keys(&mut self, ui: &mut egui::Ui, page: usize) -> bool {
+        let buffer = &mut *self.buffer;
+        let selections = &mut *self.selections;
+        let events = ui.input_mut(|i| std::mem::take(&mut i.events));
+        let mut unhandled = Vec::new();
+        let mut changed = false;
+
+        for event in events {
+            match &event {
+                egui::Event::Text(text) => {
+                    selections.insert(buffer, text);
+                    changed = true;
+                }
+                egui::Event::Paste(text) => {
+                    // As many lines as carets: one line goes to each caret.
+                    let lines: Vec<&str> = text.lines().collect();
+                    if selections.len() > 1 && lines.len() == selections.len() {
+                        selections.insert_each(buffer, &lines);
+                    } else {
+                        selections.insert(buffer, text);
+                    }
+                    changed = true;
+                }
+                egui::Event::Copy => {
+                    let text = selections.copy(buffer);
+                    ui.ctx().output_mut(|o| o.copied_text = text);
+                }
+                egui::Event::Cut => {
+                    let text = selections.copy(buffer);
+                    ui.ctx().output_mut(|o| o.copied_text = text);
+                    selections.insert(buffer, "");
+                    changed = true;
+                }
+                egui::Event::Key {
+                    key,
+                    pressed: true,
+                    modifiers,
+                    ..
+                } if !modifiers.alt => {
+                    let motion = match (key, modifiers.command) {
+                        (egui::Key::ArrowLeft, false) => Some(Motion::Left),
+                        (egui::Key::ArrowRight, false) => Some(Motion::Right),
+                        (egui::Key::ArrowLeft, true) => Some(Motion::WordLeft),
+                        (egui::Key::ArrowRight, true) => Some(Motion::WordRight),
+                        (egui::Key::ArrowUp, false) => Some(Motion::Up(1)),
+                        (egui::Key::ArrowDown, false) => Some(Motion::Down(1)),
+                        (egui::Key::PageUp, false) => Some(Motion::Up(page)),
+                        (egui::Key::PageDown, false) => Some(Motion::Down(page)),
+                        (egui::Key::Home, false) => Some(Motion::LineStart),
+                        (egui::Key::End, false) => Some(Motion::LineEnd),
+                        (egui::Key::Home, true) => Some(Motion::DocStart),
+                        (egui::Key::End, true) => Some(Motion::DocEnd),
+                        _ => None,
+                    };
+                    if let Some(motion) = motion {
+                        selections.move_carets(buffer, motion, modifiers.shift, self.tab_size);
+                        // Moving the caret ends the current typing burst.
+                        buffer.seal_history();
+                        continue;
+                    }
+                    if modifiers.command {
+                        unhandled.push(event);
+                        continue;
+                    }
+                    match key {
+                        egui::Key::Backspace => selections.backspace(buffer),
+                        egui::Key::Delete => selections.delete_forward(buffer),
+                        egui::Key::Enter => selections.insert(buffer, "\n"),
+                        egui::Key::Tab => selections.insert_tab(
+                            buffer,
+                            self.tab_size,
+                            self.translate_tabs_to_spaces,
+                        ),
+                        egui::Key::Escape => {
+                            selections.collapse();
+                            continue;
+                        }
+                        _ => {
+                            unhandled.push(event);
+                            continue;
+                        }
+                    }
+                    changed = true;
+                }
+                _ => unhandled.push(event),
+            }
+        }
+        ui.input_mut(|i| i.events = unhandled);
+        changed
+    }
+}
+
+fn layout_line(
+    ui: &egui::Ui,
+    text: &str,
+    styles: &[(Range<usize>, egui::Color32)],
+    font_id: &egui::FontId,
+    default_color: egui::Color32,
+    wrap_width: f32,
+    tab_size: usize,
+) -> Arc<egui::Galley> {
+    let mut job = egui::text::LayoutJob::default();
+    job.wrap.max_width = wrap_width;
+    job.wrap.break_anywhere = true;
+    let format = |color| egui::TextFormat {
+        font_id: font_id.clone(),
+        color,
+        ..Default::default()
+    };
+    // A tab reaches the next multiple of `tab_size`, but epaint draws one
+    // four spaces wide. Narrower ones are drawn as a space instead, and space
+    // in front of whatever follows makes up the rest, so a tab that ends the
+    // line is only as wide as its glyph. The char count stays the same, so
+    // cursor positions still line up with the buffer.
+    let char_width = ui.fonts(|f| f.glyph_width(font_id, ' '));
+    let glyph_cols = egui::epaint::text::TAB_SIZE;
+    let mut col = 0;
+    let mut leading = 0.0;
+    let mut append = |job: &mut egui::text::LayoutJob, text: &str, color| {
+        if text.is_empty() {
+            job.append(text, leading, format(color));
+        }
+        for piece in text.split_inclusive('\t') {
+            let Some(before) = piece.strip_suffix('\t') else {
+                job.append(piece, leading, format(color));
+                col += piece.chars().count();
+                leading = 0.0;
+                continue;
+            };
+            col += before.chars().count();
+            let width = col_after('\t', col, tab_size) - col;
+            let drawn = if width >= glyph_cols {
+                job.append(piece, leading, format(color));
+                glyph_cols
+            } else {
+                job.append(&format!("{} ", before), leading, format(color));
+                1
+            };
+            col += width;
+            leading = (width - drawn) as f32 * char_width;
+        }
+    };
+
+    let mut pos = 0;
+    for (range, color) in styles {
+        let end = range.end.min(text.len());
+        if range.start < pos || end <= range.start || !text.is_char_boundary(end) {
+            continue;
+        }
+        if range.start > pos {
+            append(&mut job, &text[pos..range.start], default_color);
+        }
+        append(&mut job, &text[range.start..end], *color);
+        pos = end;
+    }
+    // Also gives empty lines a row of the right height.
+    if pos < text.len() || text.is_empty() {
+        append(&mut job, &text[pos..], default_color);
+    }
+    ui.fonts(|f| f.layout_job(job))
+}
+
+// Fills the background of a char range within one laid out line, across
+// wrapped rows. An end one past the text stands for the line's newline.
+fn paint_range(
+    painter: &egui::Painter,
+    galley: &egui::Galley,
+    origin: egui::Pos2,
+    range: Range<usize>,
+    char_width: f32,
+    color: egui::Color32,
+) {
+    let mut row_start = 0;
+    for (idx, row) in galley.rows.iter().enumerate() {
+        let row_end = row_start + row.char_count_excluding_newline();
+        let newline = idx + 1 == galley.rows.len() && range.end > row_end;
+        let start = range.start.max(row_start);
+        let end = range.end.min(row_end);
+        if start < end || (newline && start <= row_end) {
+            let left = row.x_offset(start - row_start);
+            let mut right = row.x_offset(end.max(start) - row_start);
+            if newline {
+                right += char_width;
+            }
+            let rect = egui::Rect::from_x_y_ranges(left..=right, row.min_y()..=row.max_y());
+            painter.rect_filled(rect.translate(origin.to_vec2()), 0.0, color);
+        }
+        row_start += row.char_count_including_newline();
+    }
+}
@@ -0,0 +1,106 @@
+use crate::app::SublimeRustApp;
+use crate::fuzzy::fuzzy_match;
+use crate::ui::palette::highlighted_label;
+use eframe::egui;
+
+pub fn render_theme_picker(app: &mut SublimeRustApp, ctx: &egui::Context) {
+    if !app.theme_picker_active {
+        return;
+    }
+
+    let mut matches: Vec<(i64, Vec<usize>, String)> = app
+        .theme_picker_names
+        .iter()
+        .filter_map(|name| {
+            fuzzy_match(&app.theme_picker_query, name).map(|(s, p)| (s, p, name.clone()))
+        })
+        .collect();
+    matches.sort_by_key(|(score, _, _)| std::cmp::Reverse(*score));
+
+    if matches.is_empty() {
+        app.theme_picker_selected = 0;
+    } else {
+        app.theme_picker_selected = app.theme_picker_selected.min(matches.len() - 1);
+    }
+
+    let (up, down, enter) = ctx.input_mut(|i| {
+        (
+            i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+            i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+            i.consume_key(egui::Modifiers::NONE, egui::Key::Enter),
+        )
+    });
+    if up && app.theme_picker_selected > 0 {
+        app.theme_picker_selected -= 1;
+    }
+    if down && app.theme_picker_selected + 1 < matches.len() {
+        app.theme_picker_selected += 1;
+    }
+
+    let mut chosen = None;
+    if enter {
+        chosen = matches
+            .get(app.theme_picker_selected)
+            .map(|(_, _, n)| n.clone());
+    }
+
+    egui::Window::new("Select Color Scheme")
+        .title_bar(false)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 40.0))
+        .fixed_size(egui::vec2(480.0, 0.0))
+        .show(ctx, |ui| {
+            let input_id = ui.make_persistent_id("theme_picker_input");
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut app.theme_picker_query)
+                    .id(input_id)
+                    .hint_text("Select color scheme")
+                    .desired_width(f32::INFINITY),
+            );
+            if app.theme_picker_just_activated {
+                ui.ctx().memory_mut(|mem| mem.request_focus(input_id));
+                // Start on the theme in use.
+                app.theme_picker_selected = matches
+                    .iter()
+                    .position(|(_, _, n)| *n == app.theme_picker_original)
+                    .unwrap_or(0);
+                app.theme_picker_just_activated = false;
+            }
+            if response.changed() {
+                app.theme_picker_selected = 0;
+            }
+            ui.separator();
+
+            egui::ScrollArea::vertical()
+                .max_height(320.0)
+                .show(ui, |ui| {
+                    for (idx, (_, positions, name)) in matches.iter().enumerate() {
+                        let selected = idx == app.theme_picker_selected;
+                        let label = highlighted_label(
+                            ui,
+                            name,
+                            positions,
+                            app.chrome.foreground,
+                            app.chrome.accent,
+                        );
+                        let row = ui.add(egui::SelectableLabel::new(selected, label));
+                        if selected && (up || down) {
+                            row.scroll_to_me(None);
+                        }
+                        if row.clicked() {
+                            chosen = Some(name.clone());
+                        }
+                    }
+                });
+        });
+
+    match chosen {
+        Some(name) => app.finish_theme_picker(ctx, Some(name)),
+        None => {
+            if let Some((_, _, name)) = matches.get(app.theme_picker_selected) {
+                app.preview_theme(ctx, name);
+            }
+        }
+    }
+}